@@ -0,0 +1,11 @@
+ALTER TABLE targets ADD COLUMN config TEXT;
+
+CREATE TABLE IF NOT EXISTS target_threads (
+    target_id TEXT NOT NULL,
+    thread_key TEXT NOT NULL,
+    channel TEXT NOT NULL,
+    ts TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (target_id, thread_key),
+    FOREIGN KEY(target_id) REFERENCES targets(id) ON DELETE CASCADE
+);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS media (
+    id TEXT PRIMARY KEY,
+    content_type TEXT NOT NULL,
+    data BLOB NOT NULL,
+    size INTEGER NOT NULL,
+    source TEXT,
+    created_at INTEGER NOT NULL
+);
@@ -0,0 +1,2 @@
+ALTER TABLE deliveries ADD COLUMN part INTEGER;
+ALTER TABLE deliveries ADD COLUMN parts INTEGER;
@@ -0,0 +1 @@
+ALTER TABLE endpoints ADD COLUMN allowed_platforms TEXT;
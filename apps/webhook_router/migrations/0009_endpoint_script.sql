@@ -0,0 +1 @@
+ALTER TABLE endpoints ADD COLUMN script TEXT;
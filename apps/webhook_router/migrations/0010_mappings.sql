@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS mappings (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    id_path TEXT,
+    title_path TEXT,
+    timestamp_path TEXT,
+    meta TEXT NOT NULL,
+    template TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
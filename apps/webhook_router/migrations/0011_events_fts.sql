@@ -0,0 +1,22 @@
+-- Trigram tokens match substrings in any script, including CJK text without spaces.
+CREATE VIRTUAL TABLE IF NOT EXISTS events_fts USING fts5(
+    event_id UNINDEXED,
+    title,
+    markdown,
+    tokenize = 'trigram'
+);
+
+INSERT INTO events_fts (event_id, title, markdown)
+SELECT id, COALESCE(title, ''), markdown FROM events;
+
+CREATE TRIGGER IF NOT EXISTS events_fts_insert AFTER INSERT ON events BEGIN
+    INSERT INTO events_fts (event_id, title, markdown)
+    VALUES (new.id, COALESCE(new.title, ''), new.markdown);
+END;
+
+CREATE TRIGGER IF NOT EXISTS events_fts_delete AFTER DELETE ON events BEGIN
+    DELETE FROM events_fts WHERE event_id = old.id;
+END;
+
+CREATE INDEX IF NOT EXISTS idx_events_created_at ON events(created_at);
+CREATE INDEX IF NOT EXISTS idx_deliveries_event_id ON deliveries(event_id);
@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS idx_media_created_at ON media(created_at);
@@ -0,0 +1,159 @@
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::adapters::{str_field, AdapterError, WebhookAdapter};
+use crate::models::{OutgoingPayload, UemEvent};
+
+/// Prometheus Alertmanager webhook notifications (also sent by Grafana
+/// alerting). Ingress only.
+#[derive(Debug)]
+pub struct AlertmanagerAdapter;
+
+impl WebhookAdapter for AlertmanagerAdapter {
+    fn ingress_to_uem(&self, payload: &Value) -> Result<UemEvent, AdapterError> {
+        let alerts = payload
+            .get("alerts")
+            .and_then(|value| value.as_array())
+            .ok_or_else(|| AdapterError {
+                message: "alertmanager payload has no alerts".to_string(),
+            })?;
+        let status = str_field(payload, "status");
+
+        // Alertmanager's default title: `[FIRING:2] <group label values>`.
+        let group_labels = label_values(payload.get("groupLabels"));
+        let mut title = format!("[{}:{}]", status.to_uppercase(), alerts.len());
+        if !group_labels.is_empty() {
+            title = format!("{} {}", title, group_labels);
+        }
+
+        let markdown = alerts
+            .iter()
+            .map(alert_to_markdown)
+            .collect::<Vec<_>>()
+            .join("\n\n---\n\n");
+
+        Ok(UemEvent {
+            id: Uuid::new_v4().to_string(),
+            source: "alertmanager".to_string(),
+            timestamp: now_timestamp(),
+            title: Some(title),
+            markdown: if markdown.is_empty() {
+                payload.to_string()
+            } else {
+                markdown
+            },
+            raw: payload.clone(),
+            meta: json!({
+                "status": payload.get("status"),
+                "receiver": payload.get("receiver"),
+                "group_key": payload.get("groupKey"),
+                "group_labels": payload.get("groupLabels"),
+                "common_labels": payload.get("commonLabels"),
+                "external_url": payload.get("externalURL"),
+                "alerts": alerts.len(),
+            }),
+            mentions: Vec::new(),
+        })
+    }
+
+    fn uem_to_egress(&self, _event: &UemEvent) -> Result<OutgoingPayload, AdapterError> {
+        Err(AdapterError {
+            message: "alertmanager is an ingress-only platform".to_string(),
+        })
+    }
+}
+
+fn alert_to_markdown(alert: &Value) -> String {
+    let labels = alert.get("labels").unwrap_or(&Value::Null);
+    let annotations = alert.get("annotations").unwrap_or(&Value::Null);
+    let name = str_field(labels, "alertname");
+    let summary = str_field(annotations, "summary");
+
+    let mut lines = vec![match (name.is_empty(), summary.is_empty()) {
+        (false, false) => format!("**{}** {}: {}", str_field(alert, "status").to_uppercase(), name, summary),
+        (false, true) => format!("**{}** {}", str_field(alert, "status").to_uppercase(), name),
+        (true, _) => format!("**{}** {}", str_field(alert, "status").to_uppercase(), summary),
+    }];
+    let description = str_field(annotations, "description");
+    if !description.is_empty() {
+        lines.push(description.to_string());
+    }
+    let labels = labels
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(key, _)| *key != "alertname")
+        .map(|(key, value)| format!("`{}={}`", key, value.as_str().unwrap_or_default()))
+        .collect::<Vec<_>>();
+    if !labels.is_empty() {
+        lines.push(format!("Labels: {}", labels.join(" ")));
+    }
+    let source = str_field(alert, "generatorURL");
+    if !source.is_empty() {
+        lines.push(format!("[Source]({})", source));
+    }
+    lines.join("\n\n")
+}
+
+fn label_values(labels: Option<&Value>) -> String {
+    labels
+        .and_then(|value| value.as_object())
+        .map(|labels| {
+            labels
+                .values()
+                .filter_map(|value| value.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .unwrap_or_default()
+}
+
+fn now_timestamp() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use insta::assert_yaml_snapshot;
+
+    #[test]
+    fn alertmanager_ingress_to_uem() {
+        let payload = json!({
+            "version": "4",
+            "groupKey": "{}:{alertname=\"HighCPU\"}",
+            "status": "firing",
+            "receiver": "router",
+            "groupLabels": { "alertname": "HighCPU" },
+            "commonLabels": { "alertname": "HighCPU", "severity": "page" },
+            "externalURL": "http://alertmanager:9093",
+            "alerts": [
+                {
+                    "status": "firing",
+                    "labels": { "alertname": "HighCPU", "instance": "db-1", "severity": "page" },
+                    "annotations": { "summary": "CPU above 90%", "description": "db-1 has been busy for 10m" },
+                    "generatorURL": "http://prometheus:9090/graph?g0.expr=cpu",
+                },
+                {
+                    "status": "resolved",
+                    "labels": { "alertname": "HighCPU", "instance": "db-2", "severity": "page" },
+                    "annotations": {},
+                }
+            ]
+        });
+        let event = AlertmanagerAdapter.ingress_to_uem(&payload).expect("uem");
+        assert_yaml_snapshot!(
+            "adapters_alertmanager_ingress",
+            json!({
+                "source": event.source,
+                "title": event.title,
+                "markdown": event.markdown,
+                "meta": event.meta,
+            })
+        );
+        assert!(AlertmanagerAdapter.ingress_to_uem(&json!({"status": "firing"})).is_err());
+    }
+}
@@ -0,0 +1,385 @@
+//! Crypto shared by the encrypted callback protocols of chat platform apps.
+
+use aes::cipher::block_padding::NoPadding;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use axum::http::HeaderMap;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig, STANDARD};
+use base64::{alphabet, Engine};
+use serde_json::{Map, Value};
+use sha1::{Digest, Sha1};
+
+use crate::adapters::{AdapterError, Callback};
+
+type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
+type Aes256CbcEnc = cbc::Encryptor<aes::Aes256>;
+
+/// EncodingAESKeys are 43 base64 characters whose last one carries unused
+/// bits, which the strict decoder rejects.
+const AES_KEY_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_allow_trailing_bits(true),
+);
+
+pub fn callback_error(message: impl Into<String>) -> AdapterError {
+    AdapterError {
+        message: message.into(),
+    }
+}
+
+/// A string setting from the endpoint's `ingress_config` for the platform.
+pub fn setting<'a>(settings: Option<&'a Value>, name: &str) -> Option<&'a str> {
+    settings?
+        .get(name)?
+        .as_str()
+        .filter(|value| !value.is_empty())
+}
+
+pub fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// AES-256-CBC decryption without unpadding; callers strip their scheme's
+/// padding (PKCS#7 with 16- or 32-byte blocks).
+pub fn aes256_cbc_decrypt(key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, AdapterError> {
+    let decryptor =
+        Aes256CbcDec::new_from_slices(key, iv).map_err(|_| callback_error("invalid callback key length"))?;
+    if ciphertext.is_empty() || !ciphertext.len().is_multiple_of(16) {
+        return Err(callback_error("encrypted callback has an invalid length"));
+    }
+    let mut buffer = ciphertext.to_vec();
+    let plaintext = decryptor
+        .decrypt_padded_mut::<NoPadding>(&mut buffer)
+        .map_err(|_| callback_error("failed to decrypt callback"))?;
+    Ok(plaintext.to_vec())
+}
+
+/// Strips PKCS#7 padding for blocks of up to `block_size` bytes.
+pub fn strip_pkcs7(mut data: Vec<u8>, block_size: usize) -> Result<Vec<u8>, AdapterError> {
+    let pad = usize::from(*data.last().ok_or_else(|| callback_error("empty decrypted callback"))?);
+    let valid = pad > 0
+        && pad <= block_size
+        && pad <= data.len()
+        && data[data.len() - pad..].iter().all(|byte| usize::from(*byte) == pad);
+    if !valid {
+        return Err(callback_error("invalid padding in decrypted callback (wrong key?)"));
+    }
+    data.truncate(data.len() - pad);
+    Ok(data)
+}
+
+/// Compares signatures without exiting early on the first differing byte.
+pub fn signatures_match(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .bytes()
+            .zip(actual.bytes())
+            .fold(0u8, |diff, (left, right)| diff | (left ^ right))
+            == 0
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The callback encryption shared by WeCom and DingTalk apps (WeChat's
+/// `WXBizMsgCrypt`): AES-256-CBC keyed by the EncodingAESKey, with the
+/// message framed as `random(16) | length(4, big endian) | message | receive id`
+/// and signed with a SHA-1 over the sorted token, timestamp, nonce and ciphertext.
+pub struct MsgCrypt {
+    token: String,
+    key: Vec<u8>,
+    receive_id: Option<String>,
+}
+
+impl MsgCrypt {
+    /// Reads `token`, `encoding_aes_key` and the optional receive id setting
+    /// (`corp_id`, `app_key`); `None` when the endpoint has no keys.
+    pub fn from_settings(settings: Option<&Value>, receive_id: &str) -> Result<Option<Self>, AdapterError> {
+        let (Some(token), Some(aes_key)) = (setting(settings, "token"), setting(settings, "encoding_aes_key")) else {
+            return Ok(None);
+        };
+        let key = AES_KEY_BASE64
+            .decode(format!("{}=", aes_key))
+            .ok()
+            .filter(|key| key.len() == 32)
+            .ok_or_else(|| callback_error("encoding_aes_key must be the 43-character EncodingAESKey"))?;
+        Ok(Some(Self {
+            token: token.to_string(),
+            key,
+            receive_id: setting(settings, receive_id).map(String::from),
+        }))
+    }
+
+    pub fn signature(&self, timestamp: &str, nonce: &str, encrypted: &str) -> String {
+        let mut parts = [self.token.as_str(), timestamp, nonce, encrypted];
+        parts.sort_unstable();
+        hex(&Sha1::digest(parts.concat().as_bytes()))
+    }
+
+    /// Checks the signature the platform sent in the query string, and that
+    /// its `timestamp` is recent so captured callbacks can't be replayed.
+    pub fn verify(&self, callback: &Callback<'_>, signature_param: &str, encrypted: &str) -> Result<(), AdapterError> {
+        let signature = callback
+            .query(signature_param)
+            .ok_or_else(|| callback_error(format!("missing {} parameter", signature_param)))?;
+        let timestamp = callback.query("timestamp").unwrap_or_default();
+        if !is_fresh(timestamp) {
+            return Err(callback_error("timestamp parameter is missing or too far from now"));
+        }
+        let expected = self.signature(timestamp, callback.query("nonce").unwrap_or_default(), encrypted);
+        if !signatures_match(&expected, signature) {
+            return Err(callback_error(format!("invalid {}", signature_param)));
+        }
+        Ok(())
+    }
+
+    /// Returns the message and the receive id it was encrypted for, which
+    /// must match the configured one if set.
+    pub fn decrypt(&self, encrypted: &str) -> Result<(String, String), AdapterError> {
+        let data = STANDARD
+            .decode(encrypted)
+            .map_err(|_| callback_error("encrypted callback is not valid base64"))?;
+        let plaintext = strip_pkcs7(aes256_cbc_decrypt(&self.key, &self.key[..16], &data)?, 32)?;
+        let framed = plaintext
+            .get(16..20)
+            .map(|length| u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize)
+            .filter(|length| 20 + length <= plaintext.len())
+            .ok_or_else(|| callback_error("decrypted callback has an invalid length"))?;
+        let message = String::from_utf8(plaintext[20..20 + framed].to_vec())
+            .map_err(|_| callback_error("decrypted callback is not UTF-8"))?;
+        let receive_id = String::from_utf8_lossy(&plaintext[20 + framed..]).into_owned();
+        if let Some(expected) = &self.receive_id {
+            if *expected != receive_id {
+                return Err(callback_error(format!("callback was encrypted for {}", receive_id)));
+            }
+        }
+        Ok((message, receive_id))
+    }
+
+    /// Encrypts a reply for `receive_id` (the configured one wins).
+    pub fn encrypt(&self, message: &str, receive_id: &str) -> String {
+        let receive_id = self.receive_id.as_deref().unwrap_or(receive_id);
+        let mut plaintext = vec![0u8; 16];
+        OsRng.fill_bytes(&mut plaintext);
+        plaintext.extend((message.len() as u32).to_be_bytes());
+        plaintext.extend(message.as_bytes());
+        plaintext.extend(receive_id.as_bytes());
+        let pad = 32 - plaintext.len() % 32;
+        plaintext.extend(std::iter::repeat_n(pad as u8, pad));
+
+        let length = plaintext.len();
+        let ciphertext = Aes256CbcEnc::new_from_slices(&self.key, &self.key[..16])
+            .expect("32-byte key and 16-byte IV")
+            .encrypt_padded_mut::<NoPadding>(&mut plaintext, length)
+            .expect("plaintext padded to the block size");
+        STANDARD.encode(ciphertext)
+    }
+}
+
+/// How far a callback `timestamp` may be from now before the callback is
+/// treated as a replay.
+const MAX_TIMESTAMP_SKEW_SECS: i64 = 300;
+
+/// Whether `timestamp` is within [`MAX_TIMESTAMP_SKEW_SECS`] of now. WeCom
+/// sends seconds and DingTalk milliseconds.
+fn is_fresh(timestamp: &str) -> bool {
+    timestamp.parse::<i64>().is_ok_and(|timestamp| {
+        let seconds = if timestamp > 100_000_000_000 { timestamp / 1000 } else { timestamp };
+        (now_timestamp() - seconds).abs() <= MAX_TIMESTAMP_SKEW_SECS
+    })
+}
+
+fn now_timestamp() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Parses a callback XML document such as
+/// `<xml><MsgType><![CDATA[text]]></MsgType></xml>` into an object of the root
+/// element's children. Nested elements become objects, repeated ones arrays
+/// and attributes are ignored.
+pub fn xml_to_value(xml: &str) -> Result<Value, AdapterError> {
+    let mut rest = xml;
+    loop {
+        rest = rest.trim_start();
+        if rest.starts_with("<?") {
+            rest = after(rest, "?>")?;
+        } else if rest.starts_with("<!") {
+            rest = after(rest, ">")?;
+        } else {
+            break;
+        }
+    }
+    let (_, value, _) = xml_element(rest)?;
+    Ok(match value {
+        Value::String(_) => Value::Object(Map::new()),
+        value => value,
+    })
+}
+
+fn after<'a>(text: &'a str, delimiter: &str) -> Result<&'a str, AdapterError> {
+    text.split_once(delimiter)
+        .map(|(_, rest)| rest)
+        .ok_or_else(|| callback_error("malformed callback XML"))
+}
+
+/// Parses one element at the start of `xml`: its name, its value (text or
+/// an object of children) and the input after it.
+fn xml_element(xml: &str) -> Result<(String, Value, &str), AdapterError> {
+    let malformed = || callback_error("malformed callback XML");
+    let inner = xml.strip_prefix('<').ok_or_else(malformed)?;
+    let (tag, mut rest) = inner.split_once('>').ok_or_else(malformed)?;
+    let name = tag
+        .trim_end_matches('/')
+        .split_whitespace()
+        .next()
+        .ok_or_else(malformed)?
+        .to_string();
+    if tag.ends_with('/') {
+        return Ok((name, Value::String(String::new()), rest));
+    }
+
+    let mut text = String::new();
+    let mut children = Map::new();
+    loop {
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let (data, next) = cdata.split_once("]]>").ok_or_else(malformed)?;
+            text.push_str(data);
+            rest = next;
+        } else if rest.starts_with("<!--") {
+            rest = after(rest, "-->")?;
+        } else if let Some(close) = rest.strip_prefix("</") {
+            rest = after(close, ">")?;
+            break;
+        } else if rest.starts_with('<') {
+            let (child, value, next) = xml_element(rest)?;
+            match children.get_mut(&child) {
+                Some(Value::Array(values)) => values.push(value),
+                Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+                None => {
+                    children.insert(child, value);
+                }
+            }
+            rest = next;
+        } else {
+            let end = rest.find('<').ok_or_else(malformed)?;
+            text.push_str(&unescape_xml(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+
+    let value = if children.is_empty() {
+        Value::String(text)
+    } else {
+        Value::Object(children)
+    };
+    Ok((name, value, rest))
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn parses_callback_xml() {
+        let xml = r#"<?xml version="1.0"?>
+            <xml>
+              <ToUserName><![CDATA[ww1]]></ToUserName>
+              <CreateTime>1348831860</CreateTime>
+              <Content><![CDATA[a <b> & c]]></Content>
+              <Title>x &amp; y</Title>
+              <Item><Id>1</Id></Item><Item><Id>2</Id></Item>
+              <Empty/>
+            </xml>"#;
+        assert_eq!(
+            xml_to_value(xml).unwrap(),
+            json!({
+                "ToUserName": "ww1",
+                "CreateTime": "1348831860",
+                "Content": "a <b> & c",
+                "Title": "x & y",
+                "Item": [{"Id": "1"}, {"Id": "2"}],
+                "Empty": "",
+            })
+        );
+        assert!(xml_to_value("<xml><Open>").is_err());
+    }
+
+    #[test]
+    fn msg_crypt_decrypts_documented_sample() {
+        // The URL verification sample from the WXBizMsgCrypt documentation.
+        let settings = json!({
+            "token": "QDG6eK",
+            "encoding_aes_key": "jWmYm7qr5nMoAUwZRjGtBxmz3KA1tkAj3ykkR6q2B2C",
+            "corp_id": "wx5823bf96d3bd56c7",
+        });
+        let crypt = MsgCrypt::from_settings(Some(&settings), "corp_id").unwrap().unwrap();
+        let echostr = "P9nAzCzyDtyTWESHep1vC5X9xho/qYX3Zpb4yKa9SKld1DsH3Iyt3tP3zNdtp+4RPcs8TgAE7OaBO+FZXvnaqQ==";
+        assert_eq!(
+            crypt.signature("1409659589", "263014780", echostr),
+            "5c45ff5e21c57e6ad56bac8758b79b1d9ac89fd3"
+        );
+        assert_eq!(
+            crypt.decrypt(echostr).unwrap(),
+            ("1616140317555161061".to_string(), "wx5823bf96d3bd56c7".to_string())
+        );
+    }
+
+    #[test]
+    fn msg_crypt_round_trips() {
+        let settings = json!({
+            "token": "QDG6eK",
+            "encoding_aes_key": "jWmYm7qr5nMoAUwZRjGtBxmz3KA1tkAj3ykkR6q2B2C",
+            "corp_id": "wx5823bf96d3bd56c7",
+        });
+        let crypt = MsgCrypt::from_settings(Some(&settings), "corp_id").unwrap().unwrap();
+        let encrypted = crypt.encrypt("<xml>hi</xml>", "");
+        assert_eq!(
+            crypt.decrypt(&encrypted).unwrap(),
+            ("<xml>hi</xml>".to_string(), "wx5823bf96d3bd56c7".to_string())
+        );
+
+        let headers = HeaderMap::new();
+        let signed_at = |timestamp: String| {
+            HashMap::from([
+                ("msg_signature".to_string(), crypt.signature(&timestamp, "1372623149", &encrypted)),
+                ("timestamp".to_string(), timestamp),
+                ("nonce".to_string(), "1372623149".to_string()),
+            ])
+        };
+        let query = signed_at(now_timestamp().to_string());
+        let callback = Callback { headers: &headers, query: &query, settings: Some(&settings) };
+        crypt.verify(&callback, "msg_signature", &encrypted).expect("valid signature");
+        assert!(crypt.verify(&callback, "msg_signature", "other").is_err());
+        assert!(crypt.verify(&callback, "signature", &encrypted).is_err());
+        let millis = signed_at((now_timestamp() * 1000).to_string());
+        let callback = Callback { headers: &headers, query: &millis, settings: Some(&settings) };
+        crypt.verify(&callback, "msg_signature", &encrypted).expect("millisecond timestamp");
+        let stale = signed_at("1409659813".to_string());
+        let callback = Callback { headers: &headers, query: &stale, settings: Some(&settings) };
+        let err = crypt.verify(&callback, "msg_signature", &encrypted).unwrap_err();
+        assert_eq!(err.message, "timestamp parameter is missing or too far from now");
+
+        let other = json!({"token": "t", "encoding_aes_key": settings["encoding_aes_key"], "corp_id": "ww2"});
+        let other = MsgCrypt::from_settings(Some(&other), "corp_id").unwrap().unwrap();
+        assert!(other.decrypt(&encrypted).unwrap_err().message.contains("wx5823bf96d3bd56c7"));
+        assert!(MsgCrypt::from_settings(Some(&json!({"token": "t", "encoding_aes_key": "short"})), "corp_id").is_err());
+        assert!(MsgCrypt::from_settings(Some(&json!({"token": "t"})), "corp_id").unwrap().is_none());
+    }
+}
@@ -0,0 +1,180 @@
+//! Guesses which platform sent an ingress request to `/ingress/:endpoint_id`.
+
+use std::collections::HashMap;
+
+use axum::http::HeaderMap;
+use serde_json::Value;
+
+use crate::adapters::callback::header;
+use crate::utils::body::IngressBody;
+
+const SLACK_PAYLOAD_TYPES: &[&str] = &[
+    "event_callback",
+    "block_actions",
+    "view_submission",
+    "view_closed",
+    "shortcut",
+    "message_action",
+    "interactive_message",
+];
+
+/// Platforms that could have sent the request, most likely first. Headers
+/// and query parameters that only one platform sends win over the body
+/// shape; an empty result means the body matches no known format.
+///
+/// Some formats are shared: DingTalk and WeCom robots both post
+/// `{"msgtype": "text", ...}`, and Slack and Lark send the same URL
+/// verification challenge. Those return every candidate.
+pub fn detect_platforms(
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+    body: &IngressBody,
+) -> Vec<&'static str> {
+    if header(headers, "x-slack-signature").is_some() || header(headers, "x-slack-request-timestamp").is_some() {
+        return vec!["slack"];
+    }
+    if header(headers, "x-lark-signature").is_some() || header(headers, "x-lark-request-timestamp").is_some() {
+        return vec!["lark"];
+    }
+    let user_agent = header(headers, "user-agent").unwrap_or_default();
+    if user_agent.starts_with("Alertmanager/") {
+        return vec!["alertmanager"];
+    }
+    if user_agent.starts_with("Slackbot") {
+        return vec!["slack"];
+    }
+    if query.contains_key("echostr") {
+        return vec!["wecom"];
+    }
+    detect_from_body(&body.value, query)
+}
+
+fn detect_from_body(payload: &Value, query: &HashMap<String, String>) -> Vec<&'static str> {
+    let has = |key: &str| payload.get(key).is_some();
+    let str_field = |key: &str| payload.get(key).and_then(Value::as_str).unwrap_or_default();
+
+    if has("encrypt") {
+        return if query.contains_key("signature") {
+            vec!["dingtalk", "lark", "wecom"]
+        } else if query.contains_key("msg_signature") {
+            vec!["wecom", "dingtalk", "lark"]
+        } else {
+            vec!["lark", "dingtalk", "wecom"]
+        };
+    }
+    if str_field("text").trim_start().starts_with("<xml") || has("MsgType") {
+        return vec!["wecom"];
+    }
+    if has("EventType") {
+        return vec!["dingtalk"];
+    }
+    if payload.get("alerts").is_some_and(Value::is_array) && (has("receiver") || has("groupLabels")) {
+        return vec!["alertmanager"];
+    }
+    if str_field("schema") == "2.0" || (str_field("type") == "event_callback" && has("uuid")) {
+        return vec!["lark"];
+    }
+    if str_field("type") == "url_verification" {
+        return vec!["slack", "lark"];
+    }
+    if SLACK_PAYLOAD_TYPES.contains(&str_field("type"))
+        || has("api_app_id")
+        || (has("command") && has("team_id"))
+        || (!has("msgtype") && (has("blocks") || has("attachments")))
+    {
+        return vec!["slack"];
+    }
+    if has("msg_type") && (has("content") || has("card")) {
+        return vec!["lark"];
+    }
+    if let Some(msgtype) = payload.get("msgtype").and_then(Value::as_str) {
+        return robot_platforms(payload, msgtype);
+    }
+    Vec::new()
+}
+
+/// DingTalk and WeCom robots share `msgtype`; their message types and
+/// mention fields tell them apart.
+fn robot_platforms(payload: &Value, msgtype: &str) -> Vec<&'static str> {
+    let text = payload.get("text").unwrap_or(&Value::Null);
+    let markdown = payload.get("markdown").unwrap_or(&Value::Null);
+    let dingtalk = ["actionCard", "feedCard", "link"].contains(&msgtype)
+        || ["at", "conversationId", "senderStaffId", "chatbotUserId"]
+            .iter()
+            .any(|key| payload.get(key).is_some())
+        || markdown.get("title").is_some();
+    let wecom = ["news", "template_card", "markdown_v2", "image", "file", "voice"].contains(&msgtype)
+        || text.get("mentioned_list").is_some()
+        || text.get("mentioned_mobile_list").is_some()
+        || markdown.get("content").is_some();
+    match (dingtalk, wecom) {
+        (true, false) => vec!["dingtalk"],
+        (false, true) => vec!["wecom"],
+        _ => vec!["dingtalk", "wecom"],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use serde_json::json;
+
+    use super::*;
+
+    fn detect(headers: &[(&'static str, &str)], query: &[(&str, &str)], value: Value) -> Vec<&'static str> {
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            header_map.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        let query = query
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let body = IngressBody {
+            bytes: value.to_string().into_bytes(),
+            value,
+            content_type: "application/json".to_string(),
+        };
+        detect_platforms(&header_map, &query, &body)
+    }
+
+    #[test]
+    fn detects_platforms() {
+        let text = json!({"text": "hi"});
+        assert_eq!(detect(&[("x-slack-signature", "v0=1")], &[], text.clone()), ["slack"]);
+        assert_eq!(detect(&[("x-lark-request-timestamp", "1")], &[], text.clone()), ["lark"]);
+        assert_eq!(detect(&[("user-agent", "Alertmanager/0.27.0")], &[], text.clone()), ["alertmanager"]);
+        assert!(detect(&[], &[], text).is_empty());
+
+        let alerts = json!({"receiver": "r", "status": "firing", "alerts": []});
+        assert_eq!(detect(&[], &[], alerts), ["alertmanager"]);
+        assert_eq!(detect(&[], &[], json!({"schema": "2.0", "header": {}})), ["lark"]);
+        assert_eq!(detect(&[], &[], json!({"msg_type": "text", "content": {"text": "hi"}})), ["lark"]);
+        assert_eq!(detect(&[], &[], json!({"type": "event_callback", "team_id": "T1", "api_app_id": "A1"})), ["slack"]);
+        assert_eq!(detect(&[], &[], json!({"command": "/deploy", "team_id": "T1"})), ["slack"]);
+        assert_eq!(detect(&[], &[], json!({"text": "hi", "blocks": []})), ["slack"]);
+        assert_eq!(
+            detect(&[], &[], json!({"type": "url_verification", "challenge": "c", "token": "t"})),
+            ["slack", "lark"]
+        );
+
+        let markdown = |body: Value| json!({"msgtype": "markdown", "markdown": body});
+        assert_eq!(detect(&[], &[], markdown(json!({"title": "t", "text": "x"}))), ["dingtalk"]);
+        assert_eq!(detect(&[], &[], markdown(json!({"content": "x"}))), ["wecom"]);
+        assert_eq!(
+            detect(&[], &[], json!({"msgtype": "text", "text": {"content": "x"}, "at": {"isAtAll": true}})),
+            ["dingtalk"]
+        );
+        assert_eq!(
+            detect(&[], &[], json!({"msgtype": "text", "text": {"content": "x", "mentioned_list": ["a"]}})),
+            ["wecom"]
+        );
+        assert_eq!(detect(&[], &[], json!({"msgtype": "text", "text": {"content": "x"}})), ["dingtalk", "wecom"]);
+
+        assert_eq!(detect(&[], &[("signature", "s")], json!({"encrypt": "x"}))[0], "dingtalk");
+        assert_eq!(detect(&[], &[("msg_signature", "s")], json!({"encrypt": "x"}))[0], "wecom");
+        assert_eq!(detect(&[], &[("msg_signature", "s")], json!({"text": "<xml><Encrypt/></xml>"})), ["wecom"]);
+        assert_eq!(detect(&[], &[("echostr", "e")], Value::Null), ["wecom"]);
+        assert_eq!(detect(&[], &[], json!({"EventType": "user_add_org"})), ["dingtalk"]);
+    }
+}
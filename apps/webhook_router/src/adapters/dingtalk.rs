@@ -1,17 +1,66 @@
 use serde_json::{json, Value};
 use uuid::Uuid;
 
-use crate::adapters::{AdapterError, WebhookAdapter};
-use crate::models::{OutgoingPayload, UemEvent};
+use crate::adapters::callback::{callback_error, MsgCrypt};
+use crate::adapters::{
+    AdapterError, Callback, CallbackAnswer, CallbackReply, MessageLimit, WebhookAdapter,
+};
+use crate::document::markdown::{write_markdown, MarkdownStyle};
+use crate::document::{plain_title, render_event, Capabilities, Document, Renderer, TableSupport};
+use crate::models::{Mention, OutgoingPayload, UemEvent};
+use crate::utils::body::IngressBody;
 use crate::utils::markdown::extract_title_from_markdown;
+use crate::utils::mention::{lookup_mention, mention_token, replace_handle_references};
 
 #[derive(Debug)]
 pub struct DingTalkAdapter;
 
 impl WebhookAdapter for DingTalkAdapter {
+    /// With `dingtalk.token` and `dingtalk.encoding_aes_key` set, event
+    /// callbacks must be `{"encrypt": ...}` bodies; each is answered with an
+    /// encrypted `success`. The `check_url` event sent when the callback URL
+    /// is saved gets the same answer without creating an event.
+    fn open_callback(&self, callback: &Callback<'_>, body: &mut IngressBody) -> Result<CallbackAnswer, AdapterError> {
+        let encrypted = body.value.get("encrypt").and_then(Value::as_str).map(String::from);
+        let Some(crypt) = MsgCrypt::from_settings(callback.settings, "app_key")? else {
+            if encrypted.is_some() {
+                return Err(callback_error(
+                    "encrypted DingTalk callback but no dingtalk.token and dingtalk.encoding_aes_key are configured",
+                ));
+            }
+            return Ok(CallbackAnswer::Event);
+        };
+
+        let encrypted = encrypted.ok_or_else(|| callback_error("DingTalk callback is not encrypted"))?;
+        // Older callback registrations sign with `msg_signature`.
+        let signature = if callback.query("msg_signature").is_some() {
+            "msg_signature"
+        } else {
+            "signature"
+        };
+        crypt.verify(callback, signature, &encrypted)?;
+        let (message, receive_id) = crypt.decrypt(&encrypted)?;
+        body.value = serde_json::from_str(&message)
+            .map_err(|err| callback_error(format!("decrypted DingTalk payload is not JSON: {}", err)))?;
+
+        let reply = dingtalk_success_reply(&crypt, &receive_id);
+        if body.value.get("EventType").and_then(Value::as_str) == Some("check_url") {
+            return Ok(CallbackAnswer::Handshake(reply));
+        }
+        Ok(CallbackAnswer::EventReply(reply))
+    }
+
     fn ingress_to_uem(&self, payload: &Value) -> Result<UemEvent, AdapterError> {
+        if let Some(event_type) = payload.get("EventType").and_then(|value| value.as_str()) {
+            return Ok(dingtalk_app_event_to_uem(payload, event_type));
+        }
         let msgtype = payload.get("msgtype").and_then(|value| value.as_str());
-        let text = dingtalk_markdown_from_payload(payload, msgtype);
+        let mut mentions = Vec::new();
+        let text = normalize_dingtalk_mentions(
+            &dingtalk_markdown_from_payload(payload, msgtype),
+            payload,
+            &mut mentions,
+        );
         let title = dingtalk_title_from_payload(payload, msgtype);
 
         let event_id = payload
@@ -41,33 +90,139 @@ impl WebhookAdapter for DingTalkAdapter {
                 "msgtype": payload.get("msgtype"),
                 "at": payload.get("at"),
             }),
+            mentions,
         })
     }
 
     fn uem_to_egress(&self, event: &UemEvent) -> Result<OutgoingPayload, AdapterError> {
+        render_event(self, event, &Document::parse(&event.markdown))
+    }
+
+    fn renderer(&self) -> Option<&dyn Renderer> {
+        Some(self)
+    }
+
+    fn message_limit(&self) -> Option<MessageLimit> {
+        // DingTalk rejects markdown content over 20,000 bytes.
+        Some(MessageLimit::bytes(20_000))
+    }
+}
+
+impl Renderer for DingTalkAdapter {
+    fn platform(&self) -> &'static str {
+        "dingtalk"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            tables: TableSupport::Fields,
+            images: true,
+            code_blocks: false,
+            mentions: true,
+        }
+    }
+
+    fn render(&self, event: &UemEvent, doc: &Document) -> Result<OutgoingPayload, AdapterError> {
+        let mut at_mobiles: Vec<String> = Vec::new();
+        let style = MarkdownStyle {
+            emphasis: true,
+            strikethrough: false,
+        };
+        let markdown = write_markdown(doc, style, &mut |platform, handle| {
+            match lookup_mention(&event.mentions, platform, handle, "dingtalk") {
+                (Some(mobile), _) => {
+                    if !at_mobiles.iter().any(|existing| existing == mobile) {
+                        at_mobiles.push(mobile.to_string());
+                    }
+                    format!("@{}", mobile)
+                }
+                (None, fallback) => format!("@{}", fallback),
+            }
+        });
+
         let (title, text) = if let Some(t) = &event.title {
-            (t.clone(), format!("# {}\n\n{}", t, event.markdown))
+            let t = plain_title(t, &event.mentions, "dingtalk");
+            let text = format!("# {}\n\n{}", t, markdown);
+            (t, text)
         } else {
             (
-                extract_title_from_markdown(&event.markdown)
+                extract_title_from_markdown(&markdown)
                     .unwrap_or_else(|| "Webhook Router".to_string()),
-                event.markdown.clone(),
+                markdown,
             )
         };
 
+        let mut body = json!({
+            "msgtype": "markdown",
+            "markdown": {
+                "title": title,
+                "text": text
+            }
+        });
+        if !at_mobiles.is_empty() {
+            body["at"] = json!({ "atMobiles": at_mobiles, "isAtAll": false });
+        }
+
         Ok(OutgoingPayload {
-            body: json!({
-                "msgtype": "markdown",
-                "markdown": {
-                    "title": title,
-                    "text": text
-                }
-            }),
+            body,
             content_type: "application/json",
         })
     }
 }
 
+/// The encrypted `success` DingTalk expects in answer to a callback.
+fn dingtalk_success_reply(crypt: &MsgCrypt, receive_id: &str) -> CallbackReply {
+    let encrypted = crypt.encrypt("success", receive_id);
+    let timestamp = (now_timestamp() * 1000).to_string();
+    let nonce = Uuid::new_v4().simple().to_string()[..16].to_string();
+    CallbackReply {
+        content_type: "application/json",
+        body: json!({
+            "msg_signature": crypt.signature(&timestamp, &nonce, &encrypted),
+            "timeStamp": timestamp,
+            "nonce": nonce,
+            "encrypt": encrypted,
+        })
+        .to_string(),
+    }
+}
+
+/// Converts an app event callback (`{"EventType": "user_add_org", ...}`):
+/// the event type followed by its fields.
+fn dingtalk_app_event_to_uem(payload: &Value, event_type: &str) -> UemEvent {
+    let mut lines = vec![format!("**Event: {}**", event_type)];
+    for (key, value) in payload.as_object().into_iter().flatten() {
+        if key == "EventType" || key == "TimeStamp" {
+            continue;
+        }
+        let value = match value {
+            Value::String(text) => text.clone(),
+            Value::Array(items) => items
+                .iter()
+                .map(|item| item.as_str().map(String::from).unwrap_or_else(|| item.to_string()))
+                .collect::<Vec<_>>()
+                .join(", "),
+            other => other.to_string(),
+        };
+        lines.push(format!("- {}: {}", key, value));
+    }
+
+    UemEvent {
+        id: Uuid::new_v4().to_string(),
+        source: "dingtalk".to_string(),
+        timestamp: payload
+            .get("TimeStamp")
+            .and_then(value_to_i64)
+            .map(|millis| millis / 1000)
+            .unwrap_or_else(now_timestamp),
+        title: None,
+        markdown: lines.join("\n"),
+        raw: payload.clone(),
+        meta: json!({ "event_type": event_type }),
+        mentions: Vec::new(),
+    }
+}
+
 fn dingtalk_markdown_from_payload(payload: &Value, msgtype: Option<&str>) -> String {
     match msgtype {
         Some("text") => payload
@@ -201,6 +356,43 @@ fn dingtalk_markdown_from_payload(payload: &Value, msgtype: Option<&str>) -> Str
     }
 }
 
+/// Replaces `@mobile` references listed in `at.atMobiles` with UEM mention
+/// tokens. Mobiles that are not referenced in the text are appended so the
+/// mention is not lost.
+fn normalize_dingtalk_mentions(text: &str, payload: &Value, mentions: &mut Vec<Mention>) -> String {
+    let mut mobiles = payload
+        .get("at")
+        .and_then(|value| value.get("atMobiles"))
+        .and_then(|value| value.as_array())
+        .map(|items| items.iter().filter_map(|item| item.as_str()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    mobiles.sort_by_key(|mobile| std::cmp::Reverse(mobile.len()));
+
+    let mut output = text.to_string();
+    let mut unreferenced = Vec::new();
+    for mobile in mobiles {
+        if mentions
+            .iter()
+            .any(|mention| mention.platform == "dingtalk" && mention.handle == mobile)
+        {
+            continue;
+        }
+        let token = mention_token("dingtalk", mobile);
+        match replace_handle_references(&output, mobile, &token) {
+            Some(replaced) => output = replaced,
+            None => unreferenced.push(token),
+        }
+        mentions.push(Mention::new("dingtalk", mobile, None));
+    }
+    if !unreferenced.is_empty() {
+        if !output.is_empty() {
+            output.push_str("\n\n");
+        }
+        output.push_str(&unreferenced.join(" "));
+    }
+    output
+}
+
 fn dingtalk_title_from_payload(payload: &Value, msgtype: Option<&str>) -> Option<String> {
     match msgtype {
         Some("markdown") => payload
@@ -248,6 +440,10 @@ fn value_to_i64(value: &Value) -> Option<i64> {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
+    use axum::http::HeaderMap;
+
     use super::*;
     use insta::assert_yaml_snapshot;
     use serde_json::json;
@@ -402,6 +598,7 @@ mod tests {
             markdown: "hello".to_string(),
             raw: json!({}),
             meta: json!({}),
+            mentions: Vec::new(),
         };
         let payload = adapter.uem_to_egress(&event).expect("payload");
         assert_yaml_snapshot!(
@@ -424,6 +621,7 @@ mod tests {
             markdown: "hello".to_string(),
             raw: json!({}),
             meta: json!({}),
+            mentions: Vec::new(),
         };
         let payload = adapter.uem_to_egress(&event).expect("payload");
         assert_yaml_snapshot!(
@@ -434,4 +632,97 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn dingtalk_uem_to_egress_with_mentions() {
+        let adapter = DingTalkAdapter;
+        let mut mention = Mention::new("slack", "U1", Some("Alice".to_string()));
+        mention
+            .handles
+            .insert("dingtalk".to_string(), "13800000000".to_string());
+        let event = UemEvent {
+            id: "evt-3".to_string(),
+            source: "slack".to_string(),
+            timestamp: 1,
+            title: None,
+            markdown: "ping <@slack:U1> and <@slack:U2>".to_string(),
+            raw: json!({}),
+            meta: json!({}),
+            mentions: vec![mention],
+        };
+        let payload = adapter.uem_to_egress(&event).expect("payload");
+        assert_yaml_snapshot!(
+            "adapters_dingtalk_uem_to_egress_with_mentions",
+            json!({
+                "content_type": payload.content_type,
+                "body": payload.body,
+            })
+        );
+    }
+
+    #[test]
+    fn dingtalk_opens_encrypted_event_callbacks() {
+        let settings = json!({
+            "token": "123456",
+            "encoding_aes_key": "4g5j64qlyl3zvetqxz5jiocdr586fn2zvjpa8zls3ij",
+        });
+        let crypt = MsgCrypt::from_settings(Some(&settings), "app_key").unwrap().unwrap();
+        let headers = HeaderMap::new();
+        // DingTalk timestamps are in milliseconds.
+        let timestamp = (now_timestamp() * 1000).to_string();
+        let open = |event: &str, settings: Option<&Value>| {
+            let encrypted = crypt.encrypt(event, "dingappkey");
+            let query: HashMap<String, String> = [
+                ("signature", crypt.signature(&timestamp, "n1", &encrypted)),
+                ("timestamp", timestamp.clone()),
+                ("nonce", "n1".to_string()),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+            let value = json!({ "encrypt": encrypted });
+            let mut body = IngressBody {
+                bytes: value.to_string().into_bytes(),
+                value,
+                content_type: "application/json".to_string(),
+            };
+            DingTalkAdapter
+                .open_callback(&Callback { headers: &headers, query: &query, settings }, &mut body)
+                .map(|answer| (answer, body))
+        };
+
+        // The reply must decrypt to `success` for the same receive id.
+        let decrypt_reply = |reply: &CallbackReply| {
+            let reply: Value = serde_json::from_str(&reply.body).unwrap();
+            let encrypted = reply["encrypt"].as_str().unwrap();
+            assert_eq!(
+                reply["msg_signature"],
+                crypt.signature(reply["timeStamp"].as_str().unwrap(), reply["nonce"].as_str().unwrap(), encrypted)
+            );
+            crypt.decrypt(encrypted).unwrap()
+        };
+
+        let (answer, _) = open(r#"{"EventType":"check_url"}"#, Some(&settings)).expect("open");
+        let CallbackAnswer::Handshake(reply) = answer else {
+            panic!("check_url is a handshake: {:?}", answer);
+        };
+        assert_eq!(decrypt_reply(&reply), ("success".to_string(), "dingappkey".to_string()));
+
+        let (answer, body) =
+            open(r#"{"EventType":"user_add_org","UserId":["u1","u2"],"TimeStamp":"1700000000000"}"#, Some(&settings))
+                .expect("open");
+        let CallbackAnswer::EventReply(reply) = answer else {
+            panic!("events are answered: {:?}", answer);
+        };
+        assert_eq!(decrypt_reply(&reply).0, "success");
+        let uem = DingTalkAdapter.ingress_body_to_uem(&body).expect("uem");
+        assert_eq!(uem.markdown, "**Event: user_add_org**\n- UserId: u1, u2");
+        assert_eq!(uem.timestamp, 1700000000);
+
+        let err = open(r#"{"EventType":"check_url"}"#, None).unwrap_err();
+        assert!(err.message.starts_with("encrypted DingTalk callback but no dingtalk.token"));
+        let wrong_token = json!({"token": "other", "encoding_aes_key": settings["encoding_aes_key"]});
+        let err = open(r#"{"EventType":"check_url"}"#, Some(&wrong_token)).unwrap_err();
+        assert_eq!(err.message, "invalid signature");
+    }
 }
@@ -38,6 +38,7 @@ impl WebhookAdapter for HttpAdapter {
             markdown,
             raw: payload.clone(),
             meta: json!({}),
+            mentions: Vec::new(),
         })
     }
 
@@ -51,6 +52,7 @@ impl WebhookAdapter for HttpAdapter {
                 "markdown": event.markdown,
                 "meta": event.meta,
                 "raw": event.raw,
+                "mentions": event.mentions,
             }),
             content_type: "application/json",
         })
@@ -106,6 +108,7 @@ mod tests {
             markdown: "hello".to_string(),
             raw: json!({}),
             meta: json!({}),
+            mentions: Vec::new(),
         };
         let payload = adapter.uem_to_egress(&event).expect("payload");
         assert_yaml_snapshot!(
@@ -1,11 +1,23 @@
-use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use base64::Engine;
 use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
-use crate::adapters::{AdapterError, WebhookAdapter};
-use crate::models::{OutgoingPayload, UemEvent};
+use crate::adapters::callback::{
+    aes256_cbc_decrypt, callback_error, header, hex, setting, signatures_match, strip_pkcs7,
+};
+use crate::adapters::{
+    str_field, AdapterError, Callback, CallbackAnswer, CallbackReply, MessageLimit, WebhookAdapter,
+};
+use crate::document::markdown::{write_markdown, MarkdownStyle};
+use crate::document::{
+    plain_text_for, plain_title, render_event, table, Alignment, Block, Capabilities, Document, Inline, Renderer, TableSupport,
+};
+use crate::models::{Mention, OutgoingPayload, UemEvent};
+use crate::utils::body::IngressBody;
+use crate::utils::mention::{lookup_mention, mention_token};
 
-/// Converts Standard Markdown to Lark's 'post' JSON structure.
+/// Renders a document as Lark's 'post' JSON structure.
 ///
 /// Lark Rich Text Structure:
 /// {
@@ -16,93 +28,20 @@ use crate::models::{OutgoingPayload, UemEvent};
 ///     ]
 ///   }
 /// }
-fn markdown_to_lark(title: Option<&str>, markdown: &str) -> Value {
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    
-    let parser = Parser::new_ext(markdown, options);
-    
+fn document_to_lark(title: Option<&str>, doc: &Document, mentions: &[Mention]) -> Value {
     let mut content: Vec<Vec<Value>> = Vec::new();
-    let mut current_paragraph: Vec<Value> = Vec::new();
-    
-    // State tracking
-    let mut link_url: Option<String> = None;
-
-    let flush_paragraph = |content: &mut Vec<Vec<Value>>, current: &mut Vec<Value>| {
-        if !current.is_empty() {
-            content.push(current.clone());
-            current.clear();
-        }
-    };
-
-    for event in parser {
-        match event {
-            Event::Start(tag) => match tag {
-                Tag::Paragraph => {}, // Start collecting
-                Tag::Heading { level: _, .. } => {
-                    flush_paragraph(&mut content, &mut current_paragraph);
-                },
-                Tag::BlockQuote(_) => {}, 
-                Tag::CodeBlock(_) => {
-                     flush_paragraph(&mut content, &mut current_paragraph);
-                }, 
-                Tag::List(_) => flush_paragraph(&mut content, &mut current_paragraph),
-                Tag::Item => {},
-                Tag::Emphasis => {},
-                Tag::Strong => {},
-                Tag::Strikethrough => {},
-                Tag::Link { dest_url, .. } => link_url = Some(dest_url.to_string()),
-                _ => {}
-            },
-            Event::End(tag) => match tag {
-                TagEnd::Paragraph => flush_paragraph(&mut content, &mut current_paragraph),
-                TagEnd::Heading(_) => {
-                     flush_paragraph(&mut content, &mut current_paragraph);
-                },
-                TagEnd::BlockQuote(_) => flush_paragraph(&mut content, &mut current_paragraph),
-                TagEnd::CodeBlock => flush_paragraph(&mut content, &mut current_paragraph),
-                TagEnd::List(_) => flush_paragraph(&mut content, &mut current_paragraph),
-                TagEnd::Item => flush_paragraph(&mut content, &mut current_paragraph),
-                TagEnd::Emphasis => {},
-                TagEnd::Strong => {},
-                TagEnd::Strikethrough => {},
-                TagEnd::Link => link_url = None,
-                _ => {}
-            },
-            Event::Text(text) => {
-                if let Some(url) = &link_url {
-                     current_paragraph.push(json!({
-                        "tag": "a",
-                        "text": text.as_ref(),
-                        "href": url
-                    }));
-                } else {
-                     current_paragraph.push(json!({
-                        "tag": "text",
-                        "text": text.as_ref()
-                    }));
-                }
-            },
-             Event::Code(text) => {
-                  current_paragraph.push(json!({
-                        "tag": "text",
-                        "text": text.as_ref()
-                    }));
-             },
-             Event::SoftBreak | Event::HardBreak => {},
-             _ => {}
-        }
+    for block in &doc.blocks {
+        lark_block(block, "", mentions, &mut content);
     }
-    flush_paragraph(&mut content, &mut current_paragraph);
 
     // Fallback if empty
     if content.is_empty() {
-         content.push(vec![json!({"tag": "text", "text": markdown})]);
+        content.push(vec![json!({"tag": "text", "text": ""})]);
     }
 
     let mut zh_cn = serde_json::Map::new();
     if let Some(t) = title {
-        zh_cn.insert("title".to_string(), json!(t));
+        zh_cn.insert("title".to_string(), json!(plain_title(t, mentions, "lark")));
     }
     zh_cn.insert("content".to_string(), json!(content));
 
@@ -111,23 +50,801 @@ fn markdown_to_lark(title: Option<&str>, markdown: &str) -> Value {
     })
 }
 
+/// Lark card tables show at most this many rows per page.
+const CARD_TABLE_PAGE_SIZE: usize = 10;
+
+/// Renders a document containing tables as an interactive card: runs of
+/// other blocks become `markdown` elements and each table a `table` element,
+/// since the 'post' format has no table support.
+fn document_to_lark_card(title: Option<&str>, doc: &Document, mentions: &[Mention]) -> Value {
+    let mut elements = Vec::new();
+    let mut pending: Vec<Block> = Vec::new();
+    for block in &doc.blocks {
+        match block {
+            Block::Table { alignments, header, rows } => {
+                flush_card_markdown(&mut pending, mentions, &mut elements);
+                elements.push(card_table(alignments, header, rows, mentions));
+            }
+            Block::Heading { inlines, .. } => {
+                pending.push(Block::Paragraph(vec![Inline::Strong(inlines.clone())]))
+            }
+            other => pending.push(other.clone()),
+        }
+    }
+    flush_card_markdown(&mut pending, mentions, &mut elements);
+
+    let mut card = serde_json::Map::new();
+    if let Some(title) = title {
+        card.insert(
+            "header".to_string(),
+            json!({ "title": { "tag": "plain_text", "content": plain_title(title, mentions, "lark") } }),
+        );
+    }
+    card.insert("elements".to_string(), json!(elements));
+    Value::Object(card)
+}
+
+fn flush_card_markdown(pending: &mut Vec<Block>, mentions: &[Mention], elements: &mut Vec<Value>) {
+    if pending.is_empty() {
+        return;
+    }
+    let doc = Document {
+        blocks: std::mem::take(pending),
+    };
+    elements.push(json!({ "tag": "markdown", "content": card_markdown(&doc, mentions) }));
+}
+
+fn card_table(alignments: &[Alignment], header: &[Vec<Inline>], rows: &[Vec<Vec<Inline>>], mentions: &[Mention]) -> Value {
+    let columns: Vec<Value> = header
+        .iter()
+        .enumerate()
+        .map(|(index, cell)| {
+            let align = match alignments.get(index).copied().unwrap_or(Alignment::None) {
+                Alignment::Center => "center",
+                Alignment::Right => "right",
+                Alignment::None | Alignment::Left => "left",
+            };
+            json!({
+                "name": format!("col_{}", index),
+                "display_name": plain_text_for(cell, mentions, "lark"),
+                "data_type": "lark_md",
+                "horizontal_align": align,
+            })
+        })
+        .collect();
+    let rows: Vec<Value> = rows
+        .iter()
+        .map(|row| {
+            let cells = row
+                .iter()
+                .take(header.len())
+                .enumerate()
+                .map(|(index, cell)| {
+                    let doc = Document {
+                        blocks: vec![Block::Paragraph(cell.clone())],
+                    };
+                    (format!("col_{}", index), json!(card_markdown(&doc, mentions)))
+                })
+                .collect::<serde_json::Map<_, _>>();
+            Value::Object(cells)
+        })
+        .collect();
+    json!({
+        "tag": "table",
+        "page_size": rows.len().clamp(1, CARD_TABLE_PAGE_SIZE),
+        "row_height": "low",
+        "header_style": { "bold": true },
+        "columns": columns,
+        "rows": rows,
+    })
+}
+
+/// Writes `lark_md`, which has no headings and takes mentions as `<at>` tags.
+fn card_markdown(doc: &Document, mentions: &[Mention]) -> String {
+    let style = MarkdownStyle {
+        emphasis: false,
+        strikethrough: true,
+    };
+    write_markdown(doc, style, &mut |platform, handle| {
+        match lookup_mention(mentions, platform, handle, "lark") {
+            (Some(user_id), _) => format!("<at id={}></at>", user_id),
+            (None, fallback) => format!("@{}", fallback),
+        }
+    })
+}
+
+/// Appends the post paragraphs (lines) for `block`, starting each line with
+/// `prefix` (quote markers and list indentation).
+fn lark_block(block: &Block, prefix: &str, mentions: &[Mention], content: &mut Vec<Vec<Value>>) {
+    match block {
+        Block::Heading { inlines, .. } | Block::Paragraph(inlines) => {
+            lark_lines(inlines, prefix, mentions, content)
+        }
+        Block::Quote(blocks) => {
+            let prefix = format!("{}> ", prefix);
+            for block in blocks {
+                lark_block(block, &prefix, mentions, content);
+            }
+        }
+        Block::List { start, items } => {
+            for (index, item) in items.iter().enumerate() {
+                let marker = match start {
+                    Some(start) => format!("{}. ", start + index as u64),
+                    None => "- ".to_string(),
+                };
+                let indent = format!("{}{}", prefix, " ".repeat(marker.len()));
+                for (position, block) in item.iter().enumerate() {
+                    let line_prefix = if position == 0 {
+                        format!("{}{}", prefix, marker)
+                    } else {
+                        indent.clone()
+                    };
+                    lark_block(block, &line_prefix, mentions, content);
+                }
+            }
+        }
+        Block::Code { text, .. } | Block::Html(text) => {
+            for line in text.trim_end_matches('\n').lines() {
+                content.push(vec![json!({ "tag": "text", "text": format!("{}{}", prefix, line) })]);
+            }
+        }
+        // Top-level tables become card tables; nested ones keep their columns as text.
+        Block::Table { alignments, header, rows } => {
+            for line in table::monospace(alignments, header, rows).lines() {
+                content.push(vec![json!({ "tag": "text", "text": format!("{}{}", prefix, line) })]);
+            }
+        }
+        Block::Rule => content.push(vec![json!({ "tag": "text", "text": format!("{}---", prefix) })]),
+    }
+}
+
+fn lark_lines(inlines: &[Inline], prefix: &str, mentions: &[Mention], content: &mut Vec<Vec<Value>>) {
+    let mut line: Vec<Value> = Vec::new();
+    if !prefix.is_empty() {
+        line.push(json!({ "tag": "text", "text": prefix }));
+    }
+    lark_inlines(inlines, mentions, &mut line, content, prefix);
+    if !line.is_empty() {
+        content.push(line);
+    }
+}
+
+fn lark_inlines(
+    inlines: &[Inline],
+    mentions: &[Mention],
+    line: &mut Vec<Value>,
+    content: &mut Vec<Vec<Value>>,
+    prefix: &str,
+) {
+    for inline in inlines {
+        match inline {
+            Inline::Text(text) | Inline::Code(text) | Inline::Html(text) => push_lark_text(line, text),
+            Inline::Emphasis(children) | Inline::Strong(children) | Inline::Strikethrough(children) => {
+                lark_inlines(children, mentions, line, content, prefix)
+            }
+            Inline::Link { url, children } => line.push(json!({
+                "tag": "a",
+                "text": plain_text_for(children, mentions, "lark"),
+                "href": url
+            })),
+            Inline::Image { url, alt } => line.push(json!({ "tag": "a", "text": alt, "href": url })),
+            Inline::Mention { platform, handle } => {
+                match lookup_mention(mentions, platform, handle, "lark") {
+                    (Some(user_id), _) => line.push(json!({ "tag": "at", "user_id": user_id })),
+                    (None, fallback) => {
+                        line.push(json!({ "tag": "text", "text": format!("@{}", fallback) }))
+                    }
+                }
+            }
+            Inline::SoftBreak | Inline::LineBreak => {
+                content.push(std::mem::take(line));
+                if !prefix.is_empty() {
+                    line.push(json!({ "tag": "text", "text": prefix }));
+                }
+            }
+        }
+    }
+}
+
+/// Appends text to the line, merging it into a preceding text element.
+fn push_lark_text(line: &mut Vec<Value>, text: &str) {
+    match line.last_mut() {
+        Some(last) if last["tag"] == "text" => {
+            let merged = format!("{}{}", last["text"].as_str().unwrap_or(""), text);
+            last["text"] = json!(merged);
+        }
+        _ => line.push(json!({ "tag": "text", "text": text })),
+    }
+}
+
+/// Replaces `@_user_N` placeholders in message text with UEM mention tokens
+/// using the message `mentions` array.
+fn normalize_lark_mentions(text: &str, message: &Value, mentions: &mut Vec<Mention>) -> String {
+    let mut entries: Vec<(&str, &str, Option<&str>)> = message
+        .get("mentions")
+        .and_then(|value| value.as_array())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| {
+                    let key = item.get("key").and_then(|value| value.as_str())?;
+                    let id = item.get("id");
+                    let open_id = id
+                        .and_then(|id| id.get("open_id"))
+                        .or_else(|| id.and_then(|id| id.get("user_id")))
+                        .or(id)
+                        .and_then(|value| value.as_str())?;
+                    let name = item.get("name").and_then(|value| value.as_str());
+                    Some((key, open_id, name))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    // Longest keys first so `@_user_10` is not clobbered by `@_user_1`.
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.0.len()));
+
+    let mut output = text.to_string();
+    for (key, open_id, name) in entries {
+        if output.contains(key) {
+            output = output.replace(key, &mention_token("lark", open_id));
+        }
+        if !mentions.iter().any(|mention| mention.handle == open_id) {
+            mentions.push(Mention::new("lark", open_id, name.map(String::from)));
+        }
+    }
+    strip_mention_keys(&output)
+}
+
+/// Removes `@_user_N` keys that had no entry in the `mentions` array and
+/// rewrites `@_all` to `@all`.
+fn strip_mention_keys(text: &str) -> String {
+    let text = text.replace("@_all", "@all");
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find("@_user_") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + "@_user_".len()..];
+        let digits = after.chars().take_while(|ch| ch.is_ascii_digit()).count();
+        if digits == 0 {
+            output.push_str("@_user_");
+        }
+        rest = &after[digits..];
+        if digits > 0 && output.ends_with(' ') && rest.starts_with(' ') {
+            rest = &rest[1..];
+        }
+    }
+    output.push_str(rest);
+    output.trim().to_string()
+}
+
+/// Returns the message content as JSON. Event callbacks carry it as a JSON
+/// string, custom bot payloads as an object (or a top-level `card`).
+fn lark_message_content(message: &Value) -> Value {
+    match message.get("content") {
+        Some(Value::String(raw)) => {
+            serde_json::from_str(raw).unwrap_or_else(|_| json!({ "text": raw }))
+        }
+        Some(content) => content.clone(),
+        None => message.get("card").cloned().unwrap_or(Value::Null),
+    }
+}
+
+/// Converts message content to `(title, markdown)` based on `message_type`.
+fn lark_content_to_markdown(
+    message_type: &str,
+    content: &Value,
+    mentions: &mut Vec<Mention>,
+    attachments: &mut Vec<Value>,
+) -> (Option<String>, String) {
+    match message_type {
+        "post" => lark_post_to_markdown(content, mentions, attachments),
+        "interactive" => lark_card_to_markdown(content.get("card").unwrap_or(content), mentions, attachments),
+        "image" => {
+            let key = str_field(content, "image_key");
+            attachments.push(json!({ "type": "image", "image_key": key }));
+            (None, format!("[Image: {}]", key))
+        }
+        "file" => {
+            let name = content
+                .get("file_name")
+                .and_then(|value| value.as_str())
+                .unwrap_or_else(|| str_field(content, "file_key"));
+            attachments.push(json!({
+                "type": "file",
+                "file_key": content.get("file_key"),
+                "file_name": content.get("file_name"),
+            }));
+            (None, format!("[File: {}]", name))
+        }
+        "media" => {
+            let name = content
+                .get("file_name")
+                .and_then(|value| value.as_str())
+                .unwrap_or_else(|| str_field(content, "file_key"));
+            attachments.push(json!({
+                "type": "media",
+                "file_key": content.get("file_key"),
+                "file_name": content.get("file_name"),
+                "image_key": content.get("image_key"),
+                "duration": content.get("duration"),
+            }));
+            (None, format!("[Media: {}]", name))
+        }
+        "audio" => (None, "[Audio]".to_string()),
+        "sticker" => (None, "[Sticker]".to_string()),
+        _ => {
+            let text = content
+                .get("text")
+                .and_then(|value| value.as_str())
+                .unwrap_or("");
+            (None, lark_at_tags_to_tokens(text, mentions))
+        }
+    }
+}
+
+/// Picks the post body for the preferred locale: `zh_cn`, then `en_us`, then
+/// whichever comes first. Accepts bodies wrapped in `post` or unwrapped.
+fn lark_post_locale(content: &Value) -> &Value {
+    let content = content.get("post").unwrap_or(content);
+    if content.get("content").is_some() {
+        return content;
+    }
+    content
+        .get("zh_cn")
+        .or_else(|| content.get("en_us"))
+        .or_else(|| content.as_object().and_then(|map| map.values().next()))
+        .unwrap_or(content)
+}
+
+fn lark_post_to_markdown(
+    content: &Value,
+    mentions: &mut Vec<Mention>,
+    attachments: &mut Vec<Value>,
+) -> (Option<String>, String) {
+    let body = lark_post_locale(content);
+    let title = body
+        .get("title")
+        .and_then(|value| value.as_str())
+        .filter(|title| !title.is_empty())
+        .map(String::from);
+    let paragraphs = body
+        .get("content")
+        .and_then(|value| value.as_array())
+        .map(|paragraphs| lark_paragraphs_to_markdown(paragraphs, mentions, attachments))
+        .unwrap_or_default();
+    (title, paragraphs)
+}
+
+fn lark_paragraphs_to_markdown(
+    paragraphs: &[Value],
+    mentions: &mut Vec<Mention>,
+    attachments: &mut Vec<Value>,
+) -> String {
+    let mut blocks: Vec<String> = Vec::new();
+    for paragraph in paragraphs {
+        let elements = match paragraph.as_array() {
+            Some(elements) => elements,
+            None => continue,
+        };
+        let mut line = String::new();
+        for element in elements {
+            match element.get("tag").and_then(|value| value.as_str()) {
+                Some("text") => {
+                    let text = str_field(element, "text");
+                    line.push_str(&apply_lark_style(text, element.get("style")));
+                }
+                Some("a") => {
+                    let href = str_field(element, "href");
+                    let text = element
+                        .get("text")
+                        .and_then(|value| value.as_str())
+                        .filter(|text| !text.is_empty())
+                        .unwrap_or(href);
+                    line.push_str(&format!(
+                        "[{}]({})",
+                        apply_lark_style(text, element.get("style")),
+                        href
+                    ));
+                }
+                Some("at") => {
+                    let user_id = str_field(element, "user_id");
+                    let name = element.get("user_name").and_then(|value| value.as_str());
+                    line.push_str(&lark_at_to_token(user_id, name, mentions));
+                }
+                Some("img") => {
+                    let key = str_field(element, "image_key");
+                    attachments.push(json!({ "type": "image", "image_key": key }));
+                    line.push_str(&format!("[Image: {}]", key));
+                }
+                Some("media") => {
+                    let key = str_field(element, "file_key");
+                    attachments.push(json!({
+                        "type": "media",
+                        "file_key": key,
+                        "image_key": element.get("image_key"),
+                    }));
+                    line.push_str(&format!("[Media: {}]", key));
+                }
+                Some("emotion") => {
+                    line.push_str(&format!(":{}:", str_field(element, "emoji_type")));
+                }
+                Some("hr") => {
+                    flush_line(&mut blocks, &mut line);
+                    blocks.push("---".to_string());
+                }
+                Some("code_block") => {
+                    flush_line(&mut blocks, &mut line);
+                    let language = element
+                        .get("language")
+                        .and_then(|value| value.as_str())
+                        .unwrap_or("")
+                        .to_lowercase();
+                    let code = str_field(element, "text").trim_end_matches('\n');
+                    blocks.push(format!("```{}\n{}\n```", language, code));
+                }
+                Some("md") => {
+                    line.push_str(&lark_at_tags_to_tokens(str_field(element, "text"), mentions));
+                }
+                _ => {}
+            }
+        }
+        flush_line(&mut blocks, &mut line);
+    }
+    blocks.join("\n\n")
+}
 
+fn flush_line(blocks: &mut Vec<String>, line: &mut String) {
+    if !line.trim().is_empty() {
+        blocks.push(line.trim_end().to_string());
+    }
+    line.clear();
+}
+
+fn apply_lark_style(text: &str, style: Option<&Value>) -> String {
+    let styles: Vec<&str> = style
+        .and_then(|value| value.as_array())
+        .map(|items| items.iter().filter_map(|item| item.as_str()).collect())
+        .unwrap_or_default();
+    let trimmed = text.trim();
+    if styles.is_empty() || trimmed.is_empty() {
+        return text.to_string();
+    }
+    let mut styled = trimmed.to_string();
+    if styles.contains(&"lineThrough") {
+        styled = format!("~~{}~~", styled);
+    }
+    if styles.contains(&"italic") {
+        styled = format!("*{}*", styled);
+    }
+    if styles.contains(&"bold") {
+        styled = format!("**{}**", styled);
+    }
+    let leading = &text[..text.len() - text.trim_start().len()];
+    let trailing = &text[text.trim_end().len()..];
+    format!("{}{}{}", leading, styled, trailing)
+}
+
+/// Converts interactive card JSON (1.0 `elements` or 2.0 `body.elements`) to
+/// markdown, using the header title as the event title.
+fn lark_card_to_markdown(
+    card: &Value,
+    mentions: &mut Vec<Mention>,
+    attachments: &mut Vec<Value>,
+) -> (Option<String>, String) {
+    let title = card
+        .get("header")
+        .and_then(|header| header.get("title"))
+        .and_then(|title| title.get("content").or(Some(title)))
+        .and_then(|value| value.as_str())
+        .or_else(|| card.get("title").and_then(|value| value.as_str()))
+        .filter(|title| !title.is_empty())
+        .map(String::from);
+    let elements = card
+        .get("body")
+        .and_then(|body| body.get("elements"))
+        .or_else(|| card.get("elements"))
+        .and_then(|value| value.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let mut blocks = Vec::new();
+    lark_card_elements(elements, mentions, attachments, &mut blocks);
+    (title, blocks.join("\n\n"))
+}
+
+fn lark_card_elements(
+    elements: &[Value],
+    mentions: &mut Vec<Mention>,
+    attachments: &mut Vec<Value>,
+    blocks: &mut Vec<String>,
+) {
+    for element in elements {
+        // Message events carry cards in a simplified post-like form.
+        if element.is_array() {
+            let paragraph =
+                lark_paragraphs_to_markdown(std::slice::from_ref(element), mentions, attachments);
+            if !paragraph.is_empty() {
+                blocks.push(paragraph);
+            }
+            continue;
+        }
+        match element.get("tag").and_then(|value| value.as_str()) {
+            Some("div") => {
+                let mut lines = Vec::new();
+                if let Some(text) = lark_card_text(element.get("text"), mentions) {
+                    lines.push(text);
+                }
+                if let Some(fields) = element.get("fields").and_then(|value| value.as_array()) {
+                    for field in fields {
+                        if let Some(text) = lark_card_text(field.get("text"), mentions) {
+                            lines.push(format!("- {}", text));
+                        }
+                    }
+                }
+                if !lines.is_empty() {
+                    blocks.push(lines.join("\n"));
+                }
+            }
+            Some("markdown") | Some("lark_md") => {
+                let text = lark_at_tags_to_tokens(str_field(element, "content"), mentions);
+                if !text.is_empty() {
+                    blocks.push(text);
+                }
+            }
+            Some("plain_text") => {
+                let text = str_field(element, "content");
+                if !text.is_empty() {
+                    blocks.push(text.to_string());
+                }
+            }
+            Some("hr") => blocks.push("---".to_string()),
+            Some("img") => {
+                let key = str_field(element, "img_key");
+                let alt = element
+                    .get("alt")
+                    .and_then(|alt| alt.get("content"))
+                    .and_then(|value| value.as_str())
+                    .filter(|alt| !alt.is_empty())
+                    .unwrap_or("Image");
+                attachments.push(json!({ "type": "image", "image_key": key }));
+                blocks.push(format!("[{}: {}]", alt, key));
+            }
+            Some("note") => {
+                let notes: Vec<String> = element
+                    .get("elements")
+                    .and_then(|value| value.as_array())
+                    .map(|items| {
+                        items
+                            .iter()
+                            .filter_map(|item| lark_card_text(Some(item), mentions))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                if !notes.is_empty() {
+                    blocks.push(format!("*{}*", notes.join(" ")));
+                }
+            }
+            Some("action") => {
+                let lines: Vec<String> = element
+                    .get("actions")
+                    .and_then(|value| value.as_array())
+                    .map(|actions| {
+                        actions
+                            .iter()
+                            .filter_map(|action| {
+                                let label = lark_card_text(action.get("text"), mentions)?;
+                                let url = action
+                                    .get("url")
+                                    .or_else(|| {
+                                        action
+                                            .get("multi_url")
+                                            .and_then(|multi| multi.get("url"))
+                                    })
+                                    .and_then(|value| value.as_str())
+                                    .filter(|url| !url.is_empty())?;
+                                Some(format!("- [{}]({})", label, url))
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                if !lines.is_empty() {
+                    blocks.push(lines.join("\n"));
+                }
+            }
+            Some("column_set") => {
+                if let Some(columns) = element.get("columns").and_then(|value| value.as_array()) {
+                    for column in columns {
+                        if let Some(children) =
+                            column.get("elements").and_then(|value| value.as_array())
+                        {
+                            lark_card_elements(children, mentions, attachments, blocks);
+                        }
+                    }
+                }
+            }
+            Some("collapsible_panel") => {
+                if let Some(children) = element.get("elements").and_then(|value| value.as_array())
+                {
+                    lark_card_elements(children, mentions, attachments, blocks);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Reads a card text object (`plain_text` or `lark_md`).
+fn lark_card_text(value: Option<&Value>, mentions: &mut Vec<Mention>) -> Option<String> {
+    let value = value?;
+    let content = value
+        .get("content")
+        .and_then(|value| value.as_str())
+        .or_else(|| value.as_str())?;
+    if content.is_empty() {
+        return None;
+    }
+    Some(if value.get("tag").and_then(|tag| tag.as_str()) == Some("plain_text") {
+        content.to_string()
+    } else {
+        lark_at_tags_to_tokens(content, mentions)
+    })
+}
+
+/// Rewrites `<at user_id="ou_x">Name</at>` (and the card `<at id=ou_x></at>`
+/// form) into UEM mention tokens. `all` becomes `@all`.
+fn lark_at_tags_to_tokens(text: &str, mentions: &mut Vec<Mention>) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("<at ") {
+        output.push_str(&rest[..start]);
+        let candidate = &rest[start..];
+        let (open_end, close) = match (candidate.find('>'), candidate.find("</at>")) {
+            (Some(open_end), Some(close)) if open_end < close => (open_end, close),
+            _ => {
+                output.push_str("<at ");
+                rest = &candidate[4..];
+                continue;
+            }
+        };
+        let attributes = &candidate[4..open_end];
+        let name = candidate[open_end + 1..close].trim();
+        let user_id = attributes
+            .split_whitespace()
+            .filter_map(|attribute| attribute.split_once('='))
+            .find(|(key, _)| matches!(*key, "user_id" | "id" | "open_id"))
+            .map(|(_, value)| value.trim_matches(|ch| ch == '"' || ch == '\''));
+        match user_id {
+            Some(user_id) => {
+                let name = if name.is_empty() { None } else { Some(name) };
+                output.push_str(&lark_at_to_token(user_id, name, mentions));
+            }
+            None => output.push_str(&format!("@{}", name)),
+        }
+        rest = &candidate[close + "</at>".len()..];
+    }
+    output.push_str(rest);
+    output
+}
+
+fn lark_at_to_token(user_id: &str, name: Option<&str>, mentions: &mut Vec<Mention>) -> String {
+    if user_id == "all" {
+        return "@all".to_string();
+    }
+    // Message events use `@_user_N` keys resolved later from `mentions`.
+    if user_id.starts_with("@_") {
+        return user_id.to_string();
+    }
+    if !mentions.iter().any(|mention| mention.handle == user_id) {
+        mentions.push(Mention::new("lark", user_id, name.map(String::from)));
+    }
+    mention_token("lark", user_id)
+}
+
+/// Decrypts an `{"encrypt": ...}` event body: AES-256-CBC keyed with
+/// SHA-256 of the app's Encrypt Key, IV prepended to the ciphertext.
+fn decrypt_lark(encrypt_key: &str, encrypted: &str) -> Result<Vec<u8>, AdapterError> {
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(encrypted)
+        .map_err(|_| callback_error("encrypted Lark payload is not valid base64"))?;
+    if data.len() < 32 {
+        return Err(callback_error("encrypted Lark payload is too short"));
+    }
+    let (iv, ciphertext) = data.split_at(16);
+    let plaintext = aes256_cbc_decrypt(&Sha256::digest(encrypt_key.as_bytes()), iv, ciphertext)?;
+    strip_pkcs7(plaintext, 16)
+}
+
+/// How far `X-Lark-Request-Timestamp` may be from now before a signed request
+/// is treated as a replay.
+const MAX_TIMESTAMP_SKEW_SECS: i64 = 300;
+
+/// `X-Lark-Signature`: hex SHA-256 of timestamp, nonce, Encrypt Key and body.
+fn lark_signature(timestamp: &str, nonce: &str, encrypt_key: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(timestamp.as_bytes());
+    hasher.update(nonce.as_bytes());
+    hasher.update(encrypt_key.as_bytes());
+    hasher.update(body);
+    hex(&hasher.finalize())
+}
 
 #[derive(Debug)]
 pub struct LarkAdapter;
 
 impl WebhookAdapter for LarkAdapter {
+    /// With `lark.encrypt_key` set, checks `X-Lark-Signature` and its
+    /// timestamp and decrypts `{"encrypt": ...}` bodies. The only unsigned
+    /// request accepted is the encrypted URL verification, which is answered
+    /// here.
+    fn open_callback(
+        &self,
+        callback: &Callback<'_>,
+        body: &mut IngressBody,
+    ) -> Result<CallbackAnswer, AdapterError> {
+        let headers = callback.headers;
+        let encrypted = body.value.get("encrypt").and_then(|value| value.as_str()).map(String::from);
+        let Some(encrypt_key) = setting(callback.settings, "encrypt_key") else {
+            if encrypted.is_some() {
+                return Err(callback_error("encrypted Lark payload but no lark.encrypt_key is configured"));
+            }
+            return Ok(CallbackAnswer::Event);
+        };
+
+        if let Some(encrypted) = &encrypted {
+            let plaintext = decrypt_lark(encrypt_key, encrypted)?;
+            body.value = serde_json::from_slice(&plaintext)
+                .map_err(|err| callback_error(format!("decrypted Lark payload is not JSON: {}", err)))?;
+        }
+
+        let Some(signature) = header(headers, "x-lark-signature") else {
+            let challenge = body
+                .value
+                .get("challenge")
+                .and_then(|value| value.as_str())
+                .filter(|_| encrypted.is_some() && str_field(&body.value, "type") == "url_verification");
+            return match challenge {
+                Some(challenge) => Ok(CallbackAnswer::Handshake(CallbackReply {
+                    content_type: "application/json",
+                    body: json!({ "challenge": challenge }).to_string(),
+                })),
+                None => Err(callback_error("missing X-Lark-Signature")),
+            };
+        };
+        let timestamp = header(headers, "x-lark-request-timestamp").unwrap_or_default();
+        let fresh = timestamp
+            .parse::<i64>()
+            .is_ok_and(|timestamp| (now_timestamp() - timestamp).abs() <= MAX_TIMESTAMP_SKEW_SECS);
+        if !fresh {
+            return Err(callback_error("X-Lark-Request-Timestamp is missing or too far from now"));
+        }
+        let expected = lark_signature(
+            timestamp,
+            header(headers, "x-lark-request-nonce").unwrap_or_default(),
+            encrypt_key,
+            &body.bytes,
+        );
+        if !signatures_match(&expected, signature) {
+            return Err(callback_error("invalid X-Lark-Signature"));
+        }
+        Ok(CallbackAnswer::Event)
+    }
+
     fn ingress_to_uem(&self, payload: &Value) -> Result<UemEvent, AdapterError> {
         let header = payload.get("header").unwrap_or(payload);
         let event = payload.get("event").unwrap_or(payload);
         let message = event.get("message").unwrap_or(payload);
 
-        let content_text = message
-            .get("content")
+        let message_type = message
+            .get("message_type")
+            .or_else(|| message.get("msg_type"))
             .and_then(|value| value.as_str())
-            .and_then(|raw| serde_json::from_str::<Value>(raw).ok())
-            .and_then(|value| value.get("text").and_then(|text| text.as_str()).map(String::from))
-            .unwrap_or_default();
+            .unwrap_or("text");
+        let content = lark_message_content(message);
+
+        let mut mentions = Vec::new();
+        let mut attachments = Vec::new();
+        let (title, content_text) =
+            lark_content_to_markdown(message_type, &content, &mut mentions, &mut attachments);
+        let content_text = normalize_lark_mentions(&content_text, message, &mut mentions);
 
         let event_id = header
             .get("event_id")
@@ -141,17 +858,20 @@ impl WebhookAdapter for LarkAdapter {
             .map(|millis| millis / 1000)
             .unwrap_or_else(now_timestamp);
 
-        let meta = json!({
-            "message_type": message.get("message_type"),
+        let mut meta = json!({
+            "message_type": message_type,
             "chat_id": message.get("chat_id"),
             "sender": event.get("sender"),
         });
+        if !attachments.is_empty() {
+            meta["attachments"] = json!(attachments);
+        }
 
         Ok(UemEvent {
             id: event_id,
             source: "lark".to_string(),
             timestamp,
-            title: None,
+            title,
             markdown: if content_text.is_empty() {
                 payload.to_string()
             } else {
@@ -159,11 +879,47 @@ impl WebhookAdapter for LarkAdapter {
             },
             raw: payload.clone(),
             meta,
+            mentions,
         })
     }
 
     fn uem_to_egress(&self, event: &UemEvent) -> Result<OutgoingPayload, AdapterError> {
-        let post_content = markdown_to_lark(event.title.as_deref(), &event.markdown);
+        render_event(self, event, &Document::parse(&event.markdown))
+    }
+
+    fn renderer(&self) -> Option<&dyn Renderer> {
+        Some(self)
+    }
+
+    fn message_limit(&self) -> Option<MessageLimit> {
+        // Lark caps request bodies at 30 KB; leave room for the post structure.
+        Some(MessageLimit::bytes(20_000))
+    }
+}
+
+impl Renderer for LarkAdapter {
+    fn platform(&self) -> &'static str {
+        "lark"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            tables: TableSupport::Native,
+            images: false,
+            code_blocks: false,
+            mentions: true,
+        }
+    }
+
+    fn render(&self, event: &UemEvent, doc: &Document) -> Result<OutgoingPayload, AdapterError> {
+        if doc.blocks.iter().any(|block| matches!(block, Block::Table { .. })) {
+            let card = document_to_lark_card(event.title.as_deref(), doc, &event.mentions);
+            return Ok(OutgoingPayload {
+                body: json!({ "msg_type": "interactive", "card": card }),
+                content_type: "application/json",
+            });
+        }
+        let post_content = document_to_lark(event.title.as_deref(), doc, &event.mentions);
         Ok(OutgoingPayload {
             body: json!({
                 "msg_type": "post",
@@ -189,6 +945,10 @@ fn value_to_i64(value: &Value) -> Option<i64> {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
+    use axum::http::HeaderMap;
+
     use super::*;
     use insta::assert_yaml_snapshot;
     use serde_json::json;
@@ -220,6 +980,112 @@ mod tests {
         );
     }
 
+    #[test]
+    fn lark_ingress_post() {
+        let adapter = LarkAdapter;
+        let content = json!({
+            "en_us": {
+                "title": "Release notes",
+                "content": [[{ "tag": "text", "text": "english" }]]
+            },
+            "zh_cn": {
+                "title": "发布说明",
+                "content": [
+                    [
+                        { "tag": "at", "user_id": "@_user_1", "user_name": "Tom" },
+                        { "tag": "text", "text": " 版本 " },
+                        { "tag": "text", "text": "v1.2", "style": ["bold"] },
+                        { "tag": "text", "text": " 已发布 " },
+                        { "tag": "a", "text": "详情", "href": "https://example.com/release" }
+                    ],
+                    [{ "tag": "img", "image_key": "img_v2_1" }],
+                    [{ "tag": "hr" }],
+                    [{ "tag": "code_block", "language": "GO", "text": "func main() {}\n" }],
+                    [{ "tag": "emotion", "emoji_type": "SMILE" }]
+                ]
+            }
+        });
+        let payload = json!({
+            "header": { "event_id": "evt-post-1", "event_time": "1700000000000" },
+            "event": {
+                "message": {
+                    "message_type": "post",
+                    "chat_id": "oc_1",
+                    "content": content.to_string(),
+                    "mentions": [
+                        { "key": "@_user_1", "id": { "open_id": "ou_1" }, "name": "Tom" }
+                    ]
+                }
+            }
+        });
+        let event = adapter.ingress_to_uem(&payload).expect("uem");
+        assert_yaml_snapshot!(
+            "adapters_lark_ingress_post",
+            json!({
+                "title": event.title,
+                "markdown": event.markdown,
+                "meta": event.meta,
+                "mentions": event.mentions,
+            })
+        );
+    }
+
+    #[test]
+    fn lark_ingress_interactive_card() {
+        let adapter = LarkAdapter;
+        let payload = json!({
+            "msg_type": "interactive",
+            "card": {
+                "header": { "title": { "tag": "plain_text", "content": "Alert firing" } },
+                "elements": [
+                    { "tag": "div", "text": { "tag": "lark_md", "content": "**CPU** high on db-7 <at id=ou_2></at>" } },
+                    { "tag": "div", "fields": [
+                        { "is_short": true, "text": { "tag": "lark_md", "content": "**Severity**: critical" } }
+                    ] },
+                    { "tag": "hr" },
+                    { "tag": "markdown", "content": "See [dashboard](https://grafana/d/1)" },
+                    { "tag": "action", "actions": [
+                        { "tag": "button", "text": { "tag": "plain_text", "content": "Silence" }, "url": "https://am/silence" }
+                    ] },
+                    { "tag": "note", "elements": [{ "tag": "plain_text", "content": "from alertmanager" }] }
+                ]
+            }
+        });
+        let event = adapter.ingress_to_uem(&payload).expect("uem");
+        assert_yaml_snapshot!(
+            "adapters_lark_ingress_interactive",
+            json!({
+                "title": event.title,
+                "markdown": event.markdown,
+                "meta": event.meta,
+                "mentions": event.mentions,
+            })
+        );
+    }
+
+    #[test]
+    fn lark_ingress_image_and_file() {
+        let adapter = LarkAdapter;
+        let image = json!({
+            "event": { "message": { "message_type": "image", "content": "{\"image_key\":\"img_v2_9\"}" } }
+        });
+        let file = json!({
+            "event": { "message": {
+                "message_type": "file",
+                "content": "{\"file_key\":\"file_v2_1\",\"file_name\":\"report.pdf\"}"
+            } }
+        });
+        let image = adapter.ingress_to_uem(&image).expect("uem");
+        let file = adapter.ingress_to_uem(&file).expect("uem");
+        assert_yaml_snapshot!(
+            "adapters_lark_ingress_image_and_file",
+            json!({
+                "image": { "markdown": image.markdown, "meta": image.meta },
+                "file": { "markdown": file.markdown, "meta": file.meta },
+            })
+        );
+    }
+
     #[test]
     fn lark_uem_to_egress() {
         let adapter = LarkAdapter;
@@ -231,6 +1097,7 @@ mod tests {
             markdown: "hello".to_string(),
             raw: json!({}),
             meta: json!({}),
+            mentions: Vec::new(),
         };
         let payload = adapter.uem_to_egress(&event).expect("payload");
         assert_yaml_snapshot!(
@@ -253,6 +1120,7 @@ mod tests {
             markdown: "hello".to_string(),
             raw: json!({}),
             meta: json!({}),
+            mentions: Vec::new(),
         };
         let payload = adapter.uem_to_egress(&event).expect("payload");
         assert_yaml_snapshot!(
@@ -267,8 +1135,124 @@ mod tests {
     #[test]
     fn test_lark_structure() {
         let md = "Hello [World](http://example.com)";
-        let lark = markdown_to_lark(None, md);
+        let lark = document_to_lark(None, &Document::parse(md), &[]);
         let content = lark["zh_cn"]["content"].as_array().unwrap();
         assert!(!content.is_empty());
     }
+
+    #[test]
+    fn lark_mentions_round_trip() {
+        let adapter = LarkAdapter;
+        let payload = json!({
+            "event": {
+                "message": {
+                    "message_type": "text",
+                    "content": "{\"text\":\"@_user_1 please check\"}",
+                    "mentions": [
+                        { "key": "@_user_1", "id": { "open_id": "ou_1" }, "name": "Alice" }
+                    ]
+                }
+            }
+        });
+        let mut event = adapter.ingress_to_uem(&payload).expect("uem");
+        assert_eq!(event.markdown, "<@lark:ou_1> please check");
+
+        event.markdown = "cc <@lark:ou_1> and <@slack:U9>".to_string();
+        let payload = adapter.uem_to_egress(&event).expect("payload");
+        assert_eq!(
+            payload.body["content"]["post"]["zh_cn"]["content"][0],
+            json!([
+                { "tag": "text", "text": "cc " },
+                { "tag": "at", "user_id": "ou_1" },
+                { "tag": "text", "text": " and " },
+                { "tag": "text", "text": "@U9" }
+            ])
+        );
+    }
+
+    fn encrypt_lark(encrypt_key: &str, plaintext: &str) -> String {
+        use aes::cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit};
+        let iv = [7u8; 16];
+        let mut buffer = plaintext.as_bytes().to_vec();
+        buffer.resize(plaintext.len() + 16, 0);
+        let ciphertext = cbc::Encryptor::<aes::Aes256>::new_from_slices(&Sha256::digest(encrypt_key), &iv)
+            .unwrap()
+            .encrypt_padded_mut::<Pkcs7>(&mut buffer, plaintext.len())
+            .unwrap();
+        base64::engine::general_purpose::STANDARD.encode([iv.as_slice(), ciphertext].concat())
+    }
+
+    #[test]
+    fn lark_decrypts_documented_sample() {
+        // From the Lark open platform docs on event encryption.
+        let plaintext = decrypt_lark("test key", "P37w+VZImNgPEO1RBhJ6RtKl7n6zymIbEG1pReEzghk=").expect("decrypt");
+        assert_eq!(plaintext, b"hello world");
+        assert!(decrypt_lark("other key", "P37w+VZImNgPEO1RBhJ6RtKl7n6zymIbEG1pReEzghk=").is_err());
+    }
+
+    fn open(
+        headers: &HeaderMap,
+        body: &mut IngressBody,
+        settings: Option<&Value>,
+    ) -> Result<CallbackAnswer, AdapterError> {
+        let query = HashMap::new();
+        LarkAdapter.open_callback(&Callback { headers, query: &query, settings }, body)
+    }
+
+    #[test]
+    fn lark_opens_encrypted_callbacks() {
+        let settings = json!({"encrypt_key": "kudryavka"});
+        let body_for = |value: Value| {
+            let bytes = value.to_string().into_bytes();
+            IngressBody { value, bytes, content_type: "application/json".to_string() }
+        };
+
+        let challenge = json!({"encrypt": encrypt_lark("kudryavka", r#"{"challenge":"c-1","type":"url_verification"}"#)});
+        let answer = open(&HeaderMap::new(), &mut body_for(challenge.clone()), Some(&settings)).expect("open");
+        assert_eq!(
+            answer,
+            CallbackAnswer::Handshake(CallbackReply {
+                content_type: "application/json",
+                body: r#"{"challenge":"c-1"}"#.to_string(),
+            })
+        );
+        let err = open(&HeaderMap::new(), &mut body_for(challenge), None).unwrap_err();
+        assert_eq!(err.message, "encrypted Lark payload but no lark.encrypt_key is configured");
+
+        // Unsigned requests are only accepted as an encrypted handshake.
+        let unsigned = [
+            json!({"challenge": "c-1", "type": "url_verification"}),
+            json!({"encrypt": encrypt_lark("kudryavka", r#"{"type":"url_verification","event":{"text":"hi"}}"#)}),
+        ];
+        for value in unsigned {
+            let err = open(&HeaderMap::new(), &mut body_for(value), Some(&settings)).unwrap_err();
+            assert_eq!(err.message, "missing X-Lark-Signature");
+        }
+
+        let event = r#"{"schema":"2.0","header":{"event_id":"e-1"},"event":{"message":{"message_type":"text","content":"{\"text\":\"hi\"}"}}}"#;
+        let mut body = body_for(json!({"encrypt": encrypt_lark("kudryavka", event)}));
+        let timestamp = now_timestamp().to_string();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-lark-request-timestamp", timestamp.parse().unwrap());
+        headers.insert("x-lark-request-nonce", "n-1".parse().unwrap());
+        let err = open(&headers, &mut body.clone(), Some(&settings)).unwrap_err();
+        assert_eq!(err.message, "missing X-Lark-Signature");
+        headers.insert("x-lark-signature", "0".repeat(64).parse().unwrap());
+        let err = open(&headers, &mut body.clone(), Some(&settings)).unwrap_err();
+        assert_eq!(err.message, "invalid X-Lark-Signature");
+
+        let mut replayed = headers.clone();
+        replayed.insert("x-lark-request-timestamp", "1700000000".parse().unwrap());
+        let stale = lark_signature("1700000000", "n-1", "kudryavka", &body.bytes);
+        replayed.insert("x-lark-signature", stale.parse().unwrap());
+        let err = open(&replayed, &mut body.clone(), Some(&settings)).unwrap_err();
+        assert_eq!(err.message, "X-Lark-Request-Timestamp is missing or too far from now");
+
+        let signature = lark_signature(&timestamp, "n-1", "kudryavka", &body.bytes);
+        headers.insert("x-lark-signature", signature.parse().unwrap());
+        assert_eq!(open(&headers, &mut body, Some(&settings)).expect("open"), CallbackAnswer::Event);
+        let uem = LarkAdapter.ingress_body_to_uem(&body).expect("uem");
+        assert_eq!(uem.id, "e-1");
+        assert_eq!(uem.markdown, "hi");
+    }
 }
@@ -0,0 +1,279 @@
+//! Declarative ingress adapters built from [`Mapping`]s: JSONPath picks the
+//! id, title, timestamp and meta fields out of the payload, and a minijinja
+//! template renders the markdown.
+
+use minijinja::Environment;
+use serde_json::{json, Map, Value};
+use serde_json_path::JsonPath;
+use uuid::Uuid;
+
+use crate::adapters::{ingress_platform, AdapterError, WebhookAdapter};
+use crate::models::{Mapping, OutgoingPayload, UemEvent};
+
+/// Bounds template loops; a template over a large payload stays well below it.
+const TEMPLATE_FUEL: u64 = 100_000;
+
+/// Turns arbitrary vendor JSON into UEM events as described by a [`Mapping`]
+/// managed through `/api/mappings`. Ingress only.
+#[derive(Debug)]
+pub struct MappedAdapter {
+    mapping: Mapping,
+}
+
+impl MappedAdapter {
+    pub fn new(mapping: Mapping) -> Self {
+        Self { mapping }
+    }
+}
+
+impl WebhookAdapter for MappedAdapter {
+    fn ingress_to_uem(&self, payload: &Value) -> Result<UemEvent, AdapterError> {
+        let mapping = &self.mapping;
+        let optional = |path: &Option<String>| match path {
+            Some(path) => select(path, payload),
+            None => Ok(None),
+        };
+
+        let id = optional(&mapping.id_path)?
+            .as_ref()
+            .and_then(scalar_string)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        let title = optional(&mapping.title_path)?.as_ref().and_then(scalar_string);
+        let timestamp = optional(&mapping.timestamp_path)?
+            .as_ref()
+            .and_then(timestamp_seconds)
+            .unwrap_or_else(now_timestamp);
+        let mut meta = Map::new();
+        for (field, path) in &mapping.meta {
+            if let Some(value) = select(path, payload)? {
+                meta.insert(field.clone(), value);
+            }
+        }
+        let meta = Value::Object(meta);
+
+        let context = json!({
+            "payload": payload,
+            "id": id,
+            "title": title,
+            "meta": meta,
+        });
+        let markdown = environment()
+            .render_str(&mapping.template, context)
+            .map_err(|err| AdapterError {
+                message: format!("mapping {}: {}", mapping.name, err),
+            })?;
+
+        Ok(UemEvent {
+            id,
+            source: mapping.name.clone(),
+            timestamp,
+            title,
+            markdown: if markdown.trim().is_empty() {
+                payload.to_string()
+            } else {
+                markdown
+            },
+            raw: payload.clone(),
+            meta,
+            mentions: Vec::new(),
+        })
+    }
+
+    fn uem_to_egress(&self, _event: &UemEvent) -> Result<OutgoingPayload, AdapterError> {
+        Err(AdapterError {
+            message: "mappings are ingress only".to_string(),
+        })
+    }
+}
+
+/// Checks a mapping's name, JSONPath expressions and template before it is saved.
+pub fn validate_mapping(mapping: &Mapping) -> Result<(), String> {
+    let name = &mapping.name;
+    let valid = !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'_' || byte == b'-');
+    if !valid {
+        return Err(format!(
+            "invalid mapping name {:?}: use lowercase letters, digits, `_` and `-`",
+            name
+        ));
+    }
+    if name == "mapped" || ingress_platform(name).is_some() {
+        return Err(format!("mapping name {} is taken by a platform", name));
+    }
+
+    let paths = [
+        ("id_path", &mapping.id_path),
+        ("title_path", &mapping.title_path),
+        ("timestamp_path", &mapping.timestamp_path),
+    ];
+    for (field, path) in paths {
+        if let Some(path) = path {
+            JsonPath::parse(path).map_err(|err| format!("invalid {}: {}", field, err))?;
+        }
+    }
+    for (field, path) in &mapping.meta {
+        JsonPath::parse(path).map_err(|err| format!("invalid meta.{} path: {}", field, err))?;
+    }
+    environment()
+        .template_from_str(&mapping.template)
+        .map_err(|err| format!("invalid template: {}", err))?;
+    Ok(())
+}
+
+/// Fails when a platform has the name of a saved mapping, e.g. from a plugin
+/// loaded after the mapping was created: ingress would never reach the
+/// mapping.
+pub fn check_mappings_reachable(mappings: &[Mapping]) -> Result<(), String> {
+    match mappings.iter().find(|mapping| ingress_platform(&mapping.name).is_some()) {
+        Some(mapping) => Err(format!(
+            "platform {} shadows the mapping of the same name; rename the mapping or remove the plugin",
+            mapping.name
+        )),
+        None => Ok(()),
+    }
+}
+
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_fuel(Some(TEMPLATE_FUEL));
+    env
+}
+
+/// The value at `path`: `None` when nothing matches, an array when several
+/// nodes do.
+fn select(path: &str, payload: &Value) -> Result<Option<Value>, AdapterError> {
+    let path = JsonPath::parse(path).map_err(|err| AdapterError {
+        message: format!("invalid JSONPath {}: {}", path, err),
+    })?;
+    let nodes = path.query(payload).all();
+    Ok(match nodes.as_slice() {
+        [] => None,
+        [value] => Some((*value).clone()),
+        values => Some(Value::Array(values.iter().map(|value| (*value).clone()).collect())),
+    })
+}
+
+fn scalar_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
+
+/// Unix seconds from a number or numeric string; 13-digit values are
+/// milliseconds.
+fn timestamp_seconds(value: &Value) -> Option<i64> {
+    let timestamp = value
+        .as_i64()
+        .or_else(|| value.as_f64().map(|seconds| seconds as i64))
+        .or_else(|| value.as_str().and_then(|raw| raw.trim().parse::<i64>().ok()))?;
+    Some(if timestamp > 999_999_999_999 {
+        timestamp / 1000
+    } else {
+        timestamp
+    })
+}
+
+fn now_timestamp() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use insta::assert_yaml_snapshot;
+
+    fn mapping(template: &str) -> Mapping {
+        Mapping {
+            id: "m1".to_string(),
+            name: "statuspage".to_string(),
+            id_path: Some("$.incident.id".to_string()),
+            title_path: Some("$.incident.name".to_string()),
+            timestamp_path: Some("$.incident.updated_at_ms".to_string()),
+            meta: BTreeMap::from([
+                ("impact".to_string(), "$.incident.impact".to_string()),
+                ("components".to_string(), "$.incident.components[*].name".to_string()),
+                ("missing".to_string(), "$.nope".to_string()),
+            ]),
+            template: template.to_string(),
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn mapped_ingress_to_uem() {
+        let payload = json!({
+            "incident": {
+                "id": 4711,
+                "name": "Elevated API errors",
+                "impact": "major",
+                "updated_at_ms": 1_700_000_000_123_i64,
+                "components": [{"name": "API"}, {"name": "Dashboard"}],
+                "updates": [
+                    {"status": "investigating", "body": "We are looking into it."},
+                    {"status": "identified", "body": "A bad deploy; rolling back."}
+                ]
+            }
+        });
+        let template = "**{{ title }}** ({{ meta.impact | upper }})\n\n\
+            {% for update in payload.incident.updates %}- {{ update.status }}: {{ update.body }}\n{% endfor %}\n\
+            Affects {{ meta.components | join(\", \") }}";
+        let event = MappedAdapter::new(mapping(template)).ingress_to_uem(&payload).expect("uem");
+        assert_yaml_snapshot!(
+            "adapters_mapped_ingress",
+            json!({
+                "id": event.id,
+                "source": event.source,
+                "timestamp": event.timestamp,
+                "title": event.title,
+                "markdown": event.markdown,
+                "meta": event.meta,
+            })
+        );
+
+        let event = MappedAdapter::new(mapping("")).ingress_to_uem(&json!({"other": true})).expect("uem");
+        assert_eq!(event.markdown, r#"{"other":true}"#);
+        assert_eq!(event.title, None);
+        assert!(!event.id.is_empty());
+
+        let err = MappedAdapter::new(mapping("{% for x in range(1000000) %}.{% endfor %}"))
+            .ingress_to_uem(&payload)
+            .unwrap_err();
+        assert!(err.message.starts_with("mapping statuspage: "), "{}", err.message);
+    }
+
+    #[test]
+    fn validates_mappings() {
+        assert!(validate_mapping(&mapping("{{ title }}")).is_ok());
+
+        let mut invalid = mapping("{{ title }}");
+        invalid.name = "lark".to_string();
+        assert_eq!(validate_mapping(&invalid).unwrap_err(), "mapping name lark is taken by a platform");
+        invalid.name = "Status Page".to_string();
+        assert!(validate_mapping(&invalid).unwrap_err().starts_with("invalid mapping name"));
+
+        // Saved before a platform of the same name existed.
+        let mut shadowed = mapping("{{ title }}");
+        shadowed.name = "lark".to_string();
+        assert!(check_mappings_reachable(&[mapping("{{ title }}")]).is_ok());
+        let err = check_mappings_reachable(&[mapping("{{ title }}"), shadowed]).unwrap_err();
+        assert!(err.starts_with("platform lark shadows the mapping"), "{}", err);
+
+        let mut invalid = mapping("{{ title }}");
+        invalid.title_path = Some("incident.name".to_string());
+        assert!(validate_mapping(&invalid).unwrap_err().starts_with("invalid title_path: "));
+
+        let mut invalid = mapping("{% if %}");
+        invalid.meta.clear();
+        assert!(validate_mapping(&invalid).unwrap_err().starts_with("invalid template: "));
+    }
+}
@@ -1,27 +1,196 @@
+mod alertmanager;
+mod callback;
+mod detect;
 mod dingtalk;
 mod http;
 mod lark;
+mod mapped;
+pub mod plugin;
 mod slack;
 mod wecom;
 
+use std::collections::HashMap;
+
+use axum::http::HeaderMap;
 use serde_json::Value;
 
+pub use alertmanager::AlertmanagerAdapter;
+pub use detect::detect_platforms;
 pub use dingtalk::DingTalkAdapter;
 pub use http::HttpAdapter;
 pub use lark::LarkAdapter;
+pub use mapped::{check_mappings_reachable, validate_mapping, MappedAdapter};
 pub use slack::SlackAdapter;
 pub use wecom::WecomAdapter;
 
+use crate::document::Renderer;
 use crate::models::{OutgoingPayload, UemEvent};
+use crate::utils::body::IngressBody;
 
 #[derive(Debug)]
 pub struct AdapterError {
     pub message: String,
 }
 
+/// An ingress request as seen by an adapter's callback hooks, with this
+/// platform's part of the endpoint's `ingress_config`.
+pub struct Callback<'a> {
+    pub headers: &'a HeaderMap,
+    pub query: &'a HashMap<String, String>,
+    pub settings: Option<&'a Value>,
+}
+
+impl Callback<'_> {
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query.get(name).map(String::as_str)
+    }
+}
+
+/// A response body a platform expects instead of the default `{}`.
+#[derive(Debug, PartialEq)]
+pub struct CallbackReply {
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+/// How an opened callback is processed and answered.
+#[derive(Debug, PartialEq)]
+pub enum CallbackAnswer {
+    /// Process the event and answer `{}`.
+    Event,
+    /// Process the event and answer with this body.
+    EventReply(CallbackReply),
+    /// A handshake: answer with this body without creating an event.
+    Handshake(CallbackReply),
+}
+
+/// Binary content carried inside an ingress payload rather than behind a URL,
+/// shown in the UEM markdown as `placeholder` until it is relayed.
+#[derive(Debug)]
+pub struct InlineMedia {
+    pub placeholder: String,
+    pub data: Vec<u8>,
+}
+
+/// How a destination measures its maximum message size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LimitUnit {
+    Chars,
+    Bytes,
+}
+
+/// The largest message a destination accepts.
+#[derive(Debug, Clone, Copy)]
+pub struct MessageLimit {
+    pub max: usize,
+    pub unit: LimitUnit,
+}
+
+impl MessageLimit {
+    pub const fn chars(max: usize) -> Self {
+        Self { max, unit: LimitUnit::Chars }
+    }
+
+    pub const fn bytes(max: usize) -> Self {
+        Self { max, unit: LimitUnit::Bytes }
+    }
+
+    pub fn measure(&self, text: &str) -> usize {
+        match self.unit {
+            LimitUnit::Chars => text.chars().count(),
+            LimitUnit::Bytes => text.len(),
+        }
+    }
+}
+
 pub trait WebhookAdapter: Send + Sync {
     fn ingress_to_uem(&self, payload: &Value) -> Result<UemEvent, AdapterError>;
     fn uem_to_egress(&self, event: &UemEvent) -> Result<OutgoingPayload, AdapterError>;
+
+    /// Verifies and, if encrypted, decrypts a callback in place before the
+    /// challenge check.
+    fn open_callback(
+        &self,
+        _callback: &Callback<'_>,
+        _body: &mut IngressBody,
+    ) -> Result<CallbackAnswer, AdapterError> {
+        Ok(CallbackAnswer::Event)
+    }
+
+    /// Answers a `GET` URL verification request with the text to echo back;
+    /// `None` when the platform doesn't verify URLs this way.
+    fn verify_callback_url(&self, _callback: &Callback<'_>) -> Option<Result<String, AdapterError>> {
+        None
+    }
+
+    /// Converts a decoded ingress request. Adapters that need the bytes as
+    /// received (e.g. to check a signature) override this.
+    fn ingress_body_to_uem(&self, body: &IngressBody) -> Result<UemEvent, AdapterError> {
+        self.ingress_to_uem(&body.value)
+    }
+
+    /// Returns media embedded in `payload` (e.g. base64 images) for the relay.
+    fn inline_media(&self, _payload: &Value) -> Vec<InlineMedia> {
+        Vec::new()
+    }
+
+    /// The document renderer behind `uem_to_egress`, letting dispatch reuse
+    /// one parsed document per event.
+    fn renderer(&self) -> Option<&dyn Renderer> {
+        None
+    }
+
+    /// The destination's message size limit; longer events are split.
+    fn message_limit(&self) -> Option<MessageLimit> {
+        None
+    }
+}
+
+/// The string at `key` in a JSON object, or `""` when missing or not a string.
+fn str_field<'a>(value: &'a Value, key: &str) -> &'a str {
+    value.get(key).and_then(|value| value.as_str()).unwrap_or("")
+}
+
+/// Maps platform aliases to the canonical name used for mention handles.
+pub fn canonical_platform(platform: &str) -> Option<&'static str> {
+    match platform {
+        "slack" => Some("slack"),
+        "lark" | "feishu" => Some("lark"),
+        "dingtalk" | "ding" => Some("dingtalk"),
+        "wecom" | "wechat_work" => Some("wecom"),
+        _ => None,
+    }
+}
+
+/// Canonical names of the built-in platforms accepted at ingress; plugins
+/// add their own. `mapped` covers every mapping from `/api/mappings`.
+pub const INGRESS_PLATFORMS: &[&str] = &["slack", "lark", "dingtalk", "wecom", "alertmanager", "mapped", "custom"];
+
+/// The canonical ingress name for a platform path segment, e.g. `feishu` →
+/// `lark`. Unlike [`canonical_platform`], this includes platforms without
+/// mention syntax, and plugin platforms.
+pub fn ingress_platform(platform: &str) -> Option<&'static str> {
+    canonical_platform(platform).or(match platform {
+        "alertmanager" | "prometheus" => Some("alertmanager"),
+        "http" | "custom" => Some("custom"),
+        _ => plugin::ingress_adapter(platform).map(|adapter| adapter.name()),
+    })
+}
+
+/// Checks an endpoint's `allowed_platforms`, which must use canonical names.
+pub fn validate_allowed_platforms(platforms: &[String]) -> Result<(), String> {
+    for platform in platforms {
+        let known = INGRESS_PLATFORMS.contains(&platform.as_str()) || plugin::platforms().any(|name| name == platform);
+        if !known {
+            let known = INGRESS_PLATFORMS.iter().copied().chain(plugin::platforms()).collect::<Vec<_>>();
+            return Err(format!(
+                "unknown platform {:?} in allowed_platforms (expected one of {})",
+                platform,
+                known.join(", ")
+            ));
+        }
+    }
+    Ok(())
 }
 
 pub fn ingress_adapter(platform: &str) -> Option<Box<dyn WebhookAdapter>> {
@@ -30,18 +199,19 @@ pub fn ingress_adapter(platform: &str) -> Option<Box<dyn WebhookAdapter>> {
         "lark" | "feishu" => Some(Box::new(LarkAdapter)),
         "dingtalk" | "ding" => Some(Box::new(DingTalkAdapter)),
         "wecom" | "wechat_work" => Some(Box::new(WecomAdapter)),
+        "alertmanager" | "prometheus" => Some(Box::new(AlertmanagerAdapter)),
         "http" | "custom" => Some(Box::new(HttpAdapter)),
-        _ => None,
+        _ => plugin::ingress_adapter(platform).map(|adapter| Box::new(adapter) as Box<dyn WebhookAdapter>),
     }
 }
 
 pub fn egress_adapter(kind: &str) -> Option<Box<dyn WebhookAdapter>> {
     match kind {
-        "slack" => Some(Box::new(SlackAdapter)),
+        "slack" | "slack_api" => Some(Box::new(SlackAdapter)),
         "lark" | "feishu" => Some(Box::new(LarkAdapter)),
         "dingtalk" | "ding" => Some(Box::new(DingTalkAdapter)),
         "wecom" | "wechat_work" => Some(Box::new(WecomAdapter)),
         "http" | "custom" => Some(Box::new(HttpAdapter)),
-        _ => None,
+        _ => plugin::egress_adapter(kind).map(|adapter| Box::new(adapter) as Box<dyn WebhookAdapter>),
     }
 }
@@ -0,0 +1,444 @@
+//! WebAssembly plugin adapters, loaded from `--plugins-dir` at startup.
+//!
+//! A plugin is a core WebAssembly module without imports. It exports its
+//! `memory` and:
+//!
+//! - `alloc(len: i32) -> i32`: reserves `len` bytes for the host to write input to.
+//! - `manifest() -> i64`: `{"platforms": [...], "kinds": [...]}`, the ingress
+//!   platforms and egress target kinds the plugin handles.
+//! - `ingress_to_uem(ptr: i32, len: i32) -> i64`, given
+//!   `{"platform": ..., "payload": ...}`, returns a UEM event (`markdown` and
+//!   optionally `id`, `title`, `timestamp`, `meta`, `mentions`).
+//! - `uem_to_egress(ptr: i32, len: i32) -> i64`, given `{"kind": ..., "event": ...}`,
+//!   returns `{"body": ...}`, the JSON posted to the target URL.
+//!
+//! Results are UTF-8 JSON at `ptr << 32 | len`; the conversions wrap theirs
+//! in `{"ok": ...}` or `{"error": "message"}`. Every call runs in a fresh
+//! instance with bounded fuel and memory, so plugins keep no state between
+//! calls and can't reach the host.
+
+use std::path::Path;
+use std::sync::{LazyLock, OnceLock};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use wasmtime::{Config, Engine, InstancePre, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap};
+
+use crate::adapters::{AdapterError, WebhookAdapter};
+use crate::models::{Mention, OutgoingPayload, UemEvent};
+
+/// Fuel (roughly, wasm instructions) one plugin call may use.
+const FUEL_PER_CALL: u64 = 100_000_000;
+const MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+const MAX_OUTPUT_BYTES: usize = 4 * 1024 * 1024;
+
+static ENGINE: LazyLock<Engine> = LazyLock::new(|| {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    Engine::new(&config).expect("wasm engine config is valid")
+});
+
+static PLUGINS: OnceLock<Vec<Plugin>> = OnceLock::new();
+
+#[derive(Debug)]
+pub struct PluginError {
+    pub message: String,
+}
+
+impl std::fmt::Display for PluginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub platforms: Vec<String>,
+    #[serde(default)]
+    pub kinds: Vec<String>,
+}
+
+pub struct Plugin {
+    pub name: String,
+    pub manifest: Manifest,
+    instance_pre: InstancePre<StoreLimits>,
+}
+
+impl Plugin {
+    /// Compiles a plugin and reads its manifest. `name` (the file stem) is
+    /// only used in messages.
+    pub fn new(name: &str, wasm: &[u8]) -> Result<Self, PluginError> {
+        let error = |message: String| PluginError {
+            message: format!("plugin {}: {}", name, message),
+        };
+        let module = Module::new(&ENGINE, wasm).map_err(|err| error(err.to_string()))?;
+        if let Some(import) = module.imports().next() {
+            return Err(error(format!("imports {}::{}; plugins can't import anything", import.module(), import.name())));
+        }
+        let instance_pre = Linker::new(&ENGINE)
+            .instantiate_pre(&module)
+            .map_err(|err| error(err.to_string()))?;
+        let mut plugin = Self {
+            name: name.to_string(),
+            manifest: Manifest::default(),
+            instance_pre,
+        };
+
+        let manifest = plugin
+            .call("manifest", None)
+            .map_err(|err| PluginError { message: err.message })?;
+        plugin.manifest = serde_json::from_slice(&manifest).map_err(|err| error(format!("invalid manifest: {}", err)))?;
+        for name in plugin.manifest.platforms.iter().chain(&plugin.manifest.kinds) {
+            let valid = !name.is_empty()
+                && name.bytes().all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'_');
+            if !valid {
+                return Err(error(format!("invalid platform or kind name {:?}", name)));
+            }
+        }
+        Ok(plugin)
+    }
+
+    /// Calls `export` in a fresh instance, passing `input` through `alloc`,
+    /// and returns the bytes it points at.
+    fn call(&self, export: &str, input: Option<&[u8]>) -> Result<Vec<u8>, AdapterError> {
+        let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY_BYTES).instances(1).build();
+        let mut store = Store::new(&ENGINE, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL_PER_CALL).map_err(|err| self.wasm_error(err))?;
+        let instance = self.instance_pre.instantiate(&mut store).map_err(|err| self.wasm_error(err))?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| self.error("does not export memory"))?;
+
+        let packed = match input {
+            None => instance
+                .get_typed_func::<(), i64>(&mut store, export)
+                .and_then(|func| func.call(&mut store, ()))
+                .map_err(|err| self.wasm_error(err))?,
+            Some(input) => {
+                let len = i32::try_from(input.len()).map_err(|_| self.error("input too large"))?;
+                let ptr = instance
+                    .get_typed_func::<i32, i32>(&mut store, "alloc")
+                    .and_then(|alloc| alloc.call(&mut store, len))
+                    .map_err(|err| self.wasm_error(err))?;
+                memory
+                    .write(&mut store, ptr as u32 as usize, input)
+                    .map_err(|err| self.error(err))?;
+                instance
+                    .get_typed_func::<(i32, i32), i64>(&mut store, export)
+                    .and_then(|func| func.call(&mut store, (ptr, len)))
+                    .map_err(|err| self.wasm_error(err))?
+            }
+        };
+
+        let ptr = (packed as u64 >> 32) as usize;
+        let len = (packed as u64 & 0xffff_ffff) as usize;
+        if len > MAX_OUTPUT_BYTES {
+            return Err(self.error(format!("{} returned {} bytes", export, len)));
+        }
+        memory
+            .data(&store)
+            .get(ptr..ptr + len)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| self.error(format!("{} returned a result outside its memory", export)))
+    }
+
+    /// Calls a conversion export with JSON input and unwraps its `{"ok": ...}`
+    /// or `{"error": ...}` result.
+    fn convert(&self, export: &str, input: &Value) -> Result<Value, AdapterError> {
+        let output = self.call(export, Some(input.to_string().as_bytes()))?;
+        let mut output: Value = serde_json::from_slice(&output)
+            .map_err(|err| self.error(format!("{} returned invalid JSON: {}", export, err)))?;
+        if let Some(message) = output.get("error") {
+            return Err(AdapterError {
+                message: message.as_str().map(String::from).unwrap_or_else(|| message.to_string()),
+            });
+        }
+        match output.get_mut("ok") {
+            Some(value) => Ok(value.take()),
+            None => Err(self.error(format!("{} returned neither ok nor error", export))),
+        }
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> AdapterError {
+        AdapterError {
+            message: format!("plugin {}: {}", self.name, message),
+        }
+    }
+
+    fn wasm_error(&self, err: wasmtime::Error) -> AdapterError {
+        match err.downcast_ref::<Trap>() {
+            Some(Trap::OutOfFuel) => self.error("ran out of fuel"),
+            _ => self.error(format!("{:#}", err)),
+        }
+    }
+}
+
+/// Loads every `*.wasm` file in `dir` and registers its platforms and kinds.
+/// Names must not clash with built-in adapters or other plugins. Returns the
+/// loaded plugins' names; calling this twice is an error.
+pub fn load_dir(dir: &Path) -> Result<Vec<String>, PluginError> {
+    let io_error = |err: std::io::Error| PluginError {
+        message: format!("{}: {}", dir.display(), err),
+    };
+    let mut paths = std::fs::read_dir(dir)
+        .map_err(io_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io_error)?;
+    paths.retain(|path| path.extension().is_some_and(|extension| extension == "wasm"));
+    paths.sort();
+
+    let mut plugins = Vec::new();
+    for path in paths {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let wasm = std::fs::read(&path).map_err(io_error)?;
+        plugins.push(Plugin::new(&name, &wasm)?);
+    }
+    check_names(&plugins)?;
+
+    let names = plugins.iter().map(|plugin| plugin.name.clone()).collect();
+    PLUGINS.set(plugins).map_err(|_| PluginError {
+        message: "plugins are already loaded".to_string(),
+    })?;
+    Ok(names)
+}
+
+fn check_names(plugins: &[Plugin]) -> Result<(), PluginError> {
+    let mut seen = std::collections::HashSet::new();
+    for plugin in plugins {
+        let manifest = &plugin.manifest;
+        let builtin_platform =
+            |name: &str| super::INGRESS_PLATFORMS.contains(&name) || super::ingress_platform(name).is_some();
+        let names = manifest.platforms.iter().map(|name| ("platform", name, builtin_platform(name)));
+        let kinds = manifest.kinds.iter().map(|name| ("kind", name, super::egress_adapter(name).is_some()));
+        for (what, name, builtin) in names.chain(kinds) {
+            if builtin || !seen.insert((what, name)) {
+                return Err(PluginError {
+                    message: format!("plugin {}: {} {} is already registered", plugin.name, what, name),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn plugins() -> &'static [Plugin] {
+    PLUGINS.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+/// Ingress platforms registered by plugins.
+pub fn platforms() -> impl Iterator<Item = &'static str> {
+    plugins()
+        .iter()
+        .flat_map(|plugin| &plugin.manifest.platforms)
+        .map(String::as_str)
+}
+
+pub fn ingress_adapter(platform: &str) -> Option<PluginAdapter> {
+    plugins().iter().find_map(|plugin| {
+        let name = plugin.manifest.platforms.iter().find(|name| *name == platform)?;
+        Some(PluginAdapter { plugin, name })
+    })
+}
+
+pub fn egress_adapter(kind: &str) -> Option<PluginAdapter> {
+    plugins().iter().find_map(|plugin| {
+        let name = plugin.manifest.kinds.iter().find(|name| *name == kind)?;
+        Some(PluginAdapter { plugin, name })
+    })
+}
+
+/// One platform or kind of a loaded plugin.
+#[derive(Clone, Copy)]
+pub struct PluginAdapter {
+    plugin: &'static Plugin,
+    name: &'static str,
+}
+
+impl PluginAdapter {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// [`WebhookAdapter::ingress_to_uem`] on a blocking thread: a call may
+    /// run until its fuel is used up, which must not stall the runtime.
+    pub async fn ingress_to_uem_blocking(self, payload: Value) -> Result<UemEvent, AdapterError> {
+        run_blocking(move || self.ingress_to_uem(&payload)).await
+    }
+
+    /// [`WebhookAdapter::uem_to_egress`] on a blocking thread.
+    pub async fn uem_to_egress_blocking(self, event: UemEvent) -> Result<OutgoingPayload, AdapterError> {
+        run_blocking(move || self.uem_to_egress(&event)).await
+    }
+}
+
+async fn run_blocking<T: Send + 'static>(
+    call: impl FnOnce() -> Result<T, AdapterError> + Send + 'static,
+) -> Result<T, AdapterError> {
+    tokio::task::spawn_blocking(call).await.unwrap_or_else(|err| {
+        Err(AdapterError {
+            message: format!("plugin task failed: {}", err),
+        })
+    })
+}
+
+impl WebhookAdapter for PluginAdapter {
+    fn ingress_to_uem(&self, payload: &Value) -> Result<UemEvent, AdapterError> {
+        plugin_ingress_to_uem(self.plugin, self.name, payload)
+    }
+
+    fn uem_to_egress(&self, event: &UemEvent) -> Result<OutgoingPayload, AdapterError> {
+        plugin_uem_to_egress(self.plugin, self.name, event)
+    }
+}
+
+#[derive(Deserialize)]
+struct PluginEvent {
+    #[serde(default)]
+    id: String,
+    title: Option<String>,
+    markdown: String,
+    timestamp: Option<i64>,
+    #[serde(default)]
+    meta: Value,
+    #[serde(default)]
+    mentions: Vec<PluginMention>,
+}
+
+#[derive(Deserialize)]
+struct PluginMention {
+    platform: String,
+    handle: String,
+    name: Option<String>,
+}
+
+fn plugin_ingress_to_uem(plugin: &Plugin, platform: &str, payload: &Value) -> Result<UemEvent, AdapterError> {
+    let output = plugin.convert("ingress_to_uem", &json!({ "platform": platform, "payload": payload }))?;
+    let event: PluginEvent = serde_json::from_value(output)
+        .map_err(|err| plugin.error(format!("ingress_to_uem returned an invalid event: {}", err)))?;
+    Ok(UemEvent {
+        id: event.id,
+        source: platform.to_string(),
+        timestamp: event.timestamp.unwrap_or_else(now_timestamp),
+        title: event.title,
+        markdown: event.markdown,
+        raw: payload.clone(),
+        meta: if event.meta.is_null() { json!({}) } else { event.meta },
+        mentions: event
+            .mentions
+            .into_iter()
+            .map(|mention| Mention::new(&mention.platform, &mention.handle, mention.name))
+            .collect(),
+    })
+}
+
+fn plugin_uem_to_egress(plugin: &Plugin, kind: &str, event: &UemEvent) -> Result<OutgoingPayload, AdapterError> {
+    let event = json!({
+        "id": event.id,
+        "source": event.source,
+        "timestamp": event.timestamp,
+        "title": event.title,
+        "markdown": event.markdown,
+        "meta": event.meta,
+        "raw": event.raw,
+        "mentions": event.mentions,
+    });
+    let mut output = plugin.convert("uem_to_egress", &json!({ "kind": kind, "event": event }))?;
+    let body = output
+        .get_mut("body")
+        .map(Value::take)
+        .ok_or_else(|| plugin.error("uem_to_egress returned no body"))?;
+    Ok(OutgoingPayload {
+        body,
+        content_type: "application/json",
+    })
+}
+
+fn now_timestamp() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers with fixed results laid out in its data segments; `uem_to_egress`
+    /// for the `spin` kind never returns.
+    const ACME: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 4096))
+          (data (i32.const 0) "{\"platforms\":[\"acme\"],\"kinds\":[\"acme\"]}")
+          (data (i32.const 1024) "{\"ok\":{\"title\":\"Acme\",\"markdown\":\"from <@slack:U1>\",\"timestamp\":7,\"mentions\":[{\"platform\":\"slack\",\"handle\":\"U1\"}]}}")
+          (data (i32.const 2048) "{\"error\":\"acme targets are read-only\"}")
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "manifest") (result i64)
+            (i64.const 39))
+          (func (export "ingress_to_uem") (param i32 i32) (result i64)
+            (i64.or (i64.shl (i64.const 1024) (i64.const 32)) (i64.const 115)))
+          (func (export "uem_to_egress") (param i32 i32) (result i64)
+            (loop $spin (br $spin))
+            (i64.or (i64.shl (i64.const 2048) (i64.const 32)) (i64.const 38))))
+    "#;
+
+    #[test]
+    fn plugins_convert_through_their_exports() {
+        let plugin = Plugin::new("acme", ACME.as_bytes()).expect("plugin");
+        assert_eq!(plugin.manifest.platforms, ["acme"]);
+        assert_eq!(plugin.manifest.kinds, ["acme"]);
+
+        let payload = json!({"incident": 42});
+        let event = plugin_ingress_to_uem(&plugin, "acme", &payload).expect("uem");
+        assert_eq!(event.source, "acme");
+        assert_eq!(event.title.as_deref(), Some("Acme"));
+        assert_eq!(event.markdown, "from <@slack:U1>");
+        assert_eq!(event.timestamp, 7);
+        assert_eq!(event.raw, payload);
+        assert_eq!(event.mentions[0].handle, "U1");
+
+        let err = plugin_uem_to_egress(&plugin, "acme", &event).unwrap_err();
+        assert_eq!(err.message, "plugin acme: ran out of fuel");
+    }
+
+    #[tokio::test]
+    async fn plugin_adapters_convert_on_blocking_threads() {
+        let plugin = Box::leak(Box::new(Plugin::new("acme", ACME.as_bytes()).expect("plugin")));
+        let adapter = PluginAdapter { plugin, name: "acme" };
+        let event = adapter.ingress_to_uem_blocking(json!({})).await.expect("uem");
+        assert_eq!(event.markdown, "from <@slack:U1>");
+        let err = adapter.uem_to_egress_blocking(event).await.unwrap_err();
+        assert_eq!(err.message, "plugin acme: ran out of fuel");
+    }
+
+    #[test]
+    fn plugins_are_checked_when_loaded() {
+        let err = Plugin::new("io", br#"(module (import "wasi" "fd_write" (func)))"#).err().unwrap();
+        assert_eq!(err.message, "plugin io: imports wasi::fd_write; plugins can't import anything");
+
+        let err = Plugin::new("bad", b"not wasm").err().unwrap();
+        assert!(err.message.starts_with("plugin bad: "), "{}", err.message);
+
+        let shadowing = ACME.replace(r#"[\"acme\"],\"kinds\""#, r#"[\"lark\"],\"kinds\""#);
+        let plugin = Plugin::new("shadow", shadowing.as_bytes()).expect("plugin");
+        let err = check_names(&[plugin]).unwrap_err();
+        assert_eq!(err.message, "plugin shadow: platform lark is already registered");
+
+        let mapped = ACME
+            .replace(r#"[\"acme\"],\"kinds\""#, r#"[\"mapped\"],\"kinds\""#)
+            .replace("(i64.const 39)", "(i64.const 41)");
+        let plugin = Plugin::new("mapped", mapped.as_bytes()).expect("plugin");
+        let err = check_names(&[plugin]).unwrap_err();
+        assert_eq!(err.message, "plugin mapped: platform mapped is already registered");
+    }
+}
@@ -1,82 +1,170 @@
-use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
 use serde_json::{json, Value};
 use uuid::Uuid;
 
-use crate::adapters::{AdapterError, WebhookAdapter};
-use crate::models::{OutgoingPayload, UemEvent};
-
-/// Converts Standard Markdown to Slack's 'mrkdwn' format.
-fn markdown_to_slack(markdown: &str) -> String {
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_TABLES); 
-    
-    let parser = Parser::new_ext(markdown, options);
-    let mut slack_text = String::new();
-
-    for event in parser {
-        match event {
-            Event::Start(tag) => match tag {
-                Tag::Paragraph => {},
-                Tag::Heading { .. } => slack_text.push('*'),
-                Tag::BlockQuote(_) => slack_text.push_str("> "),
-                Tag::CodeBlock(_) => {
-                    slack_text.push_str("```\n");
-                }
-                Tag::List(Some(_)) => {}, // Ordered list
-                Tag::List(None) => {},    // Unordered list
-                Tag::Item => slack_text.push_str("• "),
-                Tag::Emphasis => slack_text.push('_'),
-                Tag::Strong => slack_text.push('*'),
-                Tag::Strikethrough => slack_text.push('~'),
-                Tag::Link { link_type: _, dest_url, title: _, id: _ } => {
-                   slack_text.push('<');
-                   slack_text.push_str(&dest_url);
-                   slack_text.push('|');
-                },
-                Tag::Image { link_type: _, dest_url, title: _, id: _ } => {
-                     slack_text.push('<');
-                     slack_text.push_str(&dest_url);
-                     slack_text.push('|');
+use crate::adapters::{AdapterError, MessageLimit, WebhookAdapter};
+use crate::utils::body::IngressBody;
+use crate::models::{Mention, OutgoingPayload, UemEvent};
+use crate::document::{plain_text_for, render_event, Block, Capabilities, Document, Inline, Renderer, TableSupport};
+use crate::utils::mention::{lookup_mention, mention_token, split_mention_tokens, Segment};
+
+/// Renders a document as Slack 'mrkdwn'.
+fn document_to_mrkdwn(doc: &Document, mentions: &[Mention]) -> String {
+    Mrkdwn { mentions }.blocks(&doc.blocks, 0).trim().to_string()
+}
+
+/// Bullets by nesting depth; mrkdwn has no list syntax, so lists are text.
+const BULLETS: [&str; 3] = ["•", "◦", "▪"];
+
+/// Slack only applies `*`, `_` and `~` at word boundaries, so a zero-width
+/// space separates markers from adjacent word characters.
+const ZERO_WIDTH_SPACE: char = '\u{200B}';
+
+struct Mrkdwn<'a> {
+    mentions: &'a [Mention],
+}
+
+impl Mrkdwn<'_> {
+    fn blocks(&self, blocks: &[Block], depth: usize) -> String {
+        blocks
+            .iter()
+            .map(|block| self.block(block, depth))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn block(&self, block: &Block, depth: usize) -> String {
+        match block {
+            Block::Heading { inlines, .. } => {
+                let mut line = Line::default();
+                line.styled("*", |line| self.inlines(line, inlines, true));
+                line.output
+            }
+            Block::Paragraph(inlines) => {
+                let mut line = Line::default();
+                self.inlines(&mut line, inlines, false);
+                line.output
+            }
+            Block::Quote(blocks) => self
+                .blocks(blocks, depth)
+                .lines()
+                .map(|line| if line.is_empty() { ">".to_string() } else { format!("> {}", line) })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Block::List { start, items } => items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| {
+                    let marker = match start {
+                        Some(start) => format!("{}.", start + index as u64),
+                        None => BULLETS[depth.min(BULLETS.len() - 1)].to_string(),
+                    };
+                    let indent = " ".repeat(marker.chars().count() + 1);
+                    let content = self.blocks(item, depth + 1);
+                    let mut lines = content.lines();
+                    let mut output = format!("{} {}", marker, lines.next().unwrap_or(""));
+                    for line in lines {
+                        output.push('\n');
+                        if !line.is_empty() {
+                            output.push_str(&indent);
+                            output.push_str(line);
+                        }
+                    }
+                    output
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Block::Code { text, .. } => format!("```\n{}\n```", escape_mrkdwn(text.trim_end_matches('\n'))),
+            // Tables are rewritten by the capability fallbacks before rendering.
+            Block::Table { .. } => String::new(),
+            Block::Rule => "---".to_string(),
+            Block::Html(html) => escape_mrkdwn(html.trim_end()),
+        }
+    }
+
+    /// Writes `inlines`; inside a bold span `bold` drops nested strong markers.
+    fn inlines(&self, line: &mut Line, inlines: &[Inline], bold: bool) {
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) | Inline::Html(text) => line.text(&escape_mrkdwn(text)),
+                Inline::Code(code) => line.styled("`", |line| line.text(&escape_mrkdwn(code))),
+                Inline::Emphasis(children) => line.styled("_", |line| self.inlines(line, children, bold)),
+                Inline::Strong(children) if bold => self.inlines(line, children, bold),
+                Inline::Strong(children) => line.styled("*", |line| self.inlines(line, children, true)),
+                Inline::Strikethrough(children) => {
+                    line.styled("~", |line| self.inlines(line, children, bold))
                 }
-                 _ => {}
-            },
-            Event::End(tag) => match tag {
-                TagEnd::Paragraph => slack_text.push('\n'),
-                TagEnd::Heading(_) => slack_text.push_str("*\n"),
-                TagEnd::BlockQuote(_) => slack_text.push('\n'),
-                TagEnd::CodeBlock => {
-                    slack_text.push_str("\n```\n");
+                Inline::Link { url, children } => line.text(&format!(
+                    "<{}|{}>",
+                    escape_mrkdwn(url),
+                    escape_mrkdwn(&plain_text_for(children, self.mentions, "slack"))
+                )),
+                // mrkdwn text can't show images; the capability fallbacks
+                // turn them into links before rendering.
+                Inline::Image { .. } => {}
+                Inline::Mention { platform, handle } => {
+                    match lookup_mention(self.mentions, platform, handle, "slack") {
+                        (Some(user), _) => line.text(&format!("<@{}>", user)),
+                        (None, fallback) => line.text(&format!("@{}", escape_mrkdwn(fallback))),
+                    }
                 }
-                TagEnd::List(_) => {},
-                TagEnd::Item => slack_text.push('\n'),
-                TagEnd::Emphasis => slack_text.push('_'),
-                TagEnd::Strong => slack_text.push('*'),
-                TagEnd::Strikethrough => slack_text.push('~'),
-                TagEnd::Link => {
-                     slack_text.push('>');
-                },
-                TagEnd::Image => {
-                    slack_text.push_str("image>");
-                },
-                 _ => {}
-            },
-            Event::Text(text) => {
-                slack_text.push_str(&text);
-            },
-            Event::Code(text) => {
-               slack_text.push('`');
-               slack_text.push_str(&text);
-               slack_text.push('`');
-            }
-            Event::SoftBreak => slack_text.push('\n'),
-            Event::HardBreak => slack_text.push('\n'),
-            Event::Rule => slack_text.push_str("---\n"),
-            _ => {}
+                Inline::SoftBreak | Inline::LineBreak => line.text("\n"),
+            }
+        }
+    }
+}
+
+/// A line of mrkdwn being written, tracking whether it ends in a closing
+/// style marker.
+#[derive(Default)]
+struct Line {
+    output: String,
+    closed: bool,
+}
+
+impl Line {
+    fn text(&mut self, text: &str) {
+        if self.closed && text.starts_with(is_word_char) {
+            self.output.push(ZERO_WIDTH_SPACE);
+        }
+        self.closed = false;
+        self.output.push_str(text);
+    }
+
+    /// Wraps whatever `write` produces in `marker`, keeping surrounding
+    /// whitespace outside the markers so Slack still recognises them.
+    fn styled(&mut self, marker: &str, write: impl FnOnce(&mut Line)) {
+        let mut inner = Line::default();
+        write(&mut inner);
+        let content = inner.output.trim();
+        if content.is_empty() {
+            self.text(&inner.output);
+            return;
+        }
+        let leading = &inner.output[..inner.output.len() - inner.output.trim_start().len()];
+        let trailing = &inner.output[inner.output.trim_end().len()..];
+        self.text(leading);
+        if self.output.ends_with(is_word_char) {
+            self.output.push(ZERO_WIDTH_SPACE);
+        }
+        self.output.push_str(marker);
+        self.output.push_str(content);
+        self.output.push_str(marker);
+        self.closed = trailing.is_empty();
+        if !trailing.is_empty() {
+            self.text(trailing);
         }
     }
-    
-    slack_text.trim().to_string()
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Escapes the characters Slack reserves for control sequences.
+fn escape_mrkdwn(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
 }
 
 #[derive(Debug)]
@@ -85,7 +173,8 @@ pub struct SlackAdapter;
 impl WebhookAdapter for SlackAdapter {
     fn ingress_to_uem(&self, payload: &Value) -> Result<UemEvent, AdapterError> {
         let event = payload.get("event").unwrap_or(payload);
-        let text = slack_markdown_from_payload(event);
+        let mut mentions = Vec::new();
+        let text = normalize_slack_mentions(&slack_markdown_from_payload(event), &mut mentions);
         let event_id = payload
             .get("event_id")
             .and_then(|value| value.as_str())
@@ -115,16 +204,60 @@ impl WebhookAdapter for SlackAdapter {
             },
             raw: payload.clone(),
             meta,
+            mentions,
         })
     }
 
     fn uem_to_egress(&self, event: &UemEvent) -> Result<OutgoingPayload, AdapterError> {
-        let markdown = if let Some(title) = &event.title {
-            format!("# {}\n\n{}", title, event.markdown)
-        } else {
-            event.markdown.clone()
-        };
-        let mrkdwn = markdown_to_slack(&markdown);
+        render_event(self, event, &Document::parse(&event.markdown))
+    }
+
+    fn ingress_body_to_uem(&self, body: &IngressBody) -> Result<UemEvent, AdapterError> {
+        let mut event = self.ingress_to_uem(&body.value)?;
+        // Slash commands arrive form-encoded with flat `*_id` fields.
+        let command = body.value.get("command").and_then(|value| value.as_str());
+        if let (Some(command), "application/x-www-form-urlencoded") = (command, body.content_type.as_str()) {
+            event.title = Some(command.to_string());
+            event.meta = json!({
+                "channel": body.value.get("channel_id"),
+                "user": body.value.get("user_id"),
+                "thread_ts": null,
+                "type": "slash_command",
+            });
+        }
+        Ok(event)
+    }
+
+    fn renderer(&self) -> Option<&dyn Renderer> {
+        Some(self)
+    }
+
+    fn message_limit(&self) -> Option<MessageLimit> {
+        // Slack truncates message text after 40,000 characters.
+        Some(MessageLimit::chars(40_000))
+    }
+}
+
+impl Renderer for SlackAdapter {
+    fn platform(&self) -> &'static str {
+        "slack"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            tables: TableSupport::Monospace,
+            images: false,
+            code_blocks: true,
+            mentions: true,
+        }
+    }
+
+    fn render(&self, event: &UemEvent, doc: &Document) -> Result<OutgoingPayload, AdapterError> {
+        let mut mrkdwn = document_to_mrkdwn(doc, &event.mentions);
+        if let Some(title) = &event.title {
+            let title = mrkdwn_title(title, &event.mentions);
+            mrkdwn = format!("*{}*\n{}", title, mrkdwn).trim().to_string();
+        }
         Ok(OutgoingPayload {
             body: json!({ "text": mrkdwn }),
             content_type: "application/json",
@@ -132,6 +265,21 @@ impl WebhookAdapter for SlackAdapter {
     }
 }
 
+/// Escapes the plain-text title, expanding mention tokens.
+fn mrkdwn_title(title: &str, mentions: &[Mention]) -> String {
+    let mut output = String::new();
+    for segment in split_mention_tokens(title) {
+        match segment {
+            Segment::Text(text) => output.push_str(&escape_mrkdwn(text)),
+            Segment::Mention { platform, handle } => match lookup_mention(mentions, platform, handle, "slack") {
+                (Some(user), _) => output.push_str(&format!("<@{}>", user)),
+                (None, fallback) => output.push_str(&format!("@{}", escape_mrkdwn(fallback))),
+            },
+        }
+    }
+    output
+}
+
 fn slack_markdown_from_payload(event: &Value) -> String {
     let mut parts = Vec::new();
 
@@ -154,7 +302,7 @@ fn slack_markdown_from_payload(event: &Value) -> String {
             parts.push(block_parts.join("\n\n"));
         }
     } else if !text.is_empty() {
-        parts.push(text);
+        parts.push(slack_mrkdwn_to_markdown(&text));
     }
 
     if let Some(attachments) = event.get("attachments").and_then(|value| value.as_array()) {
@@ -209,12 +357,12 @@ fn slack_block_to_markdown(block: &Value) -> Option<String> {
     match block_type {
         "section" => {
             let mut lines = Vec::new();
-            if let Some(text) = slack_text_object_text(block.get("text")) {
+            if let Some(text) = slack_text_object_markdown(block.get("text")) {
                 lines.push(text);
             }
             if let Some(fields) = block.get("fields").and_then(|value| value.as_array()) {
                 for field in fields {
-                    if let Some(text) = slack_text_object_text(Some(field)) {
+                    if let Some(text) = slack_text_object_markdown(Some(field)) {
                         lines.push(format!("- {}", text));
                     }
                 }
@@ -246,7 +394,7 @@ fn slack_block_to_markdown(block: &Value) -> Option<String> {
             let mut lines = Vec::new();
             if let Some(elements) = block.get("elements").and_then(|value| value.as_array()) {
                 for element in elements {
-                    if let Some(text) = slack_text_object_text(Some(element)) {
+                    if let Some(text) = slack_text_object_markdown(Some(element)) {
                         lines.push(text);
                     } else if let Some(url) =
                         element.get("image_url").and_then(|value| value.as_str())
@@ -290,54 +438,450 @@ fn slack_block_to_markdown(block: &Value) -> Option<String> {
 
 fn slack_rich_text_to_markdown(block: &Value) -> Option<String> {
     let elements = block.get("elements").and_then(|value| value.as_array())?;
-    let mut lines = Vec::new();
+    let mut output = String::new();
+    let mut previous_was_list = false;
     for element in elements {
-        if element.get("type").and_then(|value| value.as_str()) == Some("rich_text_section") {
-            if let Some(section_elements) = element.get("elements").and_then(|value| value.as_array())
-            {
-                let mut text = String::new();
-                for item in section_elements {
-                    match item.get("type").and_then(|value| value.as_str()) {
-                        Some("text") => {
-                            if let Some(value) = item.get("text").and_then(|value| value.as_str())
-                            {
-                                text.push_str(value);
-                            }
-                        }
-                        Some("link") => {
-                            let url = item.get("url").and_then(|value| value.as_str()).unwrap_or("");
-                            let label = item
-                                .get("text")
-                                .and_then(|value| value.as_str())
-                                .unwrap_or(url);
-                            if !url.is_empty() {
-                                text.push_str(&format!("[{}]({})", label, url));
-                            }
-                        }
-                        Some("emoji") => {
-                            if let Some(name) = item.get("name").and_then(|value| value.as_str())
-                            {
-                                text.push_str(&format!(":{}:", name));
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-                if !text.is_empty() {
-                    lines.push(text);
-                }
+        let element_type = element.get("type").and_then(|value| value.as_str());
+        let items = element
+            .get("elements")
+            .and_then(|value| value.as_array())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let rendered = match element_type {
+            Some("rich_text_section") => slack_rich_text_inline(items).trim_end().to_string(),
+            Some("rich_text_list") => slack_rich_text_list(element, items),
+            Some("rich_text_quote") => slack_rich_text_inline(items)
+                .trim_end()
+                .lines()
+                .map(|line| format!("> {}", line))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Some("rich_text_preformatted") => {
+                let code: String = items
+                    .iter()
+                    .filter_map(|item| item.get("text").and_then(|value| value.as_str()))
+                    .collect();
+                format!("```\n{}\n```", code.trim_end_matches('\n'))
             }
+            _ => String::new(),
+        };
+        if rendered.is_empty() {
+            continue;
+        }
+        let is_list = element_type == Some("rich_text_list");
+        if !output.is_empty() {
+            // Consecutive lists are joined tightly so indented lists nest.
+            output.push_str(if is_list && previous_was_list { "\n" } else { "\n\n" });
         }
+        output.push_str(&rendered);
+        previous_was_list = is_list;
     }
-    if lines.is_empty() {
+    if output.is_empty() {
         None
     } else {
-        Some(lines.join("\n"))
+        Some(output)
+    }
+}
+
+fn slack_rich_text_list(list: &Value, items: &[Value]) -> String {
+    let ordered = list.get("style").and_then(|value| value.as_str()) == Some("ordered");
+    let indent = list.get("indent").and_then(|value| value.as_u64()).unwrap_or(0) as usize;
+    let offset = list.get("offset").and_then(|value| value.as_u64()).unwrap_or(0);
+    let padding = "    ".repeat(indent);
+
+    let mut lines = Vec::new();
+    for (index, item) in items.iter().enumerate() {
+        let marker = if ordered {
+            format!("{}.", offset + index as u64 + 1)
+        } else {
+            "-".to_string()
+        };
+        let continuation = format!("{}{}", padding, " ".repeat(marker.len() + 1));
+        let text = slack_rich_text_inline(
+            item.get("elements")
+                .and_then(|value| value.as_array())
+                .map(Vec::as_slice)
+                .unwrap_or_default(),
+        );
+        let text = text.trim_end();
+        let mut item_lines = text.lines();
+        lines.push(format!(
+            "{}{} {}",
+            padding,
+            marker,
+            item_lines.next().unwrap_or("")
+        ));
+        for line in item_lines {
+            lines.push(format!("{}{}", continuation, line));
+        }
+    }
+    lines.join("\n")
+}
+
+fn slack_rich_text_inline(items: &[Value]) -> String {
+    let mut text = String::new();
+    for item in items {
+        let style = item.get("style");
+        match item.get("type").and_then(|value| value.as_str()) {
+            Some("text") => {
+                if let Some(value) = item.get("text").and_then(|value| value.as_str()) {
+                    text.push_str(&apply_rich_text_style(value, style));
+                }
+            }
+            Some("link") => {
+                let url = item.get("url").and_then(|value| value.as_str()).unwrap_or("");
+                let label = item
+                    .get("text")
+                    .and_then(|value| value.as_str())
+                    .filter(|label| !label.is_empty())
+                    .unwrap_or(url);
+                if !url.is_empty() {
+                    let label = apply_rich_text_style(label, style);
+                    text.push_str(&format!("[{}]({})", label, url));
+                }
+            }
+            Some("emoji") => {
+                let unicode = item
+                    .get("unicode")
+                    .and_then(|value| value.as_str())
+                    .and_then(emoji_from_unicode);
+                match (unicode, item.get("name").and_then(|value| value.as_str())) {
+                    (Some(emoji), _) => text.push_str(&emoji),
+                    (None, Some(name)) => text.push_str(&format!(":{}:", name)),
+                    (None, None) => {}
+                }
+            }
+            Some("user") => {
+                if let Some(user_id) = item.get("user_id").and_then(|value| value.as_str()) {
+                    // Kept in Slack syntax so mention normalization picks it up.
+                    text.push_str(&format!("<@{}>", user_id));
+                }
+            }
+            Some("usergroup") => {
+                if let Some(group_id) = item.get("usergroup_id").and_then(|value| value.as_str())
+                {
+                    text.push_str(&apply_rich_text_style(&format!("@{}", group_id), style));
+                }
+            }
+            Some("channel") => {
+                if let Some(channel_id) = item.get("channel_id").and_then(|value| value.as_str())
+                {
+                    text.push_str(&apply_rich_text_style(&format!("#{}", channel_id), style));
+                }
+            }
+            Some("broadcast") => {
+                if let Some(range) = item.get("range").and_then(|value| value.as_str()) {
+                    text.push_str(&apply_rich_text_style(&format!("@{}", range), style));
+                }
+            }
+            Some("date") => {
+                let fallback = item
+                    .get("fallback")
+                    .and_then(|value| value.as_str())
+                    .map(String::from)
+                    .or_else(|| item.get("timestamp").map(|value| value.to_string()));
+                if let Some(fallback) = fallback {
+                    text.push_str(&fallback);
+                }
+            }
+            Some("color") => {
+                if let Some(value) = item.get("value").and_then(|value| value.as_str()) {
+                    text.push_str(&format!("`{}`", value));
+                }
+            }
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Wraps `text` in CommonMark markers for the rich_text `style` flags,
+/// keeping surrounding whitespace outside the markers.
+fn apply_rich_text_style(text: &str, style: Option<&Value>) -> String {
+    let style = match style {
+        Some(style) => style,
+        None => return text.to_string(),
+    };
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return text.to_string();
+    }
+    let flag = |name: &str| style.get(name).and_then(|value| value.as_bool()) == Some(true);
+
+    let mut styled = trimmed.to_string();
+    if flag("code") {
+        styled = format!("`{}`", styled);
+    }
+    if flag("strike") {
+        styled = format!("~~{}~~", styled);
+    }
+    if flag("italic") {
+        styled = format!("*{}*", styled);
+    }
+    if flag("bold") {
+        styled = format!("**{}**", styled);
+    }
+
+    let leading = &text[..text.len() - text.trim_start().len()];
+    let trailing = &text[text.trim_end().len()..];
+    format!("{}{}{}", leading, styled, trailing)
+}
+
+fn emoji_from_unicode(unicode: &str) -> Option<String> {
+    unicode
+        .split('-')
+        .map(|code| u32::from_str_radix(code, 16).ok().and_then(char::from_u32))
+        .collect()
+}
+
+/// Converts Slack mrkdwn to CommonMark.
+///
+/// Handles `<url|label>` links, channel/broadcast/subteam/date references,
+/// `*bold*`, `_italic_`, `~strike~`, code spans and fences, `•` bullets and
+/// HTML entities. User references (`<@U…>`) are kept as-is so mention
+/// normalization can turn them into UEM tokens.
+fn slack_mrkdwn_to_markdown(text: &str) -> String {
+    let mut output = String::new();
+    let fences = text.matches("```").count();
+    for (index, segment) in text.split("```").enumerate() {
+        // Odd segments are code when a closing fence follows them.
+        if index % 2 == 1 && index < fences {
+            let code = decode_slack_entities(segment.trim_matches('\n'));
+            output.push_str(&format!("\n```\n{}\n```\n", code));
+        } else {
+            if index % 2 == 1 {
+                output.push_str("```");
+            }
+            output.push_str(&slack_mrkdwn_inline(segment));
+        }
+    }
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut previous_quote = false;
+    for line in output.trim_matches('\n').lines() {
+        let line = if let Some(item) = line.strip_prefix("• ") {
+            format!("- {}", item)
+        } else if let Some(item) = line.trim_start().strip_prefix("◦ ") {
+            format!("    - {}", item)
+        } else {
+            line.to_string()
+        };
+        let is_quote = line.starts_with('>');
+        // A Slack quote covers a single line; stop CommonMark lazy continuation.
+        if previous_quote && !is_quote && !line.trim().is_empty() {
+            lines.push(String::new());
+        }
+        previous_quote = is_quote;
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+fn slack_mrkdwn_inline(text: &str) -> String {
+    let mut output = String::new();
+    let backticks = text.matches('`').count();
+    for (index, part) in text.split('`').enumerate() {
+        if index % 2 == 1 && index < backticks {
+            output.push('`');
+            output.push_str(&decode_slack_entities(part));
+            output.push('`');
+        } else {
+            if index % 2 == 1 {
+                output.push('`');
+            }
+            output.push_str(&slack_mrkdwn_text(part));
+        }
+    }
+    output
+}
+
+fn slack_mrkdwn_text(text: &str) -> String {
+    // Swap references for private-use placeholders so emphasis conversion
+    // never touches URLs, then put the converted references back.
+    let mut references = Vec::new();
+    let mut masked = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        masked.push_str(&rest[..start]);
+        let candidate = &rest[start + 1..];
+        match candidate.find('>') {
+            Some(end) if !candidate[..end].contains(['<', '\n']) => {
+                let placeholder = char::from_u32(0xE000 + references.len() as u32).unwrap_or('\u{FFFD}');
+                references.push(slack_reference_to_markdown(&candidate[..end]));
+                masked.push(placeholder);
+                rest = &candidate[end + 1..];
+            }
+            _ => {
+                masked.push('<');
+                rest = candidate;
+            }
+        }
+    }
+    masked.push_str(rest);
+
+    let converted = decode_slack_text_entities(&convert_slack_emphasis(&masked));
+    converted
+        .chars()
+        .map(|ch| {
+            let index = (ch as u32).wrapping_sub(0xE000) as usize;
+            if (0xE000..0xF8FF).contains(&(ch as u32)) && index < references.len() {
+                references[index].clone()
+            } else {
+                ch.to_string()
+            }
+        })
+        .collect()
+}
+
+fn slack_reference_to_markdown(reference: &str) -> String {
+    let (target, label) = match reference.split_once('|') {
+        Some((target, label)) => (target, Some(escape_slack_entities(label))),
+        None => (reference, None),
+    };
+    if target.starts_with('@') {
+        return format!("<{}>", reference);
+    }
+    if let Some(channel) = target.strip_prefix('#') {
+        return format!("#{}", label.unwrap_or_else(|| channel.to_string()));
+    }
+    if let Some(special) = target.strip_prefix('!') {
+        if let Some(label) = label {
+            return label;
+        }
+        return match special.split_once('^') {
+            Some(("subteam", id)) => format!("@{}", id),
+            Some((name, _)) => name.to_string(),
+            None => format!("@{}", special),
+        };
+    }
+    let url = decode_slack_entities(target);
+    match label {
+        Some(label) if !label.is_empty() => format!("[{}]({})", label, url),
+        _ if url.contains("://") || url.starts_with("mailto:") => format!("<{}>", url),
+        _ => url,
+    }
+}
+
+fn convert_slack_emphasis(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut output = String::with_capacity(text.len());
+    let mut index = 0;
+    while index < chars.len() {
+        let ch = chars[index];
+        let replacement = match ch {
+            '*' => Some("**"),
+            '_' => Some("*"),
+            '~' => Some("~~"),
+            _ => None,
+        };
+        if let Some(replacement) = replacement {
+            // Runs like `**` are not Slack formatting; pass them through.
+            let run = chars[index..].iter().take_while(|&&next| next == ch).count();
+            if run > 1 {
+                output.extend(std::iter::repeat_n(ch, run));
+                index += run;
+                continue;
+            }
+            let opens = (index == 0 || !chars[index - 1].is_alphanumeric())
+                && chars
+                    .get(index + 1)
+                    .is_some_and(|next| !next.is_whitespace());
+            if opens {
+                if let Some(close) = find_emphasis_close(&chars, index + 1, ch) {
+                    let inner: String = chars[index + 1..close].iter().collect();
+                    output.push_str(replacement);
+                    output.push_str(&convert_slack_emphasis(&inner));
+                    output.push_str(replacement);
+                    index = close + 1;
+                    continue;
+                }
+            }
+        }
+        output.push(ch);
+        index += 1;
+    }
+    output
+}
+
+fn find_emphasis_close(chars: &[char], start: usize, marker: char) -> Option<usize> {
+    for index in start..chars.len() {
+        if chars[index] == '\n' {
+            return None;
+        }
+        if chars[index] == marker
+            && index > start
+            && !chars[index - 1].is_whitespace()
+            && chars
+                .get(index + 1)
+                .is_none_or(|next| !next.is_alphanumeric() && *next != marker)
+        {
+            return Some(index);
+        }
     }
+    None
+}
+
+fn decode_slack_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Decodes entities in mrkdwn text that becomes markdown. A `&gt;` starting a
+/// line is a Slack quote; everything else is escaped by
+/// [`escape_slack_entities`].
+fn decode_slack_text_entities(text: &str) -> String {
+    text.split_inclusive('\n')
+        .map(|line| match line.strip_prefix("&gt;") {
+            Some(quoted) => format!(">{}", escape_slack_entities(quoted)),
+            None => escape_slack_entities(line),
+        })
+        .collect()
+}
+
+/// Decodes `&lt;`, `&gt;` and `&amp;` into markdown escapes, so text Slack
+/// escaped stays text instead of becoming inline HTML or an entity reference.
+fn escape_slack_entities(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let (decoded, len) = if rest.starts_with("&lt;") {
+            ("\\<", 4)
+        } else if rest.starts_with("&gt;") {
+            ("\\>", 4)
+        } else if rest.starts_with("&amp;") {
+            ("&", 5)
+        } else {
+            ("&", 1)
+        };
+        rest = &rest[len..];
+        if decoded == "&" && starts_entity(rest) {
+            output.push('\\');
+        }
+        output.push_str(decoded);
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Whether `&` followed by `text` reads as a CommonMark entity like `&copy;`.
+fn starts_entity(text: &str) -> bool {
+    let name_len = text
+        .bytes()
+        .take_while(|byte| byte.is_ascii_alphanumeric() || *byte == b'#')
+        .count();
+    name_len > 0 && text[name_len..].starts_with(';')
 }
 
 fn slack_attachment_to_markdown(attachment: &Value) -> String {
     let mut parts = Vec::new();
+    if let Some(pretext) = attachment.get("pretext").and_then(|value| value.as_str()) {
+        if !pretext.is_empty() {
+            parts.push(slack_mrkdwn_to_markdown(pretext));
+        }
+    }
     let title = attachment
         .get("title")
         .and_then(|value| value.as_str())
@@ -356,7 +900,7 @@ fn slack_attachment_to_markdown(attachment: &Value) -> String {
 
     if let Some(text) = attachment.get("text").and_then(|value| value.as_str()) {
         if !text.is_empty() {
-            parts.push(text.to_string());
+            parts.push(slack_mrkdwn_to_markdown(text));
         }
     }
 
@@ -372,7 +916,7 @@ fn slack_attachment_to_markdown(attachment: &Value) -> String {
                 .and_then(|value| value.as_str())
                 .unwrap_or("");
             if !title.is_empty() && !value.is_empty() {
-                lines.push(format!("- **{}**: {}", title, value));
+                lines.push(format!("- **{}**: {}", title, slack_mrkdwn_to_markdown(value)));
             }
         }
         if !lines.is_empty() {
@@ -392,6 +936,58 @@ fn slack_attachment_to_markdown(attachment: &Value) -> String {
     parts.join("\n\n")
 }
 
+/// Rewrites Slack user references (`<@U123>` or `<@U123|name>`) into UEM
+/// mention tokens, recording each mentioned user in `mentions`.
+fn normalize_slack_mentions(text: &str, mentions: &mut Vec<Mention>) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("<@") {
+        output.push_str(&rest[..start]);
+        let candidate = &rest[start + 2..];
+        let reference = candidate
+            .find('>')
+            .map(|end| &candidate[..end])
+            .filter(|reference| !reference.contains(['<', '\n', ':']));
+        match reference {
+            Some(reference) => {
+                let (user_id, name) = match reference.split_once('|') {
+                    Some((user_id, name)) => (user_id, Some(name.to_string())),
+                    None => (reference, None),
+                };
+                output.push_str(&mention_token("slack", user_id));
+                if !mentions
+                    .iter()
+                    .any(|mention| mention.handle == user_id)
+                {
+                    mentions.push(Mention::new("slack", user_id, name));
+                }
+                rest = &candidate[reference.len() + 1..];
+            }
+            None => {
+                output.push_str("<@");
+                rest = candidate;
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Like [`slack_text_object_text`], but converts `mrkdwn` text objects to
+/// CommonMark. `plain_text` objects are returned unchanged.
+fn slack_text_object_markdown(value: Option<&Value>) -> Option<String> {
+    let text = slack_text_object_text(value)?;
+    let is_plain = value
+        .and_then(|value| value.get("type"))
+        .and_then(|value| value.as_str())
+        == Some("plain_text");
+    Some(if is_plain {
+        text
+    } else {
+        slack_mrkdwn_to_markdown(&text)
+    })
+}
+
 fn slack_text_object_text(value: Option<&Value>) -> Option<String> {
     value
         .and_then(|value| value.get("text"))
@@ -441,6 +1037,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn slack_slash_command_ingress() {
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert("content-type", "application/x-www-form-urlencoded".parse().unwrap());
+        let body = crate::utils::body::decode(
+            &headers,
+            b"command=%2Fdeploy&text=api+to+*prod*&channel_id=C1&user_id=U1",
+        )
+        .expect("body");
+        let event = SlackAdapter.ingress_body_to_uem(&body).expect("uem");
+        assert_eq!(event.title.as_deref(), Some("/deploy"));
+        assert_eq!(event.markdown, "api to **prod**");
+        assert_eq!(event.meta["channel"], "C1");
+        assert_eq!(event.meta["type"], "slash_command");
+    }
+
     #[test]
     fn slack_uem_to_egress() {
         let adapter = SlackAdapter;
@@ -452,6 +1064,7 @@ mod tests {
             markdown: "**bold**".to_string(),
             raw: json!({}),
             meta: json!({}),
+            mentions: Vec::new(),
         };
         let payload = adapter.uem_to_egress(&event).expect("payload");
         assert_yaml_snapshot!(
@@ -474,6 +1087,7 @@ mod tests {
             markdown: "hello".to_string(),
             raw: json!({}),
             meta: json!({}),
+            mentions: Vec::new(),
         };
         let payload = adapter.uem_to_egress(&event).expect("payload");
         assert_yaml_snapshot!(
@@ -530,17 +1144,161 @@ mod tests {
         );
     }
 
+    #[test]
+    fn slack_mentions_round_trip() {
+        let adapter = SlackAdapter;
+        let payload = json!({
+            "event": { "type": "message", "text": "ping <@U123|alice> and <@U456>" }
+        });
+        let event = adapter.ingress_to_uem(&payload).expect("uem");
+        assert_eq!(event.markdown, "ping <@slack:U123> and <@slack:U456>");
+        assert_eq!(event.mentions.len(), 2);
+        assert_eq!(event.mentions[0].name.as_deref(), Some("alice"));
+
+        let payload = adapter.uem_to_egress(&event).expect("payload");
+        assert_eq!(payload.body["text"], "ping <@U123> and <@U456>");
+    }
+
+    #[test]
+    fn slack_ingress_mrkdwn_text() {
+        let adapter = SlackAdapter;
+        let payload = json!({
+            "event_id": "evt-mrkdwn-1",
+            "event_time": 1700000000,
+            "event": {
+                "type": "message",
+                "text": "*Deploy* of _api_ ~failed~ on <https://ci.example.com/run?id=1&amp;x=2|run_42> for <@U1|alice> in <#C1|ops>\n<!here> see <https://example.com>\n&gt; R&amp;D said `a_b*c` &lt;ok&gt; &amp;amp; &lt;b&gt;\n• first\n• second\n```let x = 1 &lt; 2;```"
+            }
+        });
+        let event = adapter.ingress_to_uem(&payload).expect("uem");
+        assert_yaml_snapshot!(
+            "adapters_slack_ingress_mrkdwn",
+            json!({
+                "markdown": event.markdown,
+                "mentions": event.mentions,
+            })
+        );
+    }
+
+    #[test]
+    fn slack_ingress_rich_text() {
+        let adapter = SlackAdapter;
+        let payload = json!({
+            "event": {
+                "type": "message",
+                "blocks": [{
+                    "type": "rich_text",
+                    "elements": [
+                        {
+                            "type": "rich_text_section",
+                            "elements": [
+                                { "type": "text", "text": "Hi " },
+                                { "type": "user", "user_id": "U1" },
+                                { "type": "text", "text": ", status ", "style": { "italic": true } },
+                                { "type": "text", "text": "degraded ", "style": { "bold": true } },
+                                { "type": "emoji", "name": "fire", "unicode": "1f525" },
+                                { "type": "text", "text": " in " },
+                                { "type": "channel", "channel_id": "C1" },
+                                { "type": "text", "text": " cc " },
+                                { "type": "broadcast", "range": "here" },
+                                { "type": "text", "text": " " },
+                                { "type": "usergroup", "usergroup_id": "S1" },
+                                { "type": "text", "text": "\n" }
+                            ]
+                        },
+                        {
+                            "type": "rich_text_list",
+                            "style": "ordered",
+                            "indent": 0,
+                            "elements": [
+                                { "type": "rich_text_section", "elements": [{ "type": "text", "text": "check " }, { "type": "text", "text": "db-7", "style": { "code": true } }] },
+                                { "type": "rich_text_section", "elements": [{ "type": "link", "url": "https://runbook", "text": "runbook" }] }
+                            ]
+                        },
+                        {
+                            "type": "rich_text_list",
+                            "style": "bullet",
+                            "indent": 1,
+                            "elements": [
+                                { "type": "rich_text_section", "elements": [{ "type": "text", "text": "nested", "style": { "strike": true } }] }
+                            ]
+                        },
+                        {
+                            "type": "rich_text_quote",
+                            "elements": [{ "type": "text", "text": "line one\nline two" }]
+                        },
+                        {
+                            "type": "rich_text_preformatted",
+                            "elements": [{ "type": "text", "text": "SELECT 1;\n" }]
+                        }
+                    ]
+                }]
+            }
+        });
+        let event = adapter.ingress_to_uem(&payload).expect("uem");
+        assert_yaml_snapshot!(
+            "adapters_slack_ingress_rich_text",
+            json!({
+                "markdown": event.markdown,
+                "mentions": event.mentions,
+            })
+        );
+    }
+
+    #[test]
+    fn test_slack_mrkdwn_emphasis_boundaries() {
+        assert_eq!(
+            slack_mrkdwn_to_markdown("snake_case_name and 2*3*4 but *bold* and _it_"),
+            "snake_case_name and 2*3*4 but **bold** and *it*"
+        );
+        assert_eq!(slack_mrkdwn_to_markdown("unclosed ``` fence"), "unclosed ``` fence");
+    }
+
+    #[test]
+    fn slack_mrkdwn_egress() {
+        let adapter = SlackAdapter;
+        let markdown = r#"R&D <team> said un**frigging**believable and **bold _it_**.
+
+3. third
+4. fourth
+   - nested
+     - deeper
+
+> Quote line one
+> line two
+>
+> - quoted item
+
+![chart](https://img.example.com/a.png?x=1&y=2) ![](https://img.example.com/b.png)
+
+```
+if a < b && c > d {}
+```"#;
+        let event = UemEvent {
+            id: "evt-mrkdwn".to_string(),
+            source: "custom".to_string(),
+            timestamp: 1,
+            title: Some("Q&A <@slack:U1>".to_string()),
+            markdown: markdown.to_string(),
+            raw: json!({}),
+            meta: json!({}),
+            mentions: Vec::new(),
+        };
+        let payload = adapter.uem_to_egress(&event).expect("payload");
+        assert_yaml_snapshot!("adapters_slack_mrkdwn_egress", payload.body);
+    }
+
     #[test]
     fn test_slack_conversion() {
         let md = "**Bold** and *Italic* and [Link](http://example.com)";
-        let slack = markdown_to_slack(md);
+        let slack = document_to_mrkdwn(&Document::parse(md), &[]);
         assert_eq!(slack, "*Bold* and _Italic_ and <http://example.com|Link>");
     }
 
      #[test]
     fn test_slack_headers() {
         let md = "# Heading 1";
-        let slack = markdown_to_slack(md);
+        let slack = document_to_mrkdwn(&Document::parse(md), &[]);
         assert_eq!(slack, "*Heading 1*"); // Headers become bold
     }
 }
@@ -1,33 +1,78 @@
+use base64::Engine;
 use serde_json::{json, Value};
 use uuid::Uuid;
 
-use crate::adapters::{AdapterError, WebhookAdapter};
-use crate::models::{OutgoingPayload, UemEvent};
+use crate::adapters::callback::{callback_error, xml_to_value, MsgCrypt};
+use crate::adapters::{
+    str_field, AdapterError, Callback, CallbackAnswer, CallbackReply, InlineMedia, MessageLimit, WebhookAdapter,
+};
+use crate::document::markdown::{write_markdown, MarkdownStyle};
+use crate::document::{render_event, Capabilities, Document, Renderer, TableSupport};
+use crate::models::{Mention, OutgoingPayload, UemEvent};
+use crate::utils::body::IngressBody;
+use crate::utils::mention::{
+    lookup_mention, mention_token, render_mentions, replace_handle_references, split_mention_tokens,
+    Segment,
+};
 
 #[derive(Debug)]
 pub struct WecomAdapter;
 
 impl WebhookAdapter for WecomAdapter {
-    fn ingress_to_uem(&self, payload: &Value) -> Result<UemEvent, AdapterError> {
-        let msgtype = payload.get("msgtype").and_then(|value| value.as_str());
-        let text = match msgtype {
-            Some("markdown") => payload
-                .get("markdown")
-                .and_then(|value| value.get("content"))
-                .and_then(|value| value.as_str())
-                .unwrap_or(""),
-            Some("markdown_v2") => payload
-                .get("markdown_v2")
-                .and_then(|value| value.get("content"))
-                .and_then(|value| value.as_str())
-                .unwrap_or(""),
-            _ => payload
+    /// With `wecom.token` and `wecom.encoding_aes_key` set, app callbacks must
+    /// be encrypted: an XML body with an `Encrypt` element, or
+    /// `{"encrypt": ...}` from intelligent robots. They are answered with an
+    /// empty body, which WeCom takes as "no reply".
+    fn open_callback(&self, callback: &Callback<'_>, body: &mut IngressBody) -> Result<CallbackAnswer, AdapterError> {
+        let Some(crypt) = MsgCrypt::from_settings(callback.settings, "corp_id")? else {
+            if callback.query("msg_signature").is_some() {
+                return Err(callback_error(
+                    "encrypted WeCom callback but no wecom.token and wecom.encoding_aes_key are configured",
+                ));
+            }
+            return Ok(CallbackAnswer::Event);
+        };
+
+        let encrypted = match body.value.get("encrypt").and_then(Value::as_str) {
+            Some(encrypted) => encrypted.to_string(),
+            None => body
+                .value
                 .get("text")
-                .and_then(|value| value.get("content"))
-                .and_then(|value| value.as_str())
-                .unwrap_or(""),
+                .and_then(Value::as_str)
+                .map(xml_to_value)
+                .transpose()?
+                .and_then(|xml| xml.get("Encrypt").and_then(Value::as_str).map(String::from))
+                .ok_or_else(|| callback_error("WeCom callback is not encrypted"))?,
+        };
+        crypt.verify(callback, "msg_signature", &encrypted)?;
+        let (message, _) = crypt.decrypt(&encrypted)?;
+        body.value = if message.trim_start().starts_with('<') {
+            xml_to_value(&message)?
+        } else {
+            serde_json::from_str(&message)
+                .map_err(|err| callback_error(format!("decrypted WeCom payload is not JSON: {}", err)))?
+        };
+        Ok(CallbackAnswer::EventReply(CallbackReply {
+            content_type: "text/plain",
+            body: String::new(),
+        }))
+    }
+
+    /// Answers the `GET` sent when an app's callback URL is saved with the
+    /// decrypted `echostr`.
+    fn verify_callback_url(&self, callback: &Callback<'_>) -> Option<Result<String, AdapterError>> {
+        Some(wecom_verify_url(callback))
+    }
+
+    fn ingress_to_uem(&self, payload: &Value) -> Result<UemEvent, AdapterError> {
+        if let Some(msgtype) = payload.get("MsgType").and_then(|value| value.as_str()) {
+            return Ok(wecom_app_message_to_uem(payload, msgtype));
         }
-        .to_string();
+        let msgtype = payload.get("msgtype").and_then(|value| value.as_str());
+        let text = wecom_markdown_from_payload(payload, msgtype);
+        let title = wecom_title_from_payload(payload, msgtype);
+        let mut mentions = Vec::new();
+        let text = normalize_wecom_mentions(&text, payload, &mut mentions);
 
         let event_id = payload
             .get("msgid")
@@ -40,32 +85,95 @@ impl WebhookAdapter for WecomAdapter {
             .and_then(value_to_i64)
             .unwrap_or_else(now_timestamp);
 
+        let mut meta = json!({
+            "msgtype": payload.get("msgtype"),
+            "mentioned_list": payload
+                .get("text")
+                .and_then(|value| value.get("mentioned_list")),
+        });
+        if let Some(fields) = wecom_meta_from_payload(payload, msgtype) {
+            meta[msgtype.unwrap_or_default()] = fields;
+        }
+
         Ok(UemEvent {
             id: event_id,
             source: "wecom".to_string(),
             timestamp,
-            title: None,
+            title,
             markdown: if text.is_empty() {
                 payload.to_string()
             } else {
                 text
             },
             raw: payload.clone(),
-            meta: json!({
-                "msgtype": payload.get("msgtype"),
-                "mentioned_list": payload
-                    .get("text")
-                    .and_then(|value| value.get("mentioned_list")),
-            }),
+            meta,
+            mentions,
         })
     }
 
     fn uem_to_egress(&self, event: &UemEvent) -> Result<OutgoingPayload, AdapterError> {
-        let content = if let Some(title) = &event.title {
-            format!("# {}\n\n{}", title, event.markdown)
-        } else {
-            event.markdown.clone()
+        render_event(self, event, &Document::parse(&event.markdown))
+    }
+
+    fn renderer(&self) -> Option<&dyn Renderer> {
+        Some(self)
+    }
+
+    fn inline_media(&self, payload: &Value) -> Vec<InlineMedia> {
+        if payload.get("msgtype").and_then(|value| value.as_str()) != Some("image") {
+            return Vec::new();
+        }
+        let image = payload.get("image").unwrap_or(&Value::Null);
+        match base64::engine::general_purpose::STANDARD.decode(str_field(image, "base64")) {
+            Ok(data) if !data.is_empty() => vec![InlineMedia {
+                placeholder: wecom_image_placeholder(image),
+                data,
+            }],
+            _ => Vec::new(),
+        }
+    }
+
+    fn message_limit(&self) -> Option<MessageLimit> {
+        // WeCom robots reject markdown content over 4,096 bytes.
+        Some(MessageLimit::bytes(4_096))
+    }
+}
+
+impl Renderer for WecomAdapter {
+    fn platform(&self) -> &'static str {
+        "wecom"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            tables: TableSupport::Monospace,
+            images: false,
+            code_blocks: false,
+            mentions: true,
+        }
+    }
+
+    fn render(&self, event: &UemEvent, doc: &Document) -> Result<OutgoingPayload, AdapterError> {
+        // The robot `markdown` type has no italics or strikethrough.
+        let style = MarkdownStyle {
+            emphasis: false,
+            strikethrough: false,
         };
+        let mut content = write_markdown(doc, style, &mut |platform, handle| {
+            match lookup_mention(&event.mentions, platform, handle, "wecom") {
+                (Some(userid), _) => format!("<@{}>", userid),
+                (None, fallback) => format!("@{}", fallback),
+            }
+        });
+        if let Some(title) = &event.title {
+            let title = render_mentions(title, &event.mentions, "wecom", |userid, fallback| {
+                match userid {
+                    Some(userid) => format!("<@{}>", userid),
+                    None => format!("@{}", fallback),
+                }
+            });
+            content = format!("# {}\n\n{}", title, content);
+        }
 
         Ok(OutgoingPayload {
             body: json!({
@@ -77,6 +185,440 @@ impl WebhookAdapter for WecomAdapter {
     }
 }
 
+fn wecom_verify_url(callback: &Callback<'_>) -> Result<String, AdapterError> {
+    let crypt = MsgCrypt::from_settings(callback.settings, "corp_id")?
+        .ok_or_else(|| callback_error("no wecom.token and wecom.encoding_aes_key are configured"))?;
+    // An unescaped `+` in the query string arrives as a space.
+    let echostr = callback
+        .query("echostr")
+        .ok_or_else(|| callback_error("missing echostr parameter"))?
+        .replace(' ', "+");
+    crypt.verify(callback, "msg_signature", &echostr)?;
+    Ok(crypt.decrypt(&echostr)?.0)
+}
+
+/// Converts a message or event an app received through its callback, as
+/// parsed from the decrypted XML (`MsgType`, `Content`, `FromUserName`, ...).
+fn wecom_app_message_to_uem(payload: &Value, msgtype: &str) -> UemEvent {
+    let field = |key: &str| str_field(payload, key);
+    let markdown = match msgtype {
+        "text" => field("Content").to_string(),
+        "image" => format!("![image]({})", field("PicUrl")),
+        "link" => format!("**[{}]({})**\n\n{}", field("Title"), field("Url"), field("Description")),
+        "location" => format!("[Location: {} ({}, {})]", field("Label"), field("Location_X"), field("Location_Y")),
+        "voice" | "video" | "file" => format!("[{}: {}]", msgtype, field("MediaId")),
+        "event" if field("EventKey").is_empty() => format!("**Event: {}**", field("Event")),
+        "event" => format!("**Event: {}** ({})", field("Event"), field("EventKey")),
+        _ => String::new(),
+    };
+
+    UemEvent {
+        id: Some(field("MsgId"))
+            .filter(|id| !id.is_empty())
+            .map(String::from)
+            .unwrap_or_else(|| Uuid::new_v4().to_string()),
+        source: "wecom".to_string(),
+        timestamp: payload
+            .get("CreateTime")
+            .and_then(value_to_i64)
+            .unwrap_or_else(now_timestamp),
+        title: Some(field("Title"))
+            .filter(|title| msgtype == "link" && !title.is_empty())
+            .map(String::from),
+        markdown: if markdown.trim().is_empty() {
+            payload.to_string()
+        } else {
+            markdown.trim_end().to_string()
+        },
+        raw: payload.clone(),
+        meta: json!({
+            "msgtype": msgtype,
+            "from_user": payload.get("FromUserName"),
+            "agent_id": payload.get("AgentID"),
+            "event": payload.get("Event"),
+        }),
+        mentions: Vec::new(),
+    }
+}
+
+fn wecom_markdown_from_payload(payload: &Value, msgtype: Option<&str>) -> String {
+    match msgtype {
+        Some("markdown") | Some("markdown_v2") => payload
+            .get(msgtype.unwrap_or_default())
+            .and_then(|value| value.get("content"))
+            .and_then(|value| value.as_str())
+            .unwrap_or("")
+            .to_string(),
+        Some("news") => {
+            let articles = payload
+                .get("news")
+                .and_then(|value| value.get("articles"))
+                .and_then(|value| value.as_array())
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let mut parts = Vec::new();
+            for (index, article) in articles.iter().enumerate() {
+                // The first article's title is the event title; don't repeat it.
+                let title = if index == 0 { "" } else { str_field(article, "title") };
+                let url = str_field(article, "url");
+                let description = str_field(article, "description");
+                let pic_url = str_field(article, "picurl");
+                let mut lines = Vec::new();
+                match (title.is_empty(), url.is_empty()) {
+                    (false, false) => lines.push(format!("**[{}]({})**", title, url)),
+                    (false, true) => lines.push(format!("**{}**", title)),
+                    (true, false) => lines.push(format!("[{}]({})", url, url)),
+                    (true, true) => {}
+                }
+                if !description.is_empty() {
+                    lines.push(description.to_string());
+                }
+                if !pic_url.is_empty() {
+                    lines.push(format!("![{}]({})", str_field(article, "title"), pic_url));
+                }
+                if !lines.is_empty() {
+                    parts.push(lines.join("\n\n"));
+                }
+            }
+            parts.join("\n\n---\n\n")
+        }
+        Some("template_card") => {
+            wecom_template_card_to_markdown(payload.get("template_card").unwrap_or(&Value::Null))
+        }
+        Some("image") => {
+            wecom_image_placeholder(payload.get("image").unwrap_or(&Value::Null))
+        }
+        Some("file") => format!(
+            "[File: {}]",
+            str_field(payload.get("file").unwrap_or(&Value::Null), "media_id")
+        ),
+        Some("voice") => format!(
+            "[Voice: {}]",
+            str_field(payload.get("voice").unwrap_or(&Value::Null), "media_id")
+        ),
+        _ => payload
+            .get("text")
+            .and_then(|value| value.get("content"))
+            .and_then(|value| value.as_str())
+            .unwrap_or("")
+            .to_string(),
+    }
+}
+
+fn wecom_template_card_to_markdown(card: &Value) -> String {
+    let mut parts = Vec::new();
+
+    if let Some(source) = card.get("source") {
+        let desc = str_field(source, "desc");
+        if !desc.is_empty() {
+            parts.push(format!("*{}*", desc));
+        }
+    }
+
+    // `main_title.title` is the event title.
+    if let Some(main_title) = card.get("main_title") {
+        let desc = str_field(main_title, "desc");
+        if !desc.is_empty() {
+            parts.push(desc.to_string());
+        }
+    }
+
+    if let Some(emphasis) = card.get("emphasis_content") {
+        let title = str_field(emphasis, "title");
+        let desc = str_field(emphasis, "desc");
+        if !title.is_empty() {
+            parts.push(if desc.is_empty() {
+                format!("**{}**", title)
+            } else {
+                format!("**{}** {}", title, desc)
+            });
+        }
+    }
+
+    if let Some(quote) = card.get("quote_area") {
+        let mut lines = Vec::new();
+        let title = str_field(quote, "title");
+        let url = str_field(quote, "url");
+        if !title.is_empty() {
+            lines.push(if url.is_empty() {
+                format!("**{}**", title)
+            } else {
+                format!("[{}]({})", title, url)
+            });
+        }
+        lines.extend(str_field(quote, "quote_text").lines().map(String::from));
+        if !lines.is_empty() {
+            parts.push(
+                lines
+                    .iter()
+                    .map(|line| format!("> {}", line))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            );
+        }
+    }
+
+    let sub_title = str_field(card, "sub_title_text");
+    if !sub_title.is_empty() {
+        parts.push(sub_title.to_string());
+    }
+
+    if let Some(image) = card.get("card_image") {
+        let url = str_field(image, "url");
+        if !url.is_empty() {
+            parts.push(format!("![image]({})", url));
+        }
+    }
+
+    if let Some(area) = card.get("image_text_area") {
+        let title = str_field(area, "title");
+        let url = str_field(area, "url");
+        let desc = str_field(area, "desc");
+        let image_url = str_field(area, "image_url");
+        let mut lines = Vec::new();
+        if !title.is_empty() {
+            lines.push(if url.is_empty() {
+                format!("**{}**", title)
+            } else {
+                format!("[{}]({})", title, url)
+            });
+        }
+        if !desc.is_empty() {
+            lines.push(desc.to_string());
+        }
+        if !image_url.is_empty() {
+            lines.push(format!("![{}]({})", title, image_url));
+        }
+        if !lines.is_empty() {
+            parts.push(lines.join("\n\n"));
+        }
+    }
+
+    if let Some(items) = card.get("vertical_content_list").and_then(|value| value.as_array()) {
+        for item in items {
+            let title = str_field(item, "title");
+            let desc = str_field(item, "desc");
+            match (title.is_empty(), desc.is_empty()) {
+                (false, false) => parts.push(format!("**{}**\n{}", title, desc)),
+                (false, true) => parts.push(format!("**{}**", title)),
+                (true, false) => parts.push(desc.to_string()),
+                (true, true) => {}
+            }
+        }
+    }
+
+    if let Some(items) = card.get("horizontal_content_list").and_then(|value| value.as_array()) {
+        let lines: Vec<String> = items
+            .iter()
+            .filter_map(|item| {
+                let key = str_field(item, "keyname");
+                let value = str_field(item, "value");
+                let rendered = match item.get("type").and_then(|value| value.as_i64()) {
+                    Some(1) => format!("[{}]({})", value, str_field(item, "url")),
+                    Some(2) => format!("{} (attachment)", value),
+                    Some(3) => mention_token("wecom", str_field(item, "userid")),
+                    _ => value.to_string(),
+                };
+                if key.is_empty() {
+                    None
+                } else {
+                    Some(format!("- **{}**: {}", key, rendered))
+                }
+            })
+            .collect();
+        if !lines.is_empty() {
+            parts.push(lines.join("\n"));
+        }
+    }
+
+    if let Some(items) = card.get("jump_list").and_then(|value| value.as_array()) {
+        let lines: Vec<String> = items
+            .iter()
+            .filter_map(|item| {
+                let title = str_field(item, "title");
+                let url = str_field(item, "url");
+                match (title.is_empty(), url.is_empty()) {
+                    (true, _) => None,
+                    (false, true) => Some(format!("- {}", title)),
+                    (false, false) => Some(format!("- [{}]({})", title, url)),
+                }
+            })
+            .collect();
+        if !lines.is_empty() {
+            parts.push(lines.join("\n"));
+        }
+    }
+
+    if let Some(action) = card.get("card_action") {
+        let url = str_field(action, "url");
+        if action.get("type").and_then(|value| value.as_i64()) == Some(1) && !url.is_empty() {
+            parts.push(format!("[Open]({})", url));
+        }
+    }
+
+    parts.join("\n\n")
+}
+
+fn wecom_title_from_payload(payload: &Value, msgtype: Option<&str>) -> Option<String> {
+    let title = match msgtype {
+        Some("news") => payload
+            .get("news")
+            .and_then(|value| value.get("articles"))
+            .and_then(|value| value.as_array())
+            .and_then(|articles| articles.first())
+            .and_then(|article| article.get("title")),
+        Some("template_card") => payload
+            .get("template_card")
+            .and_then(|value| value.get("main_title"))
+            .and_then(|value| value.get("title")),
+        _ => None,
+    };
+    title
+        .and_then(|value| value.as_str())
+        .filter(|title| !title.is_empty())
+        .map(String::from)
+}
+
+/// Extracts the msgtype-specific fields recorded under `meta.<msgtype>`.
+/// Image payloads keep only the md5 and decoded size, not the base64 data.
+fn wecom_meta_from_payload(payload: &Value, msgtype: Option<&str>) -> Option<Value> {
+    match msgtype {
+        Some("news") => {
+            let articles: Vec<Value> = payload
+                .get("news")
+                .and_then(|value| value.get("articles"))
+                .and_then(|value| value.as_array())?
+                .iter()
+                .map(|article| {
+                    json!({
+                        "title": article.get("title"),
+                        "url": article.get("url"),
+                        "picurl": article.get("picurl"),
+                    })
+                })
+                .collect();
+            Some(json!({ "articles": articles }))
+        }
+        Some("template_card") => {
+            let card = payload.get("template_card")?;
+            Some(json!({
+                "card_type": card.get("card_type"),
+                "source": card.get("source"),
+                "card_action": card.get("card_action"),
+                "task_id": card.get("task_id"),
+            }))
+        }
+        Some("image") => {
+            let image = payload.get("image")?;
+            Some(json!({
+                "md5": image.get("md5"),
+                "size": base64_decoded_len(str_field(image, "base64")),
+            }))
+        }
+        Some("file") | Some("voice") => {
+            let media = payload.get(msgtype.unwrap_or_default())?;
+            Some(json!({ "media_id": media.get("media_id") }))
+        }
+        _ => None,
+    }
+}
+
+fn wecom_image_placeholder(image: &Value) -> String {
+    let md5 = str_field(image, "md5");
+    match base64_decoded_len(str_field(image, "base64")) {
+        Some(size) => format!("[Image: {} KB, md5 {}]", size.div_ceil(1024), md5),
+        None => format!("[Image: md5 {}]", md5),
+    }
+}
+
+fn base64_decoded_len(data: &str) -> Option<usize> {
+    if data.is_empty() {
+        return None;
+    }
+    base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .ok()
+        .map(|bytes| bytes.len())
+}
+
+/// Rewrites WeCom user references into UEM mention tokens: `<@userid>` in
+/// markdown content, and `text.mentioned_list` entries (referenced as
+/// `@userid` or appended when absent from the text).
+fn normalize_wecom_mentions(text: &str, payload: &Value, mentions: &mut Vec<Mention>) -> String {
+    for segment in split_mention_tokens(text) {
+        if let Segment::Mention { platform, handle } = segment {
+            if !mentions
+                .iter()
+                .any(|mention| mention.platform == platform && mention.handle == handle)
+            {
+                mentions.push(Mention::new(platform, handle, None));
+            }
+        }
+    }
+
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("<@") {
+        output.push_str(&rest[..start]);
+        let candidate = &rest[start + 2..];
+        let userid = candidate
+            .find('>')
+            .map(|end| &candidate[..end])
+            .filter(|userid| {
+                !userid.is_empty() && !userid.contains(['<', ':', '\n', ' '])
+            });
+        match userid {
+            Some(userid) => {
+                output.push_str(&mention_token("wecom", userid));
+                if !mentions
+                    .iter()
+                    .any(|mention| mention.platform == "wecom" && mention.handle == userid)
+                {
+                    mentions.push(Mention::new("wecom", userid, None));
+                }
+                rest = &candidate[userid.len() + 1..];
+            }
+            None => {
+                output.push_str("<@");
+                rest = candidate;
+            }
+        }
+    }
+    output.push_str(rest);
+
+    let mut mentioned = payload
+        .get("text")
+        .and_then(|value| value.get("mentioned_list"))
+        .and_then(|value| value.as_array())
+        .map(|items| items.iter().filter_map(|item| item.as_str()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    mentioned.sort_by_key(|userid| std::cmp::Reverse(userid.len()));
+    let mut unreferenced = Vec::new();
+    for userid in mentioned {
+        // "@all" notifies the whole group and has no per-person handle.
+        if userid == "@all"
+            || mentions
+                .iter()
+                .any(|mention| mention.platform == "wecom" && mention.handle == userid)
+        {
+            continue;
+        }
+        let token = mention_token("wecom", userid);
+        match replace_handle_references(&output, userid, &token) {
+            Some(replaced) => output = replaced,
+            None => unreferenced.push(token),
+        }
+        mentions.push(Mention::new("wecom", userid, None));
+    }
+    if !unreferenced.is_empty() {
+        if !output.is_empty() {
+            output.push_str("\n\n");
+        }
+        output.push_str(&unreferenced.join(" "));
+    }
+    output
+}
+
 fn now_timestamp() -> i64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -92,6 +634,10 @@ fn value_to_i64(value: &Value) -> Option<i64> {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
+    use axum::http::HeaderMap;
+
     use super::*;
     use insta::assert_yaml_snapshot;
     use serde_json::json;
@@ -131,6 +677,7 @@ mod tests {
             markdown: "hello".to_string(),
             raw: json!({}),
             meta: json!({}),
+            mentions: Vec::new(),
         };
         let payload = adapter.uem_to_egress(&event).expect("payload");
         assert_yaml_snapshot!(
@@ -153,6 +700,7 @@ mod tests {
             markdown: "hello".to_string(),
             raw: json!({}),
             meta: json!({}),
+            mentions: Vec::new(),
         };
         let payload = adapter.uem_to_egress(&event).expect("payload");
         assert_yaml_snapshot!(
@@ -187,4 +735,207 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn wecom_mentions_round_trip() {
+        let adapter = WecomAdapter;
+        let payload = json!({
+            "msgtype": "text",
+            "text": { "content": "deploy done", "mentioned_list": ["zhangsan", "@all"] }
+        });
+        let event = adapter.ingress_to_uem(&payload).expect("uem");
+        assert_eq!(event.markdown, "deploy done\n\n<@wecom:zhangsan>");
+
+        let payload = adapter.uem_to_egress(&event).expect("payload");
+        assert_eq!(payload.body["markdown"]["content"], "deploy done\n\n<@zhangsan>");
+    }
+
+    #[test]
+    fn wecom_ingress_news() {
+        let adapter = WecomAdapter;
+        let payload = json!({
+            "msgid": "wx-news-1",
+            "create_time": 1700000000,
+            "msgtype": "news",
+            "news": {
+                "articles": [
+                    {
+                        "title": "中秋节礼品领取",
+                        "description": "今年中秋节公司有豪礼相送",
+                        "url": "https://www.qq.com",
+                        "picurl": "https://res.mail.qq.com/node/ww/wwopenmng/images/independent/doc/test_pic_msg1.png"
+                    },
+                    { "title": "第二篇", "url": "https://example.com/2" }
+                ]
+            }
+        });
+        let event = adapter.ingress_to_uem(&payload).expect("uem");
+        assert_yaml_snapshot!(
+            "adapters_wecom_ingress_news",
+            json!({
+                "id": event.id,
+                "source": event.source,
+                "timestamp": event.timestamp,
+                "title": event.title,
+                "markdown": event.markdown,
+                "meta": event.meta,
+            })
+        );
+    }
+
+    #[test]
+    fn wecom_ingress_template_card() {
+        let adapter = WecomAdapter;
+        let payload = json!({
+            "msgid": "wx-card-1",
+            "create_time": 1700000000,
+            "msgtype": "template_card",
+            "template_card": {
+                "card_type": "text_notice",
+                "source": { "desc": "企业微信", "desc_color": 0 },
+                "main_title": { "title": "欢迎使用企业微信", "desc": "您的好友正在邀请您加入企业微信" },
+                "emphasis_content": { "title": "100", "desc": "数据含义" },
+                "quote_area": {
+                    "type": 1,
+                    "url": "https://work.weixin.qq.com/?from=openApi",
+                    "title": "引用文本标题",
+                    "quote_text": "Jack：企业微信真的很好用~\nBalian：超级好的一款软件！"
+                },
+                "sub_title_text": "下载企业微信还能抢红包！",
+                "horizontal_content_list": [
+                    { "keyname": "邀请人", "value": "张三" },
+                    { "keyname": "企微官网", "value": "点击访问", "type": 1, "url": "https://work.weixin.qq.com/?from=openApi" },
+                    { "keyname": "企微下载", "value": "企业微信.apk", "type": 2, "media_id": "MEDIAID" },
+                    { "keyname": "负责人", "type": 3, "userid": "zhangsan" }
+                ],
+                "jump_list": [
+                    { "type": 1, "url": "https://work.weixin.qq.com/?from=openApi", "title": "企业微信官网" },
+                    { "type": 2, "appid": "APPID", "pagepath": "PAGEPATH", "title": "跳转小程序" }
+                ],
+                "card_action": { "type": 1, "url": "https://work.weixin.qq.com/?from=openApi" }
+            }
+        });
+        let event = adapter.ingress_to_uem(&payload).expect("uem");
+        assert_yaml_snapshot!(
+            "adapters_wecom_ingress_template_card",
+            json!({
+                "id": event.id,
+                "source": event.source,
+                "timestamp": event.timestamp,
+                "title": event.title,
+                "markdown": event.markdown,
+                "meta": event.meta,
+                "mentions": event.mentions,
+            })
+        );
+    }
+
+    #[test]
+    fn wecom_ingress_image_and_file() {
+        let adapter = WecomAdapter;
+        let image = json!({
+            "msgtype": "image",
+            "image": { "base64": "aGVsbG8gd29ybGQ=", "md5": "5eb63bbbe01eeed093cb22bb8f5acdc3" }
+        });
+        let file = json!({ "msgtype": "file", "file": { "media_id": "3a8asd892asd8asd" } });
+        let image = adapter.ingress_to_uem(&image).expect("uem");
+        let file = adapter.ingress_to_uem(&file).expect("uem");
+        assert_yaml_snapshot!(
+            "adapters_wecom_ingress_image_and_file",
+            json!({
+                "image": { "markdown": image.markdown, "meta": image.meta },
+                "file": { "markdown": file.markdown, "meta": file.meta },
+            })
+        );
+    }
+
+    fn settings() -> Value {
+        // The sample app from the WeCom callback documentation.
+        json!({
+            "token": "QDG6eK",
+            "encoding_aes_key": "jWmYm7qr5nMoAUwZRjGtBxmz3KA1tkAj3ykkR6q2B2C",
+            "corp_id": "wx5823bf96d3bd56c7",
+        })
+    }
+
+    fn query(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn wecom_verifies_documented_callback_url() {
+        let settings = settings();
+        let headers = HeaderMap::new();
+        let mut query = query(&[
+            ("msg_signature", "5c45ff5e21c57e6ad56bac8758b79b1d9ac89fd3"),
+            ("timestamp", "1409659589"),
+            ("nonce", "263014780"),
+            // The `+` signs arrive as spaces when the sender doesn't escape them.
+            ("echostr", "P9nAzCzyDtyTWESHep1vC5X9xho/qYX3Zpb4yKa9SKld1DsH3Iyt3tP3zNdtp 4RPcs8TgAE7OaBO FZXvnaqQ=="),
+        ]);
+        let callback = Callback { headers: &headers, query: &query, settings: Some(&settings) };
+        let err = WecomAdapter.verify_callback_url(&callback).expect("supported").unwrap_err();
+        assert_eq!(err.message, "timestamp parameter is missing or too far from now");
+
+        // The same request signed now, as a replay-free handshake would be.
+        let crypt = MsgCrypt::from_settings(Some(&settings), "corp_id").unwrap().unwrap();
+        let echostr = query["echostr"].replace(' ', "+");
+        let timestamp = now_timestamp().to_string();
+        query.insert("msg_signature".to_string(), crypt.signature(&timestamp, "263014780", &echostr));
+        query.insert("timestamp".to_string(), timestamp);
+        let callback = Callback { headers: &headers, query: &query, settings: Some(&settings) };
+        let echo = WecomAdapter.verify_callback_url(&callback).expect("supported").expect("verified");
+        assert_eq!(echo, "1616140317555161061");
+
+        query.insert("nonce".to_string(), "1".to_string());
+        let callback = Callback { headers: &headers, query: &query, settings: Some(&settings) };
+        let err = WecomAdapter.verify_callback_url(&callback).expect("supported").unwrap_err();
+        assert_eq!(err.message, "invalid msg_signature");
+    }
+
+    #[test]
+    fn wecom_opens_encrypted_app_callbacks() {
+        let settings = settings();
+        let crypt = MsgCrypt::from_settings(Some(&settings), "corp_id").unwrap().unwrap();
+        let message = "<xml><ToUserName><![CDATA[wx5823bf96d3bd56c7]]></ToUserName>\
+            <FromUserName><![CDATA[mycreate]]></FromUserName><CreateTime>1409659813</CreateTime>\
+            <MsgType><![CDATA[text]]></MsgType><Content><![CDATA[disk full on db-1]]></Content>\
+            <MsgId>4561255354251345929</MsgId><AgentID>218</AgentID></xml>";
+        let encrypted = crypt.encrypt(message, "");
+        let xml = format!("<xml><ToUserName><![CDATA[wx5823bf96d3bd56c7]]></ToUserName><Encrypt><![CDATA[{}]]></Encrypt><AgentID><![CDATA[218]]></AgentID></xml>", encrypted);
+        let body_for = || IngressBody {
+            value: json!({ "text": xml }),
+            bytes: xml.clone().into_bytes(),
+            content_type: "text/xml".to_string(),
+        };
+
+        let headers = HeaderMap::new();
+        let timestamp = now_timestamp().to_string();
+        let signature = crypt.signature(&timestamp, "1372623149", &encrypted);
+        let query = query(&[("msg_signature", &signature), ("timestamp", &timestamp), ("nonce", "1372623149")]);
+        let mut body = body_for();
+        let answer = WecomAdapter
+            .open_callback(&Callback { headers: &headers, query: &query, settings: Some(&settings) }, &mut body)
+            .expect("open");
+        assert_eq!(
+            answer,
+            CallbackAnswer::EventReply(CallbackReply { content_type: "text/plain", body: String::new() })
+        );
+        let uem = WecomAdapter.ingress_body_to_uem(&body).expect("uem");
+        assert_eq!(uem.id, "4561255354251345929");
+        assert_eq!(uem.timestamp, 1409659813);
+        assert_eq!(uem.markdown, "disk full on db-1");
+        assert_eq!(uem.meta["from_user"], "mycreate");
+        assert_eq!(uem.meta["agent_id"], "218");
+
+        let err = WecomAdapter
+            .open_callback(&Callback { headers: &headers, query: &query, settings: None }, &mut body_for())
+            .unwrap_err();
+        assert!(err.message.starts_with("encrypted WeCom callback but no wecom.token"));
+        let unsigned = self::query(&[("timestamp", "1409659813")]);
+        let err = WecomAdapter
+            .open_callback(&Callback { headers: &headers, query: &unsigned, settings: Some(&settings) }, &mut body_for())
+            .unwrap_err();
+        assert_eq!(err.message, "missing msg_signature parameter");
+    }
 }
@@ -0,0 +1,785 @@
+//! Declarative endpoint/target configuration (`--config router.yaml`).
+//!
+//! The file is loaded at startup and the DB is reconciled to match it:
+//! endpoints are matched by their stable `id`, targets by `name` within their
+//! endpoint. `${VAR}` in any string value is replaced from the environment.
+//! The same document is served by `GET /api/export` and accepted by
+//! `POST /api/import`.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::adapters::{egress_adapter, validate_allowed_platforms};
+use crate::db::Db;
+use crate::models::{CreateEndpointRequest, CreateTargetRequest, Endpoint, ImportItem, Target};
+use crate::script;
+use crate::secrets;
+use crate::slack_api::SlackApiConfig;
+
+/// Version of the config document format.
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RouterConfig {
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub endpoints: Vec<EndpointConfig>,
+}
+
+impl RouterConfig {
+    /// Copy with secrets masked as in the endpoints and targets API.
+    pub fn masked(mut self) -> Self {
+        for endpoint in &mut self.endpoints {
+            endpoint.ingress_config = endpoint.ingress_config.as_ref().map(secrets::mask_config);
+        }
+        for target in self.endpoints.iter_mut().flat_map(|endpoint| &mut endpoint.targets) {
+            target.url = secrets::mask_url(&target.url);
+            target.headers = target.headers.as_ref().map(secrets::mask_headers);
+            target.config = target.config.as_ref().map(secrets::mask_config);
+        }
+        self
+    }
+
+    /// Whether any endpoint or target still carries a masked value, which must not be
+    /// imported over the real secret.
+    pub fn contains_masked_secrets(&self) -> bool {
+        self.endpoints.iter().any(|endpoint| {
+            endpoint.ingress_config.as_ref().is_some_and(secrets::contains_mask)
+                || endpoint.targets.iter().any(|target| {
+                    target.url.ends_with(&format!("/{}", secrets::MASK))
+                        || target.headers.as_ref().is_some_and(secrets::contains_mask)
+                        || target.config.as_ref().is_some_and(secrets::contains_mask)
+                })
+        })
+    }
+}
+
+fn default_version() -> u32 {
+    CONFIG_VERSION
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EndpointConfig {
+    /// Stable id used in ingress URLs (`/ingress/:id/:platform`).
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub footer: Option<String>,
+    /// Per-platform callback settings, e.g. `lark.encrypt_key`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingress_config: Option<Value>,
+    /// Ingress platforms the endpoint accepts; any when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_platforms: Option<Vec<String>>,
+    /// Rhai script run on each ingress event, see `crate::script`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    #[serde(default)]
+    pub targets: Vec<TargetConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TargetConfig {
+    pub name: String,
+    pub kind: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<Value>,
+}
+
+/// How reconciliation treats endpoints and targets missing from the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConfigMode {
+    /// Delete endpoints, and targets of declared endpoints, that the file
+    /// doesn't declare.
+    Strict,
+    /// Only create and update; anything else in the DB is left alone.
+    Merge,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "failed to read config: {}", err),
+            ConfigError::Parse(message) => write!(f, "failed to parse config: {}", message),
+            ConfigError::Invalid(message) => write!(f, "invalid config: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// One change needed to make the DB match the config. Field lists name what
+/// differs without showing values, since those may be secrets.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    CreateEndpoint(EndpointConfig),
+    UpdateEndpoint { endpoint: EndpointConfig, fields: Vec<&'static str> },
+    DeleteEndpoint { id: String, name: String },
+    CreateTarget { endpoint_id: String, target: TargetConfig },
+    UpdateTarget { endpoint_id: String, target_id: String, target: TargetConfig, fields: Vec<&'static str> },
+    DeleteTarget { endpoint_id: String, target_id: String, name: String },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::CreateEndpoint(endpoint) => write!(f, "+ endpoint {} ({})", endpoint.id, endpoint.name),
+            Change::UpdateEndpoint { endpoint, fields } => {
+                write!(f, "~ endpoint {}: {}", endpoint.id, fields.join(", "))
+            }
+            Change::DeleteEndpoint { id, name } => write!(f, "- endpoint {} ({})", id, name),
+            Change::CreateTarget { endpoint_id, target } => {
+                write!(f, "+ target {}/{} ({})", endpoint_id, target.name, target.kind)
+            }
+            Change::UpdateTarget { endpoint_id, target, fields, .. } => {
+                write!(f, "~ target {}/{}: {}", endpoint_id, target.name, fields.join(", "))
+            }
+            Change::DeleteTarget { endpoint_id, name, .. } => write!(f, "- target {}/{}", endpoint_id, name),
+        }
+    }
+}
+
+/// Reads a YAML (or, by `.toml` extension, TOML) config file.
+pub fn load(path: &Path) -> Result<RouterConfig, ConfigError> {
+    let raw = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+    let is_toml = path.extension().and_then(|ext| ext.to_str()) == Some("toml");
+    parse(&raw, is_toml, &|name| std::env::var(name).ok())
+}
+
+/// Parses config text, interpolating `${VAR}` (or `${VAR:-default}`) in
+/// string values with `env`. Interpolation runs after parsing, so secrets
+/// can't change the document structure, and skips [`VERBATIM_FIELDS`].
+pub fn parse(raw: &str, is_toml: bool, env: &dyn Fn(&str) -> Option<String>) -> Result<RouterConfig, ConfigError> {
+    let mut value: Value = if is_toml {
+        toml::from_str(raw).map_err(|err| ConfigError::Parse(err.to_string()))?
+    } else {
+        serde_yaml::from_str(raw).map_err(|err| ConfigError::Parse(err.to_string()))?
+    };
+    if value.is_null() {
+        value = Value::Object(Default::default());
+    }
+
+    let mut missing = Vec::new();
+    interpolate_config(&mut value, env, &mut missing);
+    if !missing.is_empty() {
+        missing.sort();
+        missing.dedup();
+        return Err(ConfigError::Invalid(format!(
+            "environment variables not set: {}",
+            missing.join(", ")
+        )));
+    }
+
+    let config: RouterConfig = serde_json::from_value(value).map_err(|err| ConfigError::Parse(err.to_string()))?;
+    validate(&config)?;
+    Ok(config)
+}
+
+/// Endpoint fields taken verbatim: scripts use `${...}` in Rhai template
+/// literals, and banners and footers are text copied into every message.
+const VERBATIM_FIELDS: &[&str] = &["script", "banner", "footer"];
+
+fn interpolate_config(value: &mut Value, env: &dyn Fn(&str) -> Option<String>, missing: &mut Vec<String>) {
+    let Some(endpoints) = value.get_mut("endpoints").and_then(Value::as_array_mut) else {
+        return interpolate_value(value, env, missing);
+    };
+    for endpoint in endpoints {
+        match endpoint.as_object_mut() {
+            Some(fields) => fields
+                .iter_mut()
+                .filter(|(name, _)| !VERBATIM_FIELDS.contains(&name.as_str()))
+                .for_each(|(_, field)| interpolate_value(field, env, missing)),
+            None => interpolate_value(endpoint, env, missing),
+        }
+    }
+    if let Some(root) = value.as_object_mut() {
+        root.iter_mut()
+            .filter(|(name, _)| name.as_str() != "endpoints")
+            .for_each(|(_, field)| interpolate_value(field, env, missing));
+    }
+}
+
+fn interpolate_value(value: &mut Value, env: &dyn Fn(&str) -> Option<String>, missing: &mut Vec<String>) {
+    match value {
+        Value::String(text) => *text = interpolate(text, env, missing),
+        Value::Array(items) => items.iter_mut().for_each(|item| interpolate_value(item, env, missing)),
+        Value::Object(map) => map.values_mut().for_each(|item| interpolate_value(item, env, missing)),
+        _ => {}
+    }
+}
+
+/// Expands `${VAR}` and `${VAR:-default}`; `$$` is a literal `$`.
+fn interpolate(text: &str, env: &dyn Fn(&str) -> Option<String>, missing: &mut Vec<String>) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find('$') {
+        output.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            output.push('$');
+            rest = after;
+        } else if let Some(end) = after.strip_prefix('{').and_then(|body| body.find('}')) {
+            let expr = &after[1..end + 1];
+            let (name, default) = match expr.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (expr, None),
+            };
+            // Like the shell, `:-` also replaces a variable that is set but empty.
+            let value = match (env(name), default) {
+                (Some(value), Some(default)) if value.is_empty() => Some(default.to_string()),
+                (value, default) => value.or(default.map(String::from)),
+            };
+            match value {
+                Some(value) => output.push_str(&value),
+                None => missing.push(name.to_string()),
+            }
+            rest = &after[end + 2..];
+        } else {
+            output.push('$');
+            rest = after;
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+pub fn validate(config: &RouterConfig) -> Result<(), ConfigError> {
+    if config.version == 0 || config.version > CONFIG_VERSION {
+        return Err(ConfigError::Invalid(format!(
+            "unsupported version {} (expected {})",
+            config.version, CONFIG_VERSION
+        )));
+    }
+    let mut endpoint_ids = HashSet::new();
+    for endpoint in &config.endpoints {
+        let id = endpoint.id.as_str();
+        if id.is_empty() || !id.chars().all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.')) {
+            return Err(ConfigError::Invalid(format!(
+                "endpoint id {:?} must be non-empty and use only letters, digits, '-', '_' or '.'",
+                id
+            )));
+        }
+        if !endpoint_ids.insert(id) {
+            return Err(ConfigError::Invalid(format!("duplicate endpoint id {}", id)));
+        }
+        if let Some(platforms) = &endpoint.allowed_platforms {
+            validate_allowed_platforms(platforms)
+                .map_err(|message| ConfigError::Invalid(format!("endpoint {}: {}", id, message)))?;
+        }
+        if let Some(source) = &endpoint.script {
+            script::compile(source).map_err(|err| ConfigError::Invalid(format!("endpoint {}: {}", id, err)))?;
+        }
+
+        let mut target_names = HashSet::new();
+        for target in &endpoint.targets {
+            if !target_names.insert(target.name.as_str()) {
+                return Err(ConfigError::Invalid(format!(
+                    "duplicate target name {} in endpoint {}",
+                    target.name, id
+                )));
+            }
+            validate_target(target).map_err(|message| {
+                ConfigError::Invalid(format!("target {}/{}: {}", id, target.name, message))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn validate_target(target: &TargetConfig) -> Result<(), String> {
+    if egress_adapter(&target.kind).is_none() {
+        return Err(format!("unsupported kind {}", target.kind));
+    }
+    if target.kind == "slack_api" {
+        SlackApiConfig::from_value(target.config.as_ref())?;
+    }
+    Ok(())
+}
+
+/// Current endpoints and targets as a config document.
+pub async fn export(db: &Db) -> Result<RouterConfig, sqlx::Error> {
+    let mut endpoints = Vec::new();
+    for endpoint in db.list_endpoints().await? {
+        let targets = db
+            .list_targets(&endpoint.id)
+            .await?
+            .into_iter()
+            .map(|target| TargetConfig {
+                name: target.name,
+                kind: target.kind,
+                url: target.url,
+                headers: target.headers,
+                config: target.config,
+            })
+            .collect();
+        endpoints.push(EndpointConfig {
+            id: endpoint.id,
+            name: endpoint.name,
+            banner: endpoint.banner,
+            footer: endpoint.footer,
+            ingress_config: endpoint.ingress_config,
+            allowed_platforms: endpoint.allowed_platforms,
+            script: endpoint.script,
+            targets,
+        });
+    }
+    Ok(RouterConfig {
+        version: CONFIG_VERSION,
+        endpoints,
+    })
+}
+
+/// Replaces the ids in `config` for an import that doesn't keep them: each
+/// endpoint takes the id of an existing endpoint with the same name, or a
+/// new one.
+pub async fn assign_ids(db: &Db, config: &mut RouterConfig) -> Result<(), sqlx::Error> {
+    let mut existing = db.list_endpoints().await?;
+    for endpoint in &mut config.endpoints {
+        endpoint.id = match existing.iter().position(|current| current.name == endpoint.name) {
+            Some(index) => existing.remove(index).id,
+            None => Uuid::new_v4().to_string(),
+        };
+    }
+    Ok(())
+}
+
+/// Computes the changes that make the DB match `config`.
+pub async fn plan(db: &Db, config: &RouterConfig, mode: ConfigMode) -> Result<Vec<Change>, sqlx::Error> {
+    let existing = db.list_endpoints().await?;
+    let mut changes = Vec::new();
+
+    for endpoint in &config.endpoints {
+        let Some(current) = existing.iter().find(|current| current.id == endpoint.id) else {
+            changes.push(Change::CreateEndpoint(endpoint.clone()));
+            changes.extend(endpoint.targets.iter().map(|target| Change::CreateTarget {
+                endpoint_id: endpoint.id.clone(),
+                target: target.clone(),
+            }));
+            continue;
+        };
+
+        let fields = endpoint_diff(current, endpoint);
+        if !fields.is_empty() {
+            changes.push(Change::UpdateEndpoint {
+                endpoint: endpoint.clone(),
+                fields,
+            });
+        }
+
+        let targets = db.list_targets(&endpoint.id).await?;
+        let mut matched = HashSet::new();
+        for target in &endpoint.targets {
+            match targets
+                .iter()
+                .find(|current| current.name == target.name && !matched.contains(&current.id))
+            {
+                Some(current) => {
+                    matched.insert(current.id.clone());
+                    let fields = target_diff(current, target);
+                    if !fields.is_empty() {
+                        changes.push(Change::UpdateTarget {
+                            endpoint_id: endpoint.id.clone(),
+                            target_id: current.id.clone(),
+                            target: target.clone(),
+                            fields,
+                        });
+                    }
+                }
+                None => changes.push(Change::CreateTarget {
+                    endpoint_id: endpoint.id.clone(),
+                    target: target.clone(),
+                }),
+            }
+        }
+        if mode == ConfigMode::Strict {
+            changes.extend(
+                targets
+                    .iter()
+                    .filter(|current| !matched.contains(&current.id))
+                    .map(|current| Change::DeleteTarget {
+                        endpoint_id: endpoint.id.clone(),
+                        target_id: current.id.clone(),
+                        name: current.name.clone(),
+                    }),
+            );
+        }
+    }
+
+    if mode == ConfigMode::Strict {
+        changes.extend(
+            existing
+                .iter()
+                .filter(|current| !config.endpoints.iter().any(|endpoint| endpoint.id == current.id))
+                .map(|current| Change::DeleteEndpoint {
+                    id: current.id.clone(),
+                    name: current.name.clone(),
+                }),
+        );
+    }
+    Ok(changes)
+}
+
+/// Applies planned changes in order, in one transaction: either every change
+/// is applied or none is.
+pub async fn apply(db: &Db, changes: &[Change]) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    for change in changes {
+        match change {
+            Change::CreateEndpoint(endpoint) => {
+                db.create_endpoint_with_id_in(&mut *tx, &endpoint.id, endpoint_request(endpoint))
+                    .await?;
+            }
+            Change::UpdateEndpoint { endpoint, .. } => {
+                db.replace_endpoint(&mut *tx, &endpoint.id, &endpoint_request(endpoint))
+                    .await?;
+            }
+            Change::DeleteEndpoint { id, .. } => {
+                db.delete_endpoint_in(&mut *tx, id).await?;
+            }
+            Change::CreateTarget { endpoint_id, target } => {
+                db.create_target_in(&mut *tx, endpoint_id, target_request(target)).await?;
+            }
+            Change::UpdateTarget { target_id, target, .. } => {
+                db.replace_target(&mut *tx, target_id, &target_request(target)).await?;
+            }
+            Change::DeleteTarget { target_id, .. } => {
+                db.delete_target_in(&mut *tx, target_id).await?;
+            }
+        }
+    }
+    tx.commit().await
+}
+
+/// One result per endpoint and target in `config` (`created`, `updated` or
+/// `skipped` when unchanged), followed by the deletions.
+pub fn report(config: &RouterConfig, changes: &[Change]) -> Vec<ImportItem> {
+    let mut items = Vec::new();
+    for endpoint in &config.endpoints {
+        let change = changes.iter().find(|change| match change {
+            Change::CreateEndpoint(desired) | Change::UpdateEndpoint { endpoint: desired, .. } => {
+                desired.id == endpoint.id
+            }
+            _ => false,
+        });
+        let (action, fields) = match change {
+            Some(Change::CreateEndpoint(_)) => ("created", Vec::new()),
+            Some(Change::UpdateEndpoint { fields, .. }) => ("updated", fields.clone()),
+            _ => ("skipped", Vec::new()),
+        };
+        items.push(ImportItem::new("endpoint", &endpoint.id, &endpoint.name, action, fields));
+
+        for target in &endpoint.targets {
+            let change = changes.iter().find(|change| match change {
+                Change::CreateTarget { endpoint_id, target: desired }
+                | Change::UpdateTarget { endpoint_id, target: desired, .. } => {
+                    *endpoint_id == endpoint.id && desired.name == target.name
+                }
+                _ => false,
+            });
+            let (action, fields) = match change {
+                Some(Change::CreateTarget { .. }) => ("created", Vec::new()),
+                Some(Change::UpdateTarget { fields, .. }) => ("updated", fields.clone()),
+                _ => ("skipped", Vec::new()),
+            };
+            items.push(ImportItem::new("target", &endpoint.id, &target.name, action, fields));
+        }
+    }
+    for change in changes {
+        match change {
+            Change::DeleteEndpoint { id, name } => {
+                items.push(ImportItem::new("endpoint", id, name, "deleted", Vec::new()));
+            }
+            Change::DeleteTarget { endpoint_id, name, .. } => {
+                items.push(ImportItem::new("target", endpoint_id, name, "deleted", Vec::new()));
+            }
+            _ => {}
+        }
+    }
+    items
+}
+
+fn endpoint_request(endpoint: &EndpointConfig) -> CreateEndpointRequest {
+    CreateEndpointRequest {
+        name: endpoint.name.clone(),
+        banner: endpoint.banner.clone(),
+        footer: endpoint.footer.clone(),
+        ingress_config: endpoint.ingress_config.clone(),
+        allowed_platforms: endpoint.allowed_platforms.clone(),
+        script: endpoint.script.clone(),
+    }
+}
+
+fn target_request(target: &TargetConfig) -> CreateTargetRequest {
+    CreateTargetRequest {
+        name: target.name.clone(),
+        kind: target.kind.clone(),
+        url: target.url.clone(),
+        headers: target.headers.clone(),
+        config: target.config.clone(),
+    }
+}
+
+fn endpoint_diff(current: &Endpoint, desired: &EndpointConfig) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if current.name != desired.name {
+        fields.push("name");
+    }
+    if current.banner != desired.banner {
+        fields.push("banner");
+    }
+    if current.footer != desired.footer {
+        fields.push("footer");
+    }
+    if current.ingress_config != desired.ingress_config {
+        fields.push("ingress_config");
+    }
+    if current.allowed_platforms != desired.allowed_platforms {
+        fields.push("allowed_platforms");
+    }
+    if current.script != desired.script {
+        fields.push("script");
+    }
+    fields
+}
+
+fn target_diff(current: &Target, desired: &TargetConfig) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if current.kind != desired.kind {
+        fields.push("kind");
+    }
+    if current.url != desired.url {
+        fields.push("url");
+    }
+    if current.headers != desired.headers {
+        fields.push("headers");
+    }
+    if current.config != desired.config {
+        fields.push("config");
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+endpoints:
+  - id: alerts
+    name: Alerts
+    banner: "**Prod**"
+    targets:
+      - name: ops
+        kind: slack
+        url: ${SLACK_URL}
+      - name: audit
+        kind: http
+        url: https://audit.example.com/hook
+        headers:
+          Authorization: Bearer ${AUDIT_TOKEN:-dev-token}
+"#;
+
+    fn env(name: &str) -> Option<String> {
+        (name == "SLACK_URL").then(|| "https://hooks.slack.com/services/T/B/x".to_string())
+    }
+
+    #[test]
+    fn interpolates_environment_variables() {
+        let config = parse(CONFIG, false, &env).expect("config");
+        let targets = &config.endpoints[0].targets;
+        assert_eq!(targets[0].url, "https://hooks.slack.com/services/T/B/x");
+        assert_eq!(targets[1].headers.as_ref().unwrap()["Authorization"], "Bearer dev-token");
+
+        let err = parse(CONFIG, false, &|_| None).unwrap_err();
+        assert_eq!(err.to_string(), "invalid config: environment variables not set: SLACK_URL");
+        let mut missing = Vec::new();
+        assert_eq!(interpolate("cost: $$5 ${A}", &|_| Some("x".to_string()), &mut missing), "cost: $5 x");
+    }
+
+    #[test]
+    fn keeps_scripts_banners_and_footers_verbatim() {
+        let raw = r#"
+endpoints:
+  - id: alerts
+    name: Alerts
+    banner: "cost: $$5"
+    footer: "${NOT_AN_ENV_VAR}"
+    script: |
+      event.title = `[${event.raw.severity}] ${event.title}`;
+      event.markdown += "$$";
+    targets:
+      - name: ops
+        kind: slack
+        url: ${SLACK_URL}
+"#;
+        let config = parse(raw, false, &env).expect("config");
+        let endpoint = &config.endpoints[0];
+        assert_eq!(endpoint.banner.as_deref(), Some("cost: $$5"));
+        assert_eq!(endpoint.footer.as_deref(), Some("${NOT_AN_ENV_VAR}"));
+        assert_eq!(
+            endpoint.script.as_deref(),
+            Some("event.title = `[${event.raw.severity}] ${event.title}`;\nevent.markdown += \"$$\";\n")
+        );
+        assert_eq!(endpoint.targets[0].url, "https://hooks.slack.com/services/T/B/x");
+    }
+
+    #[test]
+    fn parses_toml_and_rejects_invalid_targets() {
+        let toml = r#"
+[[endpoints]]
+id = "alerts"
+name = "Alerts"
+
+[[endpoints.targets]]
+name = "ops"
+kind = "pager"
+url = "https://example.com"
+"#;
+        let err = parse(toml, true, &env).unwrap_err();
+        assert_eq!(err.to_string(), "invalid config: target alerts/ops: unsupported kind pager");
+
+        let err = parse("endpoints:\n  - id: alerts\n    name: Alerts\n    allowed_platforms: [feishu]\n", false, &env)
+            .unwrap_err();
+        assert!(err.to_string().starts_with("invalid config: endpoint alerts: unknown platform \"feishu\""));
+
+        let err = parse("endpoints:\n  - id: alerts\n    name: Alerts\n    script: \"event.title = \"\n", false, &env)
+            .unwrap_err();
+        assert!(err.to_string().starts_with("invalid config: endpoint alerts: invalid script:"));
+    }
+
+    #[tokio::test]
+    async fn reconciles_db_in_strict_and_merge_modes() {
+        let db = Db::connect(":memory:").await.expect("connect");
+        let manual = db
+            .create_endpoint(CreateEndpointRequest {
+                name: "Manual".to_string(),
+                banner: None,
+                footer: None,
+                ingress_config: None,
+                allowed_platforms: None,
+                script: None,
+            })
+            .await
+            .expect("endpoint");
+
+        let config = parse(CONFIG, false, &env).expect("config");
+        let changes = plan(&db, &config, ConfigMode::Merge).await.expect("plan");
+        let diff: Vec<String> = changes.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            diff,
+            vec!["+ endpoint alerts (Alerts)", "+ target alerts/ops (slack)", "+ target alerts/audit (http)"]
+        );
+        apply(&db, &changes).await.expect("apply");
+        assert!(plan(&db, &config, ConfigMode::Merge).await.expect("plan").is_empty());
+
+        let mut changed = config.clone();
+        changed.endpoints[0].banner = None;
+        changed.endpoints[0].targets.remove(1);
+        changed.endpoints[0].targets[0].url = "https://hooks.slack.com/services/T/B/y".to_string();
+        let changes = plan(&db, &changed, ConfigMode::Strict).await.expect("plan");
+        let diff: Vec<String> = changes.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            diff,
+            vec![
+                "~ endpoint alerts: banner".to_string(),
+                "~ target alerts/ops: url".to_string(),
+                "- target alerts/audit".to_string(),
+                format!("- endpoint {} (Manual)", manual.id),
+            ]
+        );
+        apply(&db, &changes).await.expect("apply");
+        assert!(plan(&db, &changed, ConfigMode::Strict).await.expect("plan").is_empty());
+
+        let endpoint = db.get_endpoint("alerts").await.expect("get").expect("endpoint");
+        assert_eq!(endpoint.banner, None);
+        assert_eq!(db.list_endpoints().await.expect("list").len(), 1);
+
+        // A failing change rolls back the ones before it.
+        let failing = [
+            Change::DeleteEndpoint {
+                id: "alerts".to_string(),
+                name: "Alerts".to_string(),
+            },
+            Change::CreateTarget {
+                endpoint_id: "missing".to_string(),
+                target: changed.endpoints[0].targets[0].clone(),
+            },
+        ];
+        assert!(apply(&db, &failing).await.is_err());
+        assert!(db.get_endpoint("alerts").await.expect("get").is_some());
+        assert!(plan(&db, &changed, ConfigMode::Strict).await.expect("plan").is_empty());
+    }
+
+    #[tokio::test]
+    async fn export_imports_into_another_db() {
+        let staging = Db::connect(":memory:").await.expect("connect");
+        apply(&staging, &plan(&staging, &parse(CONFIG, false, &env).unwrap(), ConfigMode::Merge).await.unwrap())
+            .await
+            .expect("apply");
+        let mut document = export(&staging).await.expect("export");
+        document.endpoints[0].id = "staging-id".to_string();
+
+        let production = Db::connect(":memory:").await.expect("connect");
+        production
+            .create_endpoint_with_id(
+                "prod-id",
+                CreateEndpointRequest {
+                    name: "Alerts".to_string(),
+                    banner: None,
+                    footer: None,
+                    ingress_config: None,
+                    allowed_platforms: None,
+                    script: None,
+                },
+            )
+            .await
+            .expect("endpoint");
+        production
+            .create_target(
+                "prod-id",
+                target_request(&document.endpoints[0].targets[0]),
+            )
+            .await
+            .expect("target");
+        production
+            .create_endpoint(CreateEndpointRequest {
+                name: "Legacy".to_string(),
+                banner: None,
+                footer: None,
+                ingress_config: None,
+                allowed_platforms: None,
+                script: None,
+            })
+            .await
+            .expect("endpoint");
+
+        assign_ids(&production, &mut document).await.expect("assign");
+        assert_eq!(document.endpoints[0].id, "prod-id");
+        let changes = plan(&production, &document, ConfigMode::Strict).await.expect("plan");
+        let mut items = report(&document, &changes);
+        items.last_mut().unwrap().endpoint_id = "<legacy>".to_string();
+        insta::assert_yaml_snapshot!(items);
+    }
+}
@@ -1,19 +1,39 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
+use std::sync::Arc;
 
 use serde_json::Value;
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
-use sqlx::{QueryBuilder, Row, Sqlite};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::{QueryBuilder, Row, Sqlite, SqliteExecutor, Transaction};
 use uuid::Uuid;
 
+use crate::metrics::db_timer;
 use crate::models::{
-    CreateEndpointRequest, CreateTargetRequest, DeliveryRecord, Endpoint, EventRecord, Target,
-    UpdateEndpointRequest, UemEvent,
+    CreateEndpointRequest, CreatePersonRequest, CreateTargetRequest, DeliveryOutcome, DeliveryRecord, Endpoint,
+    EventFilter, EventRecord, DeliveryFilter, Mapping, Mention, Person, Target, UpdateEndpointRequest, UpdatePersonRequest, UemEvent,
 };
+use crate::secrets::{self, SecretKeys};
+
+/// Domain tables reported by the status API; FTS shadow tables and
+/// migration bookkeeping are left out.
+const STATUS_TABLES: [&str; 9] = [
+    "deliveries",
+    "endpoints",
+    "events",
+    "mappings",
+    "media",
+    "people",
+    "person_handles",
+    "target_threads",
+    "targets",
+];
 
 #[derive(Clone)]
 pub struct Db {
     pool: SqlitePool,
+    /// Encrypts target `url`, `headers` and `config` and endpoint
+    /// `ingress_config` when set.
+    secrets: Option<Arc<SecretKeys>>,
 }
 
 impl Db {
@@ -39,7 +59,7 @@ impl Db {
         } else {
             SqlitePoolOptions::new().connect_with(options).await?
         };
-        let db = Self { pool };
+        let db = Self { pool, secrets: None };
         db.init().await?;
         Ok(db)
     }
@@ -49,22 +69,197 @@ impl Db {
         Ok(())
     }
 
+    pub fn with_secrets(self, keys: SecretKeys) -> Self {
+        Self {
+            secrets: Some(Arc::new(keys)),
+            ..self
+        }
+    }
+
+    fn seal(&self, value: String) -> String {
+        match &self.secrets {
+            Some(keys) => keys.encrypt(&value),
+            None => value,
+        }
+    }
+
+    fn unseal(&self, value: String) -> Result<String, sqlx::Error> {
+        match &self.secrets {
+            Some(keys) => keys.decrypt(&value).map_err(|err| sqlx::Error::Decode(err.into())),
+            None if secrets::is_encrypted(&value) => Err(sqlx::Error::Decode(
+                "secrets are encrypted but no secret key is configured".into(),
+            )),
+            None => Ok(value),
+        }
+    }
+
+    fn unseal_json(&self, raw: Option<String>) -> Result<Option<Value>, sqlx::Error> {
+        Ok(match raw {
+            Some(raw) => serde_json::from_str(&self.unseal(raw)?).ok(),
+            None => None,
+        })
+    }
+
+    fn target_from_row(&self, row: SqliteRow) -> Result<Target, sqlx::Error> {
+        Ok(Target {
+            id: row.get("id"),
+            endpoint_id: row.get("endpoint_id"),
+            name: row.get("name"),
+            kind: row.get("kind"),
+            url: self.unseal(row.get("url"))?,
+            headers: self.unseal_json(row.get("headers"))?,
+            config: self.unseal_json(row.get("config"))?,
+            created_at: row.get("created_at"),
+        })
+    }
+
+    fn endpoint_from_row(&self, row: SqliteRow) -> Result<Endpoint, sqlx::Error> {
+        Ok(Endpoint {
+            id: row.get("id"),
+            name: row.get("name"),
+            banner: row.get("banner"),
+            footer: row.get("footer"),
+            ingress_config: self.unseal_json(row.get("ingress_config"))?,
+            allowed_platforms: row
+                .get::<Option<String>, _>("allowed_platforms")
+                .and_then(|value| serde_json::from_str(&value).ok()),
+            script: row.get("script"),
+            created_at: row.get("created_at"),
+        })
+    }
+
+    /// Brings every stored secret (target `url`, `headers` and `config`,
+    /// endpoint `ingress_config`) up to date with the configured keys:
+    /// plaintext is encrypted and values under a previous key are re-wrapped
+    /// with the current one. Without keys, fails if anything is already
+    /// encrypted. Returns the number of rows rewritten.
+    pub async fn seal_secrets(&self) -> Result<u64, sqlx::Error> {
+        const SEALED_COLUMNS: [(&str, &[&str]); 2] =
+            [("targets", &["url", "headers", "config"]), ("endpoints", &["ingress_config"])];
+
+        let mut rewritten = 0;
+        for (table, columns) in SEALED_COLUMNS {
+            let rows = sqlx::query(&format!("SELECT id, {} FROM {}", columns.join(", "), table))
+                .fetch_all(&self.pool)
+                .await?;
+            for row in rows {
+                let id: String = row.get("id");
+                let values: Vec<Option<String>> = columns.iter().map(|column| row.get(*column)).collect();
+                let Some(keys) = &self.secrets else {
+                    if values.iter().flatten().any(|value| secrets::is_encrypted(value)) {
+                        return Err(sqlx::Error::Decode(
+                            "secrets are encrypted but no secret key is configured".into(),
+                        ));
+                    }
+                    continue;
+                };
+
+                let mut changed = false;
+                let mut sealed = Vec::with_capacity(values.len());
+                for value in values {
+                    let resealed = match &value {
+                        Some(value) => keys.reseal(value).map_err(|err| sqlx::Error::Decode(err.into()))?,
+                        None => None,
+                    };
+                    changed |= resealed.is_some();
+                    sealed.push(resealed.or(value));
+                }
+                if changed {
+                    let assignments: Vec<String> = columns.iter().map(|column| format!("{} = ?", column)).collect();
+                    let sql = format!("UPDATE {} SET {} WHERE id = ?", table, assignments.join(", "));
+                    let mut query = sqlx::query(&sql);
+                    for value in sealed {
+                        query = query.bind(value);
+                    }
+                    query.bind(&id).execute(&self.pool).await?;
+                    rewritten += 1;
+                }
+            }
+        }
+        Ok(rewritten)
+    }
+
+    /// Checks that the database answers queries.
+    pub async fn ping(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Checks that a write lock can be taken, which fails when the file is
+    /// read-only or another writer holds it. Nothing is written: the update
+    /// matches no rows but still takes the lock, and the transaction rolls
+    /// back even if this future is dropped halfway.
+    pub async fn check_writable(&self) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE _sqlx_migrations SET version = version WHERE 0")
+            .execute(&mut *tx)
+            .await?;
+        tx.rollback().await
+    }
+
+    /// Versions of embedded migrations that haven't been applied successfully.
+    pub async fn pending_migrations(&self) -> Result<Vec<i64>, sqlx::Error> {
+        let applied: Vec<i64> = sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success = 1")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(sqlx::migrate!()
+            .iter()
+            .map(|migration| migration.version)
+            .filter(|version| !applied.contains(version))
+            .collect())
+    }
+
+    pub async fn size_bytes(&self) -> Result<i64, sqlx::Error> {
+        let page_count: i64 = sqlx::query_scalar("PRAGMA page_count").fetch_one(&self.pool).await?;
+        let page_size: i64 = sqlx::query_scalar("PRAGMA page_size").fetch_one(&self.pool).await?;
+        Ok(page_count * page_size)
+    }
+
+    /// Row count for each of [`STATUS_TABLES`].
+    pub async fn table_counts(&self) -> Result<BTreeMap<String, i64>, sqlx::Error> {
+        let mut counts = BTreeMap::new();
+        for table in STATUS_TABLES {
+            // SQLite counts through the smallest index, so `events` and the
+            // blob-heavy `media` are counted without reading their rows.
+            let query = format!("SELECT COUNT(*) FROM {}", table);
+            let count: i64 = sqlx::query_scalar(&query).fetch_one(&self.pool).await?;
+            counts.insert(table.to_string(), count);
+        }
+        Ok(counts)
+    }
+
+    /// Starts a transaction for the methods that take an executor. It commits
+    /// only when asked and rolls back when dropped.
+    pub async fn begin(&self) -> Result<Transaction<'static, Sqlite>, sqlx::Error> {
+        self.pool.begin().await
+    }
+
     pub async fn create_target(&self, endpoint_id: &str, req: CreateTargetRequest) -> Result<Target, sqlx::Error> {
+        self.create_target_in(&self.pool, endpoint_id, req).await
+    }
+
+    /// [`Db::create_target`] on `executor`, e.g. a transaction.
+    pub async fn create_target_in(
+        &self,
+        executor: impl SqliteExecutor<'_>,
+        endpoint_id: &str,
+        req: CreateTargetRequest,
+    ) -> Result<Target, sqlx::Error> {
         let id = Uuid::new_v4().to_string();
         let created_at = now_timestamp();
-        let headers = req.headers.map(|value| value.to_string());
         sqlx::query(
-            "INSERT INTO targets (id, endpoint_id, name, kind, url, headers, created_at)
-             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            "INSERT INTO targets (id, endpoint_id, name, kind, url, headers, config, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
         )
         .bind(&id)
         .bind(endpoint_id)
         .bind(&req.name)
         .bind(&req.kind)
-        .bind(&req.url)
-        .bind(headers.clone())
+        .bind(self.seal(req.url.clone()))
+        .bind(req.headers.as_ref().map(|value| self.seal(value.to_string())))
+        .bind(req.config.as_ref().map(|value| self.seal(value.to_string())))
         .bind(created_at)
-        .execute(&self.pool)
+        .execute(executor)
         .await?;
 
         Ok(Target {
@@ -73,40 +268,54 @@ impl Db {
             name: req.name,
             kind: req.kind,
             url: req.url,
-            headers: headers.and_then(|raw| serde_json::from_str(&raw).ok()),
+            headers: req.headers,
+            config: req.config,
             created_at,
         })
     }
 
     pub async fn list_targets(&self, endpoint_id: &str) -> Result<Vec<Target>, sqlx::Error> {
+        let _timer = db_timer("list_targets");
         let rows = sqlx::query(
-            "SELECT id, endpoint_id, name, kind, url, headers, created_at
+            "SELECT id, endpoint_id, name, kind, url, headers, config, created_at
              FROM targets WHERE endpoint_id = ? ORDER BY created_at DESC",
         )
         .bind(endpoint_id)
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(rows
-            .into_iter()
-            .map(|row| Target {
-                id: row.get("id"),
-                endpoint_id: row.get("endpoint_id"),
-                name: row.get("name"),
-                kind: row.get("kind"),
-                url: row.get("url"),
-                headers: row
-                    .get::<Option<String>, _>("headers")
-                    .and_then(|raw| serde_json::from_str(&raw).ok()),
-                created_at: row.get("created_at"),
-            })
-            .collect())
+        rows.into_iter().map(|row| self.target_from_row(row)).collect()
+    }
+
+    /// Sets every field of the target.
+    pub async fn replace_target(
+        &self,
+        executor: impl SqliteExecutor<'_>,
+        id: &str,
+        req: &CreateTargetRequest,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE targets SET name = ?, kind = ?, url = ?, headers = ?, config = ? WHERE id = ?",
+        )
+        .bind(&req.name)
+        .bind(&req.kind)
+        .bind(self.seal(req.url.clone()))
+        .bind(req.headers.as_ref().map(|value| self.seal(value.to_string())))
+        .bind(req.config.as_ref().map(|value| self.seal(value.to_string())))
+        .bind(id)
+        .execute(executor)
+        .await?;
+        Ok(result.rows_affected())
     }
 
     pub async fn delete_target(&self, id: &str) -> Result<u64, sqlx::Error> {
+        self.delete_target_in(&self.pool, id).await
+    }
+
+    pub async fn delete_target_in(&self, executor: impl SqliteExecutor<'_>, id: &str) -> Result<u64, sqlx::Error> {
         let result = sqlx::query("DELETE FROM targets WHERE id = ?")
             .bind(id)
-            .execute(&self.pool)
+            .execute(executor)
             .await?;
         Ok(result.rows_affected())
     }
@@ -114,43 +323,54 @@ impl Db {
     #[allow(dead_code)]
     pub async fn get_target(&self, id: &str) -> Result<Option<Target>, sqlx::Error> {
         let row = sqlx::query(
-            "SELECT id, endpoint_id, name, kind, url, headers, created_at
+            "SELECT id, endpoint_id, name, kind, url, headers, config, created_at
              FROM targets WHERE id = ?",
         )
         .bind(id)
         .fetch_optional(&self.pool)
         .await?;
 
-        Ok(row.map(|row| Target {
-            id: row.get("id"),
-            endpoint_id: row.get("endpoint_id"),
-            name: row.get("name"),
-            kind: row.get("kind"),
-            url: row.get("url"),
-            headers: row
-                .get::<Option<String>, _>("headers")
-                .and_then(|raw| serde_json::from_str(&raw).ok()),
-            created_at: row.get("created_at"),
-        }))
+        row.map(|row| self.target_from_row(row)).transpose()
     }
 
     pub async fn create_endpoint(
         &self,
         req: CreateEndpointRequest,
     ) -> Result<Endpoint, sqlx::Error> {
-        let id = Uuid::new_v4().to_string();
+        self.create_endpoint_with_id(&Uuid::new_v4().to_string(), req).await
+    }
+
+    /// Creates an endpoint with a caller-chosen id, e.g. from a config file.
+    pub async fn create_endpoint_with_id(
+        &self,
+        id: &str,
+        req: CreateEndpointRequest,
+    ) -> Result<Endpoint, sqlx::Error> {
+        self.create_endpoint_with_id_in(&self.pool, id, req).await
+    }
+
+    pub async fn create_endpoint_with_id_in(
+        &self,
+        executor: impl SqliteExecutor<'_>,
+        id: &str,
+        req: CreateEndpointRequest,
+    ) -> Result<Endpoint, sqlx::Error> {
+        let id = id.to_string();
         let created_at = now_timestamp();
 
         sqlx::query(
-            "INSERT INTO endpoints (id, name, banner, footer, created_at)
-             VALUES (?, ?, ?, ?, ?)",
+            "INSERT INTO endpoints (id, name, banner, footer, ingress_config, allowed_platforms, script, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
         )
         .bind(&id)
         .bind(&req.name)
         .bind(&req.banner)
         .bind(&req.footer)
+        .bind(req.ingress_config.as_ref().map(|value| self.seal(value.to_string())))
+        .bind(platforms_json(&req.allowed_platforms))
+        .bind(&req.script)
         .bind(created_at)
-        .execute(&self.pool)
+        .execute(executor)
         .await?;
 
         Ok(Endpoint {
@@ -158,46 +378,35 @@ impl Db {
             name: req.name,
             banner: req.banner,
             footer: req.footer,
+            ingress_config: req.ingress_config,
+            allowed_platforms: req.allowed_platforms,
+            script: req.script,
             created_at,
         })
     }
 
     pub async fn list_endpoints(&self) -> Result<Vec<Endpoint>, sqlx::Error> {
         let rows = sqlx::query(
-            "SELECT id, name, banner, footer, created_at
+            "SELECT id, name, banner, footer, ingress_config, allowed_platforms, script, created_at
              FROM endpoints ORDER BY created_at DESC",
         )
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(rows
-            .into_iter()
-            .map(|row| Endpoint {
-                id: row.get("id"),
-                name: row.get("name"),
-                banner: row.get("banner"),
-                footer: row.get("footer"),
-                created_at: row.get("created_at"),
-            })
-            .collect())
+        rows.into_iter().map(|row| self.endpoint_from_row(row)).collect()
     }
 
     pub async fn get_endpoint(&self, id: &str) -> Result<Option<Endpoint>, sqlx::Error> {
+        let _timer = db_timer("get_endpoint");
         let row = sqlx::query(
-            "SELECT id, name, banner, footer, created_at
+            "SELECT id, name, banner, footer, ingress_config, allowed_platforms, script, created_at
              FROM endpoints WHERE id = ?",
         )
         .bind(id)
         .fetch_optional(&self.pool)
         .await?;
 
-        Ok(row.map(|row| Endpoint {
-            id: row.get("id"),
-            name: row.get("name"),
-            banner: row.get("banner"),
-            footer: row.get("footer"),
-            created_at: row.get("created_at"),
-        }))
+        row.map(|row| self.endpoint_from_row(row)).transpose()
     }
 
     pub async fn update_endpoint(
@@ -219,13 +428,26 @@ impl Db {
         if req.footer.is_some() {
             endpoint.footer = req.footer;
         }
+        if req.ingress_config.is_some() {
+            endpoint.ingress_config = req.ingress_config;
+        }
+        if req.allowed_platforms.is_some() {
+            endpoint.allowed_platforms = req.allowed_platforms;
+        }
+        if req.script.is_some() {
+            endpoint.script = req.script;
+        }
 
         sqlx::query(
-            "UPDATE endpoints SET name = ?, banner = ?, footer = ? WHERE id = ?",
+            "UPDATE endpoints SET name = ?, banner = ?, footer = ?, ingress_config = ?, allowed_platforms = ?,
+             script = ? WHERE id = ?",
         )
         .bind(&endpoint.name)
         .bind(&endpoint.banner)
         .bind(&endpoint.footer)
+        .bind(endpoint.ingress_config.as_ref().map(|value| self.seal(value.to_string())))
+        .bind(platforms_json(&endpoint.allowed_platforms))
+        .bind(&endpoint.script)
         .bind(&endpoint.id)
         .execute(&self.pool)
         .await?;
@@ -233,20 +455,50 @@ impl Db {
         Ok(Some(endpoint))
     }
 
+    /// Sets every field of the endpoint, clearing optional ones that are
+    /// `None` (unlike [`Db::update_endpoint`]).
+    pub async fn replace_endpoint(
+        &self,
+        executor: impl SqliteExecutor<'_>,
+        id: &str,
+        req: &CreateEndpointRequest,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE endpoints SET name = ?, banner = ?, footer = ?, ingress_config = ?, allowed_platforms = ?,
+             script = ? WHERE id = ?",
+        )
+            .bind(&req.name)
+            .bind(&req.banner)
+            .bind(&req.footer)
+            .bind(req.ingress_config.as_ref().map(|value| self.seal(value.to_string())))
+            .bind(platforms_json(&req.allowed_platforms))
+            .bind(&req.script)
+            .bind(id)
+            .execute(executor)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
     pub async fn delete_endpoint(&self, id: &str) -> Result<u64, sqlx::Error> {
+        self.delete_endpoint_in(&self.pool, id).await
+    }
+
+    pub async fn delete_endpoint_in(&self, executor: impl SqliteExecutor<'_>, id: &str) -> Result<u64, sqlx::Error> {
         let result = sqlx::query("DELETE FROM endpoints WHERE id = ?")
             .bind(id)
-            .execute(&self.pool)
+            .execute(executor)
             .await?;
         Ok(result.rows_affected())
     }
 
+    #[tracing::instrument(skip_all)]
     pub async fn insert_event(
         &self,
         endpoint_id: &str,
         platform: &str,
         event: &UemEvent,
     ) -> Result<EventRecord, sqlx::Error> {
+        let _timer = db_timer("insert_event");
         let created_at = now_timestamp();
         let raw = event.raw.to_string();
 
@@ -276,24 +528,27 @@ impl Db {
         })
     }
 
+    #[tracing::instrument(skip_all)]
     pub async fn insert_delivery(
         &self,
         event_id: &str,
-        target_id: &str,
-        status: &str,
-        response_code: Option<u16>,
-        error: Option<String>,
+        request_id: Option<&str>,
+        outcome: &DeliveryOutcome,
     ) -> Result<(), sqlx::Error> {
+        let _timer = db_timer("insert_delivery");
         sqlx::query(
-            "INSERT INTO deliveries (id, event_id, target_id, status, response_code, error, created_at)
-             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            "INSERT INTO deliveries (id, event_id, target_id, status, response_code, error, part, parts, request_id, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
         )
         .bind(Uuid::new_v4().to_string())
         .bind(event_id)
-        .bind(target_id)
-        .bind(status)
-        .bind(response_code.map(i64::from))
-        .bind(error)
+        .bind(&outcome.target_id)
+        .bind(&outcome.status)
+        .bind(outcome.response_code.map(i64::from))
+        .bind(&outcome.error)
+        .bind(outcome.part)
+        .bind(outcome.parts)
+        .bind(request_id)
         .bind(now_timestamp())
         .execute(&self.pool)
         .await?;
@@ -302,42 +557,57 @@ impl Db {
 
     pub async fn list_events(
         &self,
-        endpoint_id: Option<&str>,
+        filter: &EventFilter,
         page: Option<i64>,
         page_size: Option<i64>,
     ) -> Result<Vec<EventRecord>, sqlx::Error> {
+        let _timer = db_timer("list_events");
         let page = page.unwrap_or(1).max(1);
         let page_size = page_size.unwrap_or(50).clamp(1, 100);
         let offset = (page - 1) * page_size;
 
-        let (query_str, has_filter) = if let Some(_ep_id) = endpoint_id {
-            (
-                "SELECT id, endpoint_id, platform, title, markdown, raw, created_at
-                 FROM events WHERE endpoint_id = ? ORDER BY created_at DESC LIMIT ? OFFSET ?",
-                true,
-            )
-        } else {
-            (
-                "SELECT id, endpoint_id, platform, title, markdown, raw, created_at
-                 FROM events ORDER BY created_at DESC LIMIT ? OFFSET ?",
-                false,
-            )
-        };
-
-        let rows = if has_filter {
-            sqlx::query(query_str)
-                .bind(endpoint_id.unwrap())
-                .bind(page_size)
-                .bind(offset)
-                .fetch_all(&self.pool)
-                .await?
-        } else {
-            sqlx::query(query_str)
-                .bind(page_size)
-                .bind(offset)
-                .fetch_all(&self.pool)
-                .await?
-        };
+        let mut builder = QueryBuilder::<Sqlite>::new(
+            "SELECT e.id, e.endpoint_id, e.platform, e.title, e.markdown, e.raw, e.created_at FROM events e WHERE 1 = 1",
+        );
+        if let Some(endpoint_id) = &filter.endpoint_id {
+            builder.push(" AND e.endpoint_id = ").push_bind(endpoint_id.clone());
+        }
+        if let Some(platform) = &filter.platform {
+            builder.push(" AND e.platform = ").push_bind(platform.clone());
+        }
+        if let Some(since) = filter.since {
+            builder.push(" AND e.created_at >= ").push_bind(since);
+        }
+        if let Some(until) = filter.until {
+            builder.push(" AND e.created_at < ").push_bind(until);
+        }
+        if let Some(target_id) = &filter.target_id {
+            builder
+                .push(" AND EXISTS (SELECT 1 FROM deliveries d WHERE d.event_id = e.id AND d.target_id = ")
+                .push_bind(target_id.clone())
+                .push(")");
+        }
+        match filter.delivery {
+            Some(DeliveryFilter::Failed) => {
+                builder.push(" AND EXISTS (SELECT 1 FROM deliveries d WHERE d.event_id = e.id AND d.status = 'failed')");
+            }
+            Some(DeliveryFilter::Sent) => {
+                builder.push(
+                    " AND EXISTS (SELECT 1 FROM deliveries d WHERE d.event_id = e.id) \
+                     AND NOT EXISTS (SELECT 1 FROM deliveries d WHERE d.event_id = e.id AND d.status != 'sent')",
+                );
+            }
+            None => {}
+        }
+        if let Some(q) = &filter.q {
+            push_search(&mut builder, q);
+        }
+        builder
+            .push(" ORDER BY e.created_at DESC LIMIT ")
+            .push_bind(page_size)
+            .push(" OFFSET ")
+            .push_bind(offset);
+        let rows = builder.build().fetch_all(&self.pool).await?;
 
         let mut events = Vec::new();
         let mut event_ids = Vec::new();
@@ -363,7 +633,7 @@ impl Db {
         }
 
         let mut builder = QueryBuilder::<Sqlite>::new(
-            "SELECT d.event_id, d.target_id, t.name AS target_name, t.kind AS target_kind, d.status, d.response_code, d.error, d.created_at \
+            "SELECT d.event_id, d.target_id, t.name AS target_name, t.kind AS target_kind, d.status, d.response_code, d.error, d.part, d.parts, d.request_id, d.created_at \
              FROM deliveries d \
              LEFT JOIN targets t ON t.id = d.target_id \
              WHERE d.event_id IN (",
@@ -374,7 +644,7 @@ impl Db {
                 separated.push_bind(event_id);
             }
         }
-        builder.push(") ORDER BY d.created_at ASC");
+        builder.push(") ORDER BY d.created_at ASC, d.part ASC");
 
         let delivery_rows = builder.build().fetch_all(&self.pool).await?;
         let mut deliveries_by_event: HashMap<String, Vec<DeliveryRecord>> = HashMap::new();
@@ -389,6 +659,9 @@ impl Db {
                     .get::<Option<i64>, _>("response_code")
                     .map(|code| code as u16),
                 error: row.get::<Option<String>, _>("error"),
+                part: row.get::<Option<u32>, _>("part"),
+                parts: row.get::<Option<u32>, _>("parts"),
+                request_id: row.get::<Option<String>, _>("request_id"),
                 created_at: row.get("created_at"),
             };
             deliveries_by_event
@@ -405,6 +678,405 @@ impl Db {
 
         Ok(events)
     }
+
+    pub async fn create_person(&self, req: CreatePersonRequest) -> Result<Person, sqlx::Error> {
+        let id = Uuid::new_v4().to_string();
+        let created_at = now_timestamp();
+        let handles = req.handles.unwrap_or_default();
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT INTO people (id, name, created_at) VALUES (?, ?, ?)")
+            .bind(&id)
+            .bind(&req.name)
+            .bind(created_at)
+            .execute(&mut *tx)
+            .await?;
+        for (platform, handle) in &handles {
+            sqlx::query("INSERT INTO person_handles (person_id, platform, handle) VALUES (?, ?, ?)")
+                .bind(&id)
+                .bind(platform)
+                .bind(handle)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(Person {
+            id,
+            name: req.name,
+            handles,
+            created_at,
+        })
+    }
+
+    pub async fn list_people(&self) -> Result<Vec<Person>, sqlx::Error> {
+        let rows = sqlx::query("SELECT id, name, created_at FROM people ORDER BY name ASC")
+            .fetch_all(&self.pool)
+            .await?;
+        let handle_rows = sqlx::query("SELECT person_id, platform, handle FROM person_handles")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut handles_by_person: HashMap<String, BTreeMap<String, String>> = HashMap::new();
+        for row in handle_rows {
+            handles_by_person
+                .entry(row.get("person_id"))
+                .or_default()
+                .insert(row.get("platform"), row.get("handle"));
+        }
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let id: String = row.get("id");
+                Person {
+                    handles: handles_by_person.remove(&id).unwrap_or_default(),
+                    id,
+                    name: row.get("name"),
+                    created_at: row.get("created_at"),
+                }
+            })
+            .collect())
+    }
+
+    pub async fn get_person(&self, id: &str) -> Result<Option<Person>, sqlx::Error> {
+        let row = sqlx::query("SELECT id, name, created_at FROM people WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        Ok(Some(Person {
+            id: row.get("id"),
+            name: row.get("name"),
+            handles: self.person_handles(id).await?,
+            created_at: row.get("created_at"),
+        }))
+    }
+
+    pub async fn update_person(
+        &self,
+        id: &str,
+        req: UpdatePersonRequest,
+    ) -> Result<Option<Person>, sqlx::Error> {
+        let mut person = match self.get_person(id).await? {
+            Some(person) => person,
+            None => return Ok(None),
+        };
+
+        if let Some(name) = req.name {
+            person.name = name;
+        }
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE people SET name = ? WHERE id = ?")
+            .bind(&person.name)
+            .bind(&person.id)
+            .execute(&mut *tx)
+            .await?;
+        if let Some(handles) = req.handles {
+            sqlx::query("DELETE FROM person_handles WHERE person_id = ?")
+                .bind(&person.id)
+                .execute(&mut *tx)
+                .await?;
+            for (platform, handle) in &handles {
+                sqlx::query(
+                    "INSERT INTO person_handles (person_id, platform, handle) VALUES (?, ?, ?)",
+                )
+                .bind(&person.id)
+                .bind(platform)
+                .bind(handle)
+                .execute(&mut *tx)
+                .await?;
+            }
+            person.handles = handles;
+        }
+        tx.commit().await?;
+
+        Ok(Some(person))
+    }
+
+    pub async fn delete_person(&self, id: &str) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM people WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn create_mapping(&self, mapping: &Mapping) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO mappings (id, name, id_path, title_path, timestamp_path, meta, template, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&mapping.id)
+        .bind(&mapping.name)
+        .bind(&mapping.id_path)
+        .bind(&mapping.title_path)
+        .bind(&mapping.timestamp_path)
+        .bind(serde_json::to_string(&mapping.meta).expect("strings serialize"))
+        .bind(&mapping.template)
+        .bind(mapping.created_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn list_mappings(&self) -> Result<Vec<Mapping>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, name, id_path, title_path, timestamp_path, meta, template, created_at
+             FROM mappings ORDER BY name ASC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(mapping_from_row).collect())
+    }
+
+    pub async fn get_mapping(&self, id: &str) -> Result<Option<Mapping>, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT id, name, id_path, title_path, timestamp_path, meta, template, created_at
+             FROM mappings WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.as_ref().map(mapping_from_row))
+    }
+
+    pub async fn get_mapping_by_name(&self, name: &str) -> Result<Option<Mapping>, sqlx::Error> {
+        let _timer = db_timer("get_mapping_by_name");
+        let row = sqlx::query(
+            "SELECT id, name, id_path, title_path, timestamp_path, meta, template, created_at
+             FROM mappings WHERE name = ?",
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.as_ref().map(mapping_from_row))
+    }
+
+    pub async fn update_mapping(&self, mapping: &Mapping) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE mappings SET name = ?, id_path = ?, title_path = ?, timestamp_path = ?, meta = ?, template = ?
+             WHERE id = ?",
+        )
+        .bind(&mapping.name)
+        .bind(&mapping.id_path)
+        .bind(&mapping.title_path)
+        .bind(&mapping.timestamp_path)
+        .bind(serde_json::to_string(&mapping.meta).expect("strings serialize"))
+        .bind(&mapping.template)
+        .bind(&mapping.id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn delete_mapping(&self, id: &str) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM mappings WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Fills in names and per-platform handles for mentions known to the
+    /// people directory. `person` tokens reference a directory entry by id.
+    pub async fn resolve_mentions(&self, mentions: &mut [Mention]) -> Result<(), sqlx::Error> {
+        let _timer = db_timer("resolve_mentions");
+        for mention in mentions.iter_mut() {
+            let row = if mention.platform == "person" {
+                sqlx::query("SELECT id, name FROM people WHERE id = ?")
+                    .bind(&mention.handle)
+                    .fetch_optional(&self.pool)
+                    .await?
+            } else {
+                sqlx::query(
+                    "SELECT p.id, p.name FROM people p \
+                     JOIN person_handles h ON h.person_id = p.id \
+                     WHERE h.platform = ? AND h.handle = ?",
+                )
+                .bind(&mention.platform)
+                .bind(&mention.handle)
+                .fetch_optional(&self.pool)
+                .await?
+            };
+            if let Some(row) = row {
+                let person_id: String = row.get("id");
+                if mention.name.is_none() {
+                    mention.name = Some(row.get("name"));
+                }
+                mention.handles = self.person_handles(&person_id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the `(channel, ts)` of the message a target posted for `thread_key`.
+    pub async fn get_thread(
+        &self,
+        target_id: &str,
+        thread_key: &str,
+    ) -> Result<Option<(String, String)>, sqlx::Error> {
+        let _timer = db_timer("get_thread");
+        let row = sqlx::query(
+            "SELECT channel, ts FROM target_threads WHERE target_id = ? AND thread_key = ?",
+        )
+        .bind(target_id)
+        .bind(thread_key)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|row| (row.get("channel"), row.get("ts"))))
+    }
+
+    /// Remembers the first message posted for `thread_key`; an existing
+    /// thread is kept.
+    #[tracing::instrument(skip_all)]
+    pub async fn save_thread(
+        &self,
+        target_id: &str,
+        thread_key: &str,
+        channel: &str,
+        ts: &str,
+    ) -> Result<(), sqlx::Error> {
+        let _timer = db_timer("save_thread");
+        sqlx::query(
+            "INSERT INTO target_threads (target_id, thread_key, channel, ts, created_at)
+             VALUES (?, ?, ?, ?, ?)
+             ON CONFLICT(target_id, thread_key) DO NOTHING",
+        )
+        .bind(target_id)
+        .bind(thread_key)
+        .bind(channel)
+        .bind(ts)
+        .bind(now_timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Stores relayed media bytes and returns the new media id.
+    #[tracing::instrument(skip_all)]
+    pub async fn insert_media(
+        &self,
+        content_type: &str,
+        data: &[u8],
+        source: Option<&str>,
+    ) -> Result<String, sqlx::Error> {
+        let _timer = db_timer("insert_media");
+        let id = Uuid::new_v4().to_string();
+        sqlx::query(
+            "INSERT INTO media (id, content_type, data, size, source, created_at)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&id)
+        .bind(content_type)
+        .bind(data)
+        .bind(data.len() as i64)
+        .bind(source)
+        .bind(now_timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(id)
+    }
+
+    /// Returns the content type and bytes of a relayed media item.
+    pub async fn get_media(&self, id: &str) -> Result<Option<(String, Vec<u8>)>, sqlx::Error> {
+        let _timer = db_timer("get_media");
+        let row = sqlx::query("SELECT content_type, data FROM media WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|row| (row.get("content_type"), row.get("data"))))
+    }
+
+    /// Deletes media stored more than `age` ago and returns how many items
+    /// were removed.
+    pub async fn delete_media_older_than(&self, age: std::time::Duration) -> Result<u64, sqlx::Error> {
+        let _timer = db_timer("delete_media_older_than");
+        let result = sqlx::query("DELETE FROM media WHERE created_at < ?")
+            .bind(now_timestamp() - age.as_secs() as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn person_handles(&self, person_id: &str) -> Result<BTreeMap<String, String>, sqlx::Error> {
+        let rows = sqlx::query("SELECT platform, handle FROM person_handles WHERE person_id = ?")
+            .bind(person_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get("platform"), row.get("handle")))
+            .collect())
+    }
+}
+
+/// Requires every search term in the event title or markdown. Terms of three
+/// or more characters go through the trigram index; it can't match shorter
+/// ones, which fall back to `LIKE`.
+fn push_search(builder: &mut QueryBuilder<'_, Sqlite>, q: &str) {
+    let (indexed, short): (Vec<_>, Vec<_>) = search_terms(q)
+        .into_iter()
+        .partition(|term| term.chars().count() >= 3);
+    if !indexed.is_empty() {
+        let query = indexed
+            .iter()
+            .map(|term| format!("\"{}\"", term))
+            .collect::<Vec<_>>()
+            .join(" ");
+        builder
+            .push(" AND e.id IN (SELECT event_id FROM events_fts WHERE events_fts MATCH ")
+            .push_bind(query)
+            .push(")");
+    }
+    for term in short {
+        let pattern = format!("%{}%", term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        builder
+            .push(" AND (COALESCE(e.title, '') LIKE ")
+            .push_bind(pattern.clone())
+            .push(" ESCAPE '\\' OR e.markdown LIKE ")
+            .push_bind(pattern)
+            .push(" ESCAPE '\\')");
+    }
+}
+
+/// Splits a search into words, keeping `"quoted phrases"` together.
+fn search_terms(q: &str) -> Vec<String> {
+    q.split('"')
+        .enumerate()
+        .flat_map(|(index, part)| {
+            if index % 2 == 1 {
+                vec![part.trim().to_string()]
+            } else {
+                part.split_whitespace().map(String::from).collect()
+            }
+        })
+        .filter(|term| !term.is_empty())
+        .collect()
+}
+
+fn mapping_from_row(row: &SqliteRow) -> Mapping {
+    Mapping {
+        id: row.get("id"),
+        name: row.get("name"),
+        id_path: row.get("id_path"),
+        title_path: row.get("title_path"),
+        timestamp_path: row.get("timestamp_path"),
+        meta: serde_json::from_str(row.get("meta")).unwrap_or_default(),
+        template: row.get("template"),
+        created_at: row.get("created_at"),
+    }
+}
+
+fn platforms_json(platforms: &Option<Vec<String>>) -> Option<String> {
+    platforms
+        .as_ref()
+        .map(|platforms| serde_json::to_string(platforms).expect("strings serialize"))
 }
 
 fn now_timestamp() -> i64 {
@@ -429,6 +1101,9 @@ mod tests {
                 name: "demo".to_string(),
                 banner: None,
                 footer: None,
+                ingress_config: None,
+                allowed_platforms: None,
+                script: None,
             })
             .await
             .expect("create endpoint");
@@ -439,6 +1114,7 @@ mod tests {
                 kind: "slack".to_string(),
                 url: "https://example.com/hook".to_string(),
                 headers: Some(json!({"X-Test": "yes"})),
+                config: None,
             })
             .await
             .expect("create target");
@@ -451,6 +1127,7 @@ mod tests {
             markdown: "hello".to_string(),
             raw: json!({"text": "hello"}),
             meta: json!({}),
+            mentions: Vec::new(),
         };
 
         let record = db
@@ -461,12 +1138,21 @@ mod tests {
         assert_eq!(record.endpoint_id, endpoint.id);
         assert_eq!(record.markdown, "hello");
 
-        db.insert_delivery(&event.id, &target.id, "sent", Some(200), None)
+        let outcome = DeliveryOutcome {
+            target_id: target.id.clone(),
+            status: "sent".to_string(),
+            response_code: Some(200),
+            error: None,
+            part: None,
+            parts: None,
+        };
+        db.insert_delivery(&event.id, Some("req-1"), &outcome)
             .await
             .expect("insert delivery");
 
-        let events = db.list_events(None, None, None).await.expect("list events");
+        let events = db.list_events(&EventFilter::default(), None, None).await.expect("list events");
         assert!(!events.is_empty());
+        assert_eq!(events[0].deliveries[0].request_id.as_deref(), Some("req-1"));
 
         let endpoints = db.list_endpoints().await.expect("list endpoints");
         assert_eq!(endpoints.len(), 1);
@@ -480,4 +1166,220 @@ mod tests {
         let endpoints_after = db.list_endpoints().await.expect("list endpoints");
         assert_eq!(endpoints_after.len(), 0);
     }
+
+    #[tokio::test]
+    async fn searches_and_filters_events() {
+        let db = Db::connect(":memory:").await.expect("connect");
+        let endpoint = db
+            .create_endpoint(CreateEndpointRequest {
+                name: "demo".to_string(),
+                banner: None,
+                footer: None,
+                ingress_config: None,
+                allowed_platforms: None,
+                script: None,
+            })
+            .await
+            .expect("create endpoint");
+        let events = [
+            ("evt-1", "slack", "Disk full", "Volume /data on **db-7** is at 98%", Some("failed")),
+            ("evt-2", "lark", "CPU high", "web-1 has been busy for 10m", Some("sent")),
+            ("evt-3", "dingtalk", "告警", "数据库连接超时 db-7", None),
+        ];
+        for (id, platform, title, markdown, status) in events {
+            let event = UemEvent {
+                id: id.to_string(),
+                source: platform.to_string(),
+                timestamp: 0,
+                title: Some(title.to_string()),
+                markdown: markdown.to_string(),
+                raw: json!({}),
+                meta: json!({}),
+                mentions: Vec::new(),
+            };
+            db.insert_event(&endpoint.id, platform, &event).await.expect("insert event");
+            if let Some(status) = status {
+                let outcome = DeliveryOutcome {
+                    target_id: format!("target-{}", id),
+                    status: status.to_string(),
+                    response_code: None,
+                    error: None,
+                    part: None,
+                    parts: None,
+                };
+                db.insert_delivery(id, None, &outcome).await.expect("insert delivery");
+            }
+        }
+
+        let search = |filter: EventFilter| {
+            let db = db.clone();
+            async move {
+                let mut ids = db
+                    .list_events(&filter, None, None)
+                    .await
+                    .expect("list events")
+                    .into_iter()
+                    .map(|event| event.id)
+                    .collect::<Vec<_>>();
+                ids.sort();
+                ids
+            }
+        };
+        let q = |q: &str| EventFilter {
+            q: Some(q.to_string()),
+            ..EventFilter::default()
+        };
+        assert_eq!(search(q("db-7")).await, ["evt-1", "evt-3"]);
+        assert_eq!(search(q("DB-7 disk")).await, ["evt-1"]);
+        assert_eq!(search(q("\"busy for\"")).await, ["evt-2"]);
+        assert_eq!(search(q("\"for busy\"")).await, Vec::<String>::new());
+        assert_eq!(search(q("连接超时")).await, ["evt-3"]);
+        assert_eq!(search(q("告警")).await, ["evt-3"]);
+        assert_eq!(search(q("98%")).await, ["evt-1"]);
+        assert_eq!(search(q("9%")).await, Vec::<String>::new());
+
+        let failed = EventFilter {
+            delivery: Some(DeliveryFilter::Failed),
+            ..EventFilter::default()
+        };
+        assert_eq!(search(failed).await, ["evt-1"]);
+        let sent = EventFilter {
+            delivery: Some(DeliveryFilter::Sent),
+            ..EventFilter::default()
+        };
+        assert_eq!(search(sent).await, ["evt-2"]);
+        let target = EventFilter {
+            target_id: Some("target-evt-2".to_string()),
+            ..EventFilter::default()
+        };
+        assert_eq!(search(target).await, ["evt-2"]);
+        let platform = EventFilter {
+            platform: Some("dingtalk".to_string()),
+            q: Some("db-7".to_string()),
+            ..EventFilter::default()
+        };
+        assert_eq!(search(platform).await, ["evt-3"]);
+        let future = EventFilter {
+            since: Some(now_timestamp() + 60),
+            ..EventFilter::default()
+        };
+        assert!(search(future).await.is_empty());
+        let past = EventFilter {
+            until: Some(now_timestamp() + 60),
+            ..EventFilter::default()
+        };
+        assert_eq!(search(past).await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn readiness_checks_pass_on_fresh_db() {
+        let db = Db::connect(":memory:").await.expect("connect");
+        db.ping().await.expect("ping");
+        db.check_writable().await.expect("writable");
+        // A check cut off by the readiness timeout must not keep the write lock.
+        for _ in 0..20 {
+            let _ = tokio::time::timeout(std::time::Duration::from_micros(50), db.check_writable()).await;
+        }
+        db.check_writable().await.expect("writable after cancelled checks");
+        assert!(db.pending_migrations().await.expect("migrations").is_empty());
+        assert!(db.size_bytes().await.expect("size") > 0);
+
+        let counts = db.table_counts().await.expect("counts");
+        assert_eq!(counts.get("events"), Some(&0));
+        assert_eq!(counts.keys().map(String::as_str).collect::<Vec<_>>(), STATUS_TABLES);
+    }
+
+    #[tokio::test]
+    async fn people_directory_resolves_mentions() {
+        let db = Db::connect(":memory:").await.expect("connect");
+
+        let person = db
+            .create_person(CreatePersonRequest {
+                name: "Alice".to_string(),
+                handles: Some(BTreeMap::from([
+                    ("slack".to_string(), "U1".to_string()),
+                    ("dingtalk".to_string(), "13800000000".to_string()),
+                ])),
+            })
+            .await
+            .expect("create person");
+
+        let mut mentions = vec![
+            Mention::new("slack", "U1", None),
+            Mention::new("slack", "U2", None),
+            Mention::new("person", &person.id, None),
+        ];
+        db.resolve_mentions(&mut mentions).await.expect("resolve");
+        assert_eq!(mentions[0].name.as_deref(), Some("Alice"));
+        assert_eq!(mentions[0].handle_for("dingtalk"), Some("13800000000"));
+        assert!(mentions[1].name.is_none());
+        assert_eq!(mentions[2].handle_for("slack"), Some("U1"));
+
+        let updated = db
+            .update_person(&person.id, UpdatePersonRequest {
+                name: None,
+                handles: Some(BTreeMap::from([("lark".to_string(), "ou_1".to_string())])),
+            })
+            .await
+            .expect("update person")
+            .expect("person exists");
+        assert_eq!(updated.handles.len(), 1);
+
+        db.delete_person(&person.id).await.expect("delete person");
+        assert!(db.list_people().await.expect("list people").is_empty());
+    }
+
+    #[tokio::test]
+    async fn encrypts_target_secrets_at_rest() {
+        use base64::Engine;
+        let key = |byte: u8| base64::engine::general_purpose::STANDARD.encode([byte; 32]);
+
+        let plain = Db::connect(":memory:").await.expect("connect");
+        let endpoint = plain
+            .create_endpoint(CreateEndpointRequest {
+                name: "demo".to_string(),
+                banner: None,
+                footer: None,
+                ingress_config: None,
+                allowed_platforms: None,
+                script: None,
+            })
+            .await
+            .expect("create endpoint");
+        plain
+            .create_target(&endpoint.id, CreateTargetRequest {
+                name: "Slack".to_string(),
+                kind: "slack".to_string(),
+                url: "https://hooks.slack.com/services/T/B/secret".to_string(),
+                headers: Some(json!({"Authorization": "Bearer secret"})),
+                config: None,
+            })
+            .await
+            .expect("create target");
+
+        let raw = |db: &Db| {
+            let pool = db.pool.clone();
+            async move {
+                let row = sqlx::query("SELECT url, headers FROM targets").fetch_one(&pool).await.unwrap();
+                (row.get::<String, _>("url"), row.get::<String, _>("headers"))
+            }
+        };
+
+        let db = plain.clone().with_secrets(SecretKeys::new(&key(1), &[]).unwrap());
+        assert_eq!(db.seal_secrets().await.expect("seal"), 1);
+        assert_eq!(db.seal_secrets().await.expect("seal"), 0);
+        let (url, headers) = raw(&db).await;
+        assert!(!url.contains("secret") && !headers.contains("secret"));
+        let targets = db.list_targets(&endpoint.id).await.expect("list");
+        assert_eq!(targets[0].url, "https://hooks.slack.com/services/T/B/secret");
+        assert_eq!(targets[0].headers, Some(json!({"Authorization": "Bearer secret"})));
+
+        assert!(plain.list_targets(&endpoint.id).await.is_err());
+        assert!(plain.seal_secrets().await.is_err());
+
+        let rotated = plain.with_secrets(SecretKeys::new(&key(2), &[key(1)]).unwrap());
+        assert_eq!(rotated.seal_secrets().await.expect("rotate"), 1);
+        let fresh = rotated.clone().with_secrets(SecretKeys::new(&key(2), &[]).unwrap());
+        assert_eq!(fresh.list_targets(&endpoint.id).await.expect("list")[0].url, targets[0].url);
+    }
 }
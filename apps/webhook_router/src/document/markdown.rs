@@ -0,0 +1,190 @@
+//! CommonMark writer for destinations that take a markdown dialect
+//! (DingTalk, WeCom).
+
+use super::{Alignment, Block, Document, Inline};
+
+/// Inline syntax the destination's markdown dialect understands; unsupported
+/// styles are written as plain text.
+#[derive(Debug, Clone, Copy)]
+pub struct MarkdownStyle {
+    pub emphasis: bool,
+    pub strikethrough: bool,
+}
+
+/// Writes `doc` as markdown, rendering mentions with `mention(platform, handle)`.
+pub fn write_markdown(
+    doc: &Document,
+    style: MarkdownStyle,
+    mention: &mut dyn FnMut(&str, &str) -> String,
+) -> String {
+    let mut writer = Writer { style, mention };
+    writer.blocks(&doc.blocks)
+}
+
+struct Writer<'a> {
+    style: MarkdownStyle,
+    mention: &'a mut dyn FnMut(&str, &str) -> String,
+}
+
+impl Writer<'_> {
+    fn blocks(&mut self, blocks: &[Block]) -> String {
+        blocks
+            .iter()
+            .map(|block| self.block(block))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    fn block(&mut self, block: &Block) -> String {
+        match block {
+            Block::Heading { level, inlines } => {
+                format!("{} {}", "#".repeat(usize::from(*level)), self.inlines(inlines))
+            }
+            Block::Paragraph(inlines) => self.inlines(inlines),
+            Block::Quote(blocks) => prefix_lines(&self.blocks(blocks), "> ", ">"),
+            Block::List { start, items } => items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| {
+                    let marker = match start {
+                        Some(start) => format!("{}. ", start + index as u64),
+                        None => "- ".to_string(),
+                    };
+                    let content = item
+                        .iter()
+                        .map(|block| self.block(block))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    let indent = " ".repeat(marker.len());
+                    format!("{}{}", marker, indent_continuation(&content, &indent))
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Block::Code { language, text } => {
+                let fence = code_fence(text);
+                format!(
+                    "{}{}\n{}\n{}",
+                    fence,
+                    language.as_deref().unwrap_or(""),
+                    text.trim_end_matches('\n'),
+                    fence
+                )
+            }
+            Block::Table { alignments, header, rows } => {
+                let mut lines = vec![self.table_row(header)];
+                let separators: Vec<&str> = (0..header.len())
+                    .map(|index| match alignments.get(index).copied().unwrap_or(Alignment::None) {
+                        Alignment::None => "---",
+                        Alignment::Left => ":---",
+                        Alignment::Center => ":---:",
+                        Alignment::Right => "---:",
+                    })
+                    .collect();
+                lines.push(format!("| {} |", separators.join(" | ")));
+                for row in rows {
+                    lines.push(self.table_row(row));
+                }
+                lines.join("\n")
+            }
+            Block::Rule => "---".to_string(),
+            Block::Html(html) => html.trim_end().to_string(),
+        }
+    }
+
+    fn table_row(&mut self, cells: &[Vec<Inline>]) -> String {
+        let cells: Vec<String> = cells
+            .iter()
+            .map(|cell| self.inlines(cell).replace('|', "\\|"))
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    }
+
+    fn inlines(&mut self, inlines: &[Inline]) -> String {
+        let mut output = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text(text) => output.push_str(&escape_text(text)),
+                Inline::Code(code) => {
+                    let ticks = if code.contains('`') { "``" } else { "`" };
+                    output.push_str(&format!("{ticks}{code}{ticks}"));
+                }
+                Inline::Emphasis(children) => {
+                    let inner = self.inlines(children);
+                    if self.style.emphasis {
+                        output.push_str(&format!("_{}_", inner));
+                    } else {
+                        output.push_str(&inner);
+                    }
+                }
+                Inline::Strong(children) => {
+                    output.push_str(&format!("**{}**", self.inlines(children)));
+                }
+                Inline::Strikethrough(children) => {
+                    let inner = self.inlines(children);
+                    if self.style.strikethrough {
+                        output.push_str(&format!("~~{}~~", inner));
+                    } else {
+                        output.push_str(&inner);
+                    }
+                }
+                Inline::Link { url, children } => {
+                    output.push_str(&format!("[{}]({})", self.inlines(children), url));
+                }
+                Inline::Image { url, alt } => output.push_str(&format!("![{}]({})", alt, url)),
+                Inline::Mention { platform, handle } => output.push_str(&(self.mention)(platform, handle)),
+                Inline::SoftBreak => output.push('\n'),
+                Inline::LineBreak => output.push_str("  \n"),
+                Inline::Html(html) => output.push_str(html),
+            }
+        }
+        output
+    }
+}
+
+/// Escapes characters that would otherwise start inline markup.
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if matches!(ch, '\\' | '*' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+fn code_fence(text: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for ch in text.chars() {
+        run = if ch == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn prefix_lines(text: &str, prefix: &str, blank: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.is_empty() {
+                blank.to_string()
+            } else {
+                format!("{}{}", prefix, line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn indent_continuation(text: &str, indent: &str) -> String {
+    let mut lines = text.lines();
+    let mut output = lines.next().unwrap_or("").to_string();
+    for line in lines {
+        output.push('\n');
+        if !line.is_empty() {
+            output.push_str(indent);
+            output.push_str(line);
+        }
+    }
+    output
+}
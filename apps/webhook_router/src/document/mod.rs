@@ -0,0 +1,649 @@
+//! Platform-neutral document model for egress rendering.
+//!
+//! UEM markdown is parsed once into a [`Document`]. Each egress adapter
+//! implements [`Renderer`] and declares its [`Capabilities`]; constructs a
+//! destination can't display are rewritten by [`prepare`] before rendering,
+//! so every platform falls back the same way.
+
+pub mod markdown;
+pub mod table;
+
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+use crate::adapters::AdapterError;
+use crate::models::{Mention, OutgoingPayload, UemEvent};
+use crate::utils::mention::{lookup_mention, render_mentions, split_mention_tokens, Segment};
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Heading { level: u8, inlines: Vec<Inline> },
+    Paragraph(Vec<Inline>),
+    Quote(Vec<Block>),
+    /// `start` is the first number of an ordered list.
+    List { start: Option<u64>, items: Vec<Vec<Block>> },
+    Code { language: Option<String>, text: String },
+    Table { alignments: Vec<Alignment>, header: Vec<Vec<Inline>>, rows: Vec<Vec<Vec<Inline>>> },
+    Rule,
+    Html(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alignment {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Code(String),
+    Emphasis(Vec<Inline>),
+    Strong(Vec<Inline>),
+    Strikethrough(Vec<Inline>),
+    Link { url: String, children: Vec<Inline> },
+    Image { url: String, alt: String },
+    Mention { platform: String, handle: String },
+    SoftBreak,
+    LineBreak,
+    Html(String),
+}
+
+/// What a destination can display natively.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capabilities {
+    pub tables: TableSupport,
+    pub images: bool,
+    pub code_blocks: bool,
+    pub mentions: bool,
+}
+
+/// How a destination displays GFM tables.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TableSupport {
+    /// The renderer has a table element of its own.
+    Native,
+    /// Aligned plain-text columns inside a code block.
+    Monospace,
+    /// One list item of `header: value` lines per row, for narrow clients.
+    Fields,
+}
+
+/// Renders prepared documents into a destination's egress payload.
+pub trait Renderer {
+    /// Canonical platform name used to look up mention handles.
+    fn platform(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    /// Builds the payload for `event` from `doc`, which has already had
+    /// fallbacks applied for unsupported capabilities.
+    fn render(&self, event: &UemEvent, doc: &Document) -> Result<OutgoingPayload, AdapterError>;
+}
+
+/// Applies capability fallbacks to `doc` and renders it with `renderer`.
+pub fn render_event(
+    renderer: &dyn Renderer,
+    event: &UemEvent,
+    doc: &Document,
+) -> Result<OutgoingPayload, AdapterError> {
+    let prepared = prepare(doc, renderer.capabilities(), &event.mentions, renderer.platform());
+    renderer.render(event, &prepared)
+}
+
+impl Document {
+    pub fn parse(markdown: &str) -> Self {
+        let mut options = Options::empty();
+        options.insert(Options::ENABLE_STRIKETHROUGH);
+        options.insert(Options::ENABLE_TABLES);
+
+        let mut builder = Builder::new();
+        for event in Parser::new_ext(markdown, options) {
+            builder.event(event);
+        }
+        builder.finish()
+    }
+}
+
+/// Rewrites constructs the destination can't display:
+/// - tables become monospace code blocks or lists of `header: value` rows,
+/// - images become links,
+/// - code blocks become quotes of inline code, one per code line,
+/// - mentions become `@name` text.
+pub fn prepare(doc: &Document, capabilities: Capabilities, mentions: &[Mention], platform: &str) -> Document {
+    let fallback = Fallback { capabilities, mentions, platform };
+    Document {
+        blocks: doc.blocks.iter().map(|block| fallback.block(block)).collect(),
+    }
+}
+
+struct Fallback<'a> {
+    capabilities: Capabilities,
+    mentions: &'a [Mention],
+    platform: &'a str,
+}
+
+impl Fallback<'_> {
+    fn block(&self, block: &Block) -> Block {
+        match block {
+            Block::Heading { level, inlines } => Block::Heading {
+                level: *level,
+                inlines: self.inlines(inlines),
+            },
+            Block::Paragraph(inlines) => Block::Paragraph(self.inlines(inlines)),
+            Block::Quote(blocks) => Block::Quote(self.blocks(blocks)),
+            Block::List { start, items } => Block::List {
+                start: *start,
+                items: items.iter().map(|item| self.blocks(item)).collect(),
+            },
+            Block::Code { text, .. } if !self.capabilities.code_blocks => {
+                let mut inlines = Vec::new();
+                for (index, line) in text.trim_end_matches('\n').lines().enumerate() {
+                    if index > 0 {
+                        inlines.push(Inline::LineBreak);
+                    }
+                    if !line.trim().is_empty() {
+                        inlines.push(Inline::Code(line.to_string()));
+                    }
+                }
+                Block::Quote(vec![Block::Paragraph(inlines)])
+            }
+            Block::Table { alignments, header, rows } if self.capabilities.tables == TableSupport::Monospace => {
+                let header: Vec<Vec<Inline>> = header.iter().map(|cell| self.plain_cell(cell)).collect();
+                let rows: Vec<Vec<Vec<Inline>>> = rows
+                    .iter()
+                    .map(|row| row.iter().map(|cell| self.plain_cell(cell)).collect())
+                    .collect();
+                self.block(&Block::Code {
+                    language: None,
+                    text: table::monospace(alignments, &header, &rows),
+                })
+            }
+            Block::Table { header, rows, .. } if self.capabilities.tables == TableSupport::Fields => {
+                let header: Vec<Vec<Inline>> = header.iter().map(|cell| self.plain_cell(cell)).collect();
+                let rows: Vec<Vec<Vec<Inline>>> = rows
+                    .iter()
+                    .map(|row| row.iter().map(|cell| self.inlines(cell)).collect())
+                    .collect();
+                table::fields(&header, &rows)
+            }
+            Block::Table { alignments, header, rows } => Block::Table {
+                alignments: alignments.clone(),
+                header: header.iter().map(|cell| self.inlines(cell)).collect(),
+                rows: rows
+                    .iter()
+                    .map(|row| row.iter().map(|cell| self.inlines(cell)).collect())
+                    .collect(),
+            },
+            other => other.clone(),
+        }
+    }
+
+    fn blocks(&self, blocks: &[Block]) -> Vec<Block> {
+        blocks.iter().map(|block| self.block(block)).collect()
+    }
+
+    /// A table cell that is shown as plain text, with mentions resolved.
+    fn plain_cell(&self, cell: &[Inline]) -> Vec<Inline> {
+        vec![Inline::Text(plain_text_for(cell, self.mentions, self.platform))]
+    }
+
+    fn inlines(&self, inlines: &[Inline]) -> Vec<Inline> {
+        inlines.iter().map(|inline| self.inline(inline)).collect()
+    }
+
+    fn inline(&self, inline: &Inline) -> Inline {
+        match inline {
+            Inline::Emphasis(children) => Inline::Emphasis(self.inlines(children)),
+            Inline::Strong(children) => Inline::Strong(self.inlines(children)),
+            Inline::Strikethrough(children) => Inline::Strikethrough(self.inlines(children)),
+            Inline::Link { url, children } => Inline::Link {
+                url: url.clone(),
+                children: self.inlines(children),
+            },
+            Inline::Image { url, alt } if !self.capabilities.images => Inline::Link {
+                url: url.clone(),
+                children: vec![Inline::Text(if alt.is_empty() { "image".to_string() } else { alt.clone() })],
+            },
+            Inline::Mention { platform, handle } if !self.capabilities.mentions => {
+                let (_, fallback) = lookup_mention(self.mentions, platform, handle, self.platform);
+                Inline::Text(format!("@{}", fallback))
+            }
+            other => other.clone(),
+        }
+    }
+}
+
+/// Concatenates the visible text of `inlines`, dropping formatting. Mentions
+/// become `@handle`; [`plain_text_for`] resolves their names.
+pub fn plain_text(inlines: &[Inline]) -> String {
+    plain_text_for(inlines, &[], "")
+}
+
+/// Like [`plain_text`], but mentions read `@name`, taken from `mentions`
+/// the way renderers write mentions `platform` can't display natively.
+pub fn plain_text_for(inlines: &[Inline], mentions: &[Mention], platform: &str) -> String {
+    let mut text = String::new();
+    for inline in inlines {
+        match inline {
+            Inline::Text(value) | Inline::Code(value) | Inline::Html(value) => text.push_str(value),
+            Inline::Emphasis(children) | Inline::Strong(children) | Inline::Strikethrough(children) => {
+                text.push_str(&plain_text_for(children, mentions, platform))
+            }
+            Inline::Link { children, .. } => text.push_str(&plain_text_for(children, mentions, platform)),
+            Inline::Image { alt, .. } => text.push_str(alt),
+            Inline::Mention { platform: source, handle } => {
+                let (_, fallback) = lookup_mention(mentions, source, handle, platform);
+                text.push('@');
+                text.push_str(fallback);
+            }
+            Inline::SoftBreak | Inline::LineBreak => text.push(' '),
+        }
+    }
+    text
+}
+
+/// Plain text for a title or summary: mention tokens read `@name` as in
+/// [`plain_text_for`].
+pub fn plain_title(title: &str, mentions: &[Mention], platform: &str) -> String {
+    render_mentions(title, mentions, platform, |_, fallback| format!("@{}", fallback))
+}
+
+enum Frame {
+    /// Root, block quote or list item content.
+    Blocks(Vec<Block>),
+    List { start: Option<u64>, items: Vec<Vec<Block>> },
+    Inlines { kind: InlineKind, inlines: Vec<Inline> },
+    Table { alignments: Vec<Alignment>, header: Vec<Vec<Inline>>, rows: Vec<Vec<Vec<Inline>>> },
+    Row(Vec<Vec<Inline>>),
+    Code { language: Option<String>, text: String },
+    Html(String),
+}
+
+enum InlineKind {
+    Paragraph,
+    /// Text directly inside a tight list item.
+    Implicit,
+    Heading(u8),
+    Emphasis,
+    Strong,
+    Strikethrough,
+    Link(String),
+    Image(String),
+    Cell,
+}
+
+struct Builder {
+    stack: Vec<Frame>,
+}
+
+impl Builder {
+    fn new() -> Self {
+        Self {
+            stack: vec![Frame::Blocks(Vec::new())],
+        }
+    }
+
+    fn event(&mut self, event: Event<'_>) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => match self.stack.last_mut() {
+                Some(Frame::Code { text: code, .. }) | Some(Frame::Html(code)) => code.push_str(&text),
+                _ => self.push_inline(Inline::Text(text.to_string())),
+            },
+            Event::Code(code) => self.push_inline(Inline::Code(code.to_string())),
+            Event::Html(html) => match self.stack.last_mut() {
+                Some(Frame::Html(block)) => block.push_str(&html),
+                _ => self.push_block(Block::Html(html.to_string())),
+            },
+            Event::InlineHtml(html) => self.push_inline(Inline::Html(html.to_string())),
+            Event::SoftBreak => self.push_inline(Inline::SoftBreak),
+            Event::HardBreak => self.push_inline(Inline::LineBreak),
+            Event::Rule => self.push_block(Block::Rule),
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag<'_>) {
+        let frame = match tag {
+            Tag::Paragraph => {
+                self.close_implicit();
+                Frame::Inlines { kind: InlineKind::Paragraph, inlines: Vec::new() }
+            }
+            Tag::Heading { level, .. } => {
+                self.close_implicit();
+                Frame::Inlines { kind: InlineKind::Heading(level as u8), inlines: Vec::new() }
+            }
+            Tag::BlockQuote(_) | Tag::Item => {
+                self.close_implicit();
+                Frame::Blocks(Vec::new())
+            }
+            Tag::CodeBlock(kind) => {
+                self.close_implicit();
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info
+                        .split_whitespace()
+                        .next()
+                        .map(String::from),
+                    CodeBlockKind::Indented => None,
+                };
+                Frame::Code { language, text: String::new() }
+            }
+            Tag::HtmlBlock => {
+                self.close_implicit();
+                Frame::Html(String::new())
+            }
+            Tag::List(start) => {
+                self.close_implicit();
+                Frame::List { start, items: Vec::new() }
+            }
+            Tag::Table(alignments) => {
+                self.close_implicit();
+                Frame::Table {
+                    alignments: alignments
+                        .into_iter()
+                        .map(|alignment| match alignment {
+                            pulldown_cmark::Alignment::None => Alignment::None,
+                            pulldown_cmark::Alignment::Left => Alignment::Left,
+                            pulldown_cmark::Alignment::Center => Alignment::Center,
+                            pulldown_cmark::Alignment::Right => Alignment::Right,
+                        })
+                        .collect(),
+                    header: Vec::new(),
+                    rows: Vec::new(),
+                }
+            }
+            Tag::TableHead | Tag::TableRow => Frame::Row(Vec::new()),
+            Tag::TableCell => Frame::Inlines { kind: InlineKind::Cell, inlines: Vec::new() },
+            Tag::Emphasis => self.inline_frame(InlineKind::Emphasis),
+            Tag::Strong => self.inline_frame(InlineKind::Strong),
+            Tag::Strikethrough => self.inline_frame(InlineKind::Strikethrough),
+            Tag::Link { dest_url, .. } => self.inline_frame(InlineKind::Link(dest_url.to_string())),
+            Tag::Image { dest_url, .. } => self.inline_frame(InlineKind::Image(dest_url.to_string())),
+            // Footnotes, definition lists and metadata are kept as plain content.
+            _ => return,
+        };
+        self.stack.push(frame);
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::Heading(_) => {
+                if let Some(Frame::Inlines { kind, inlines }) = self.stack.pop() {
+                    let inlines = finish_inlines(inlines);
+                    match kind {
+                        InlineKind::Heading(level) => self.push_block(Block::Heading { level, inlines }),
+                        _ if !inlines.is_empty() => self.push_block(Block::Paragraph(inlines)),
+                        _ => {}
+                    }
+                }
+            }
+            TagEnd::BlockQuote(_) => {
+                self.close_implicit();
+                if let Some(Frame::Blocks(blocks)) = self.stack.pop() {
+                    self.push_block(Block::Quote(blocks));
+                }
+            }
+            TagEnd::Item => {
+                self.close_implicit();
+                if let Some(Frame::Blocks(blocks)) = self.stack.pop() {
+                    if let Some(Frame::List { items, .. }) = self.stack.last_mut() {
+                        items.push(blocks);
+                    }
+                }
+            }
+            TagEnd::List(_) => {
+                if let Some(Frame::List { start, items }) = self.stack.pop() {
+                    self.push_block(Block::List { start, items });
+                }
+            }
+            TagEnd::CodeBlock => {
+                if let Some(Frame::Code { language, text }) = self.stack.pop() {
+                    self.push_block(Block::Code { language, text });
+                }
+            }
+            TagEnd::HtmlBlock => {
+                if let Some(Frame::Html(html)) = self.stack.pop() {
+                    self.push_block(Block::Html(html));
+                }
+            }
+            TagEnd::TableCell => {
+                if let Some(Frame::Inlines { inlines, .. }) = self.stack.pop() {
+                    if let Some(Frame::Row(cells)) = self.stack.last_mut() {
+                        cells.push(finish_inlines(inlines));
+                    }
+                }
+            }
+            TagEnd::TableHead => {
+                if let Some(Frame::Row(cells)) = self.stack.pop() {
+                    if let Some(Frame::Table { header, .. }) = self.stack.last_mut() {
+                        *header = cells;
+                    }
+                }
+            }
+            TagEnd::TableRow => {
+                if let Some(Frame::Row(cells)) = self.stack.pop() {
+                    if let Some(Frame::Table { rows, .. }) = self.stack.last_mut() {
+                        rows.push(cells);
+                    }
+                }
+            }
+            TagEnd::Table => {
+                if let Some(Frame::Table { alignments, header, rows }) = self.stack.pop() {
+                    self.push_block(Block::Table { alignments, header, rows });
+                }
+            }
+            TagEnd::Emphasis
+            | TagEnd::Strong
+            | TagEnd::Strikethrough
+            | TagEnd::Link
+            | TagEnd::Image => {
+                if let Some(Frame::Inlines { kind, inlines }) = self.stack.pop() {
+                    let children = finish_inlines(inlines);
+                    let inline = match kind {
+                        InlineKind::Emphasis => Inline::Emphasis(children),
+                        InlineKind::Strong => Inline::Strong(children),
+                        InlineKind::Strikethrough => Inline::Strikethrough(children),
+                        InlineKind::Link(url) => Inline::Link { url, children },
+                        InlineKind::Image(url) => Inline::Image { url, alt: plain_text(&children) },
+                        _ => return,
+                    };
+                    self.push_inline(inline);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn inline_frame(&mut self, kind: InlineKind) -> Frame {
+        self.open_implicit();
+        Frame::Inlines { kind, inlines: Vec::new() }
+    }
+
+    fn push_inline(&mut self, inline: Inline) {
+        self.open_implicit();
+        if let Some(Frame::Inlines { inlines, .. }) = self.stack.last_mut() {
+            inlines.push(inline);
+        }
+    }
+
+    fn push_block(&mut self, block: Block) {
+        self.close_implicit();
+        if let Some(Frame::Blocks(blocks)) = self.stack.last_mut() {
+            blocks.push(block);
+        }
+    }
+
+    /// Opens a paragraph for inline content that appears directly in a
+    /// block container (tight list items).
+    fn open_implicit(&mut self) {
+        if matches!(self.stack.last(), Some(Frame::Blocks(_))) {
+            self.stack.push(Frame::Inlines { kind: InlineKind::Implicit, inlines: Vec::new() });
+        }
+    }
+
+    fn close_implicit(&mut self) {
+        if matches!(self.stack.last(), Some(Frame::Inlines { kind: InlineKind::Implicit, .. })) {
+            if let Some(Frame::Inlines { inlines, .. }) = self.stack.pop() {
+                let inlines = finish_inlines(inlines);
+                if let Some(Frame::Blocks(blocks)) = self.stack.last_mut() {
+                    blocks.push(Block::Paragraph(inlines));
+                }
+            }
+        }
+    }
+
+    fn finish(mut self) -> Document {
+        self.close_implicit();
+        match self.stack.into_iter().next() {
+            Some(Frame::Blocks(blocks)) => Document { blocks },
+            _ => Document::default(),
+        }
+    }
+}
+
+/// Merges adjacent text runs (the parser splits text around `<`, `[`...) and
+/// turns mention tokens into [`Inline::Mention`].
+fn finish_inlines(inlines: Vec<Inline>) -> Vec<Inline> {
+    let mut merged: Vec<Inline> = Vec::new();
+    for inline in inlines {
+        match (merged.last_mut(), inline) {
+            (Some(Inline::Text(last)), Inline::Text(text)) => last.push_str(&text),
+            (_, inline) => merged.push(inline),
+        }
+    }
+
+    let mut finished = Vec::with_capacity(merged.len());
+    for inline in merged {
+        let Inline::Text(text) = inline else {
+            finished.push(inline);
+            continue;
+        };
+        for segment in split_mention_tokens(&text) {
+            finished.push(match segment {
+                Segment::Text(text) => Inline::Text(text.to_string()),
+                Segment::Mention { platform, handle } => Inline::Mention {
+                    platform: platform.to_string(),
+                    handle: handle.to_string(),
+                },
+            });
+        }
+    }
+    finished
+}
+
+#[cfg(test)]
+mod tests {
+    use insta::assert_yaml_snapshot;
+    use serde_json::json;
+
+    use super::*;
+    use crate::adapters::egress_adapter;
+
+    /// Shared corpus rendered by every egress renderer.
+    const CORPUS: &str = r#"## Deploy finished
+
+Build **#42** for _api_ on ~~staging~~ `production` by <@slack:U1>. See [the logs](https://ci.example.com/42).
+
+1. Checkout
+2. Build
+   - unit tests
+   - lint
+
+- [x] migrations
+
+> Rollback is ready.
+> Ping on-call if needed.
+
+```bash
+cargo build --release
+./deploy.sh
+```
+
+| Service | Status | Latency |
+| :--- | :---: | ---: |
+| api | ok | 12ms |
+| worker | degraded | 340ms |
+
+![dashboard](https://grafana.example.com/d/1.png)
+
+---
+
+Done."#;
+
+    fn corpus_event() -> UemEvent {
+        let mut mention = Mention::new("slack", "U1", Some("Alice".to_string()));
+        mention.handles.insert("lark".to_string(), "ou_alice".to_string());
+        mention.handles.insert("dingtalk".to_string(), "13800000000".to_string());
+        mention.handles.insert("wecom".to_string(), "alice".to_string());
+        UemEvent {
+            id: "evt-corpus".to_string(),
+            source: "custom".to_string(),
+            timestamp: 1,
+            title: Some("Release".to_string()),
+            markdown: CORPUS.to_string(),
+            raw: json!({}),
+            meta: json!({}),
+            mentions: vec![mention],
+        }
+    }
+
+    #[test]
+    fn parses_tight_lists_and_mentions() {
+        let doc = Document::parse("- hi <@slack:U1>\n- two");
+        assert_eq!(
+            doc.blocks,
+            vec![Block::List {
+                start: None,
+                items: vec![
+                    vec![Block::Paragraph(vec![
+                        Inline::Text("hi ".to_string()),
+                        Inline::Mention { platform: "slack".to_string(), handle: "U1".to_string() },
+                    ])],
+                    vec![Block::Paragraph(vec![Inline::Text("two".to_string())])],
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn plain_text_names_mentions() {
+        let event = corpus_event();
+        let doc = Document::parse("[ask <@slack:U1>](https://ci.example.com)");
+        let Block::Paragraph(inlines) = &doc.blocks[0] else { panic!("paragraph") };
+        assert_eq!(plain_text_for(inlines, &event.mentions, "lark"), "ask @Alice");
+        assert_eq!(plain_text(inlines), "ask @U1");
+        assert_eq!(plain_title("Paged <@slack:U1>", &event.mentions, "dingtalk"), "Paged @Alice");
+
+        let event = UemEvent {
+            title: Some("Paged <@slack:U1>".to_string()),
+            ..event
+        };
+        let lark = egress_adapter("lark").expect("adapter");
+        let payload = render_event(lark.renderer().expect("renderer"), &event, &Document::parse("hi")).expect("render");
+        assert_eq!(payload.body["content"]["post"]["zh_cn"]["title"], "Paged @Alice");
+        let dingtalk = egress_adapter("dingtalk").expect("adapter");
+        let payload = render_event(dingtalk.renderer().expect("renderer"), &event, &Document::parse("hi")).expect("render");
+        assert_eq!(payload.body["markdown"]["title"], "Paged @Alice");
+        assert_eq!(payload.body["markdown"]["text"], "# Paged @Alice\n\nhi");
+    }
+
+    #[test]
+    fn renders_corpus_for_every_platform() {
+        let event = corpus_event();
+        let doc = Document::parse(&event.markdown);
+        for kind in ["slack", "lark", "dingtalk", "wecom"] {
+            let adapter = egress_adapter(kind).expect("adapter");
+            let renderer = adapter.renderer().expect("renderer");
+            let payload = render_event(renderer, &event, &doc).expect("render");
+            assert_yaml_snapshot!(format!("render_corpus_{}", kind), payload.body);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Table fallbacks for destinations without native table support.
+
+use unicode_width::UnicodeWidthStr;
+
+use super::{plain_text, Alignment, Block, Inline};
+
+/// Lays the table out as aligned plain text for a monospace code block.
+/// Column widths use display width, so CJK cells line up too.
+pub fn monospace(alignments: &[Alignment], header: &[Vec<Inline>], rows: &[Vec<Vec<Inline>>]) -> String {
+    let header: Vec<String> = header.iter().map(|cell| cell_text(cell)).collect();
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(|cell| cell_text(cell)).collect())
+        .collect();
+    let columns = rows.iter().map(Vec::len).chain([header.len()]).max().unwrap_or(0);
+
+    let mut widths = vec![0; columns];
+    for row in rows.iter().chain([&header]) {
+        for (index, cell) in row.iter().enumerate() {
+            widths[index] = widths[index].max(cell.width());
+        }
+    }
+
+    let format_row = |row: &[String]| {
+        let cells: Vec<String> = (0..columns)
+            .map(|index| {
+                let cell = row.get(index).map(String::as_str).unwrap_or("");
+                let alignment = alignments.get(index).copied().unwrap_or(Alignment::None);
+                pad(cell, widths[index], alignment)
+            })
+            .collect();
+        cells.join(" | ").trim_end().to_string()
+    };
+
+    let mut lines = vec![format_row(&header)];
+    lines.push(
+        widths
+            .iter()
+            .map(|width| "-".repeat((*width).max(1)))
+            .collect::<Vec<_>>()
+            .join("-|-"),
+    );
+    lines.extend(rows.iter().map(|row| format_row(row)));
+    lines.join("\n")
+}
+
+/// Turns each row into a list item of `**header**: value` lines.
+pub fn fields(header: &[Vec<Inline>], rows: &[Vec<Vec<Inline>>]) -> Block {
+    let items = rows
+        .iter()
+        .map(|row| {
+            let mut inlines = Vec::new();
+            for (index, cell) in row.iter().enumerate() {
+                if index > 0 {
+                    inlines.push(Inline::LineBreak);
+                }
+                let label = header.get(index).map(|cell| plain_text(cell));
+                if let Some(label) = label.filter(|label| !label.is_empty()) {
+                    inlines.push(Inline::Strong(vec![Inline::Text(label)]));
+                    inlines.push(Inline::Text(": ".to_string()));
+                }
+                inlines.extend(cell.iter().cloned());
+            }
+            vec![Block::Paragraph(inlines)]
+        })
+        .collect();
+    Block::List { start: None, items }
+}
+
+fn cell_text(cell: &[Inline]) -> String {
+    plain_text(cell).replace('\n', " ")
+}
+
+fn pad(cell: &str, width: usize, alignment: Alignment) -> String {
+    let fill = width.saturating_sub(cell.width());
+    let (left, right) = match alignment {
+        Alignment::Right => (fill, 0),
+        Alignment::Center => (fill / 2, fill - fill / 2),
+        Alignment::None | Alignment::Left => (0, fill),
+    };
+    format!("{}{}{}", " ".repeat(left), cell, " ".repeat(right))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+
+    #[test]
+    fn aligns_columns_by_display_width() {
+        let doc = Document::parse("| 服务 | Latency |\n| :-- | --: |\n| api | 12ms |\n| 工作进程 | 340ms |");
+        let Block::Table { alignments, header, rows } = &doc.blocks[0] else {
+            panic!("expected table");
+        };
+        assert_eq!(
+            monospace(alignments, header, rows),
+            "服务     | Latency\n---------|--------\napi      |    12ms\n工作进程 |   340ms"
+        );
+    }
+}
@@ -1,29 +1,54 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
 use axum::extract::{Path, Query, State};
-use axum::http::{HeaderMap, StatusCode};
+use axum::http::{header, HeaderMap, Method, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::routing::{delete, get, post, put};
-use axum::{Json, Router};
+use axum::{Extension, Json, Router};
 use utoipa::OpenApi;
 use base64::Engine;
 use serde_json::{json, Value};
 use tokio::task::JoinSet;
-use tracing::error;
+use tracing::{error, info, warn, Instrument};
 use uuid::Uuid;
 
-use crate::adapters::{egress_adapter, ingress_adapter};
+use crate::adapters::{
+    canonical_platform, detect_platforms, egress_adapter, ingress_adapter, ingress_platform, plugin,
+    validate_allowed_platforms, validate_mapping, AdapterError, Callback, CallbackAnswer, CallbackReply, InlineMedia, MappedAdapter, MessageLimit,
+    WebhookAdapter,
+};
+use crate::config::{self, ConfigMode, EndpointConfig, RouterConfig, TargetConfig};
 use crate::db::Db;
+use crate::document::{render_event, Document};
+use crate::media::{relay_event_media, MediaRelay};
+use crate::metrics::{metrics, InFlight};
+use crate::script::{self, ScriptLimits, ScriptOutcome};
+use crate::secrets;
+use crate::slack_api::{self, SlackApiConfig};
+use crate::telemetry::inject_trace_context;
 use crate::models::{
-    BasicAuth, CreateEndpointRequest, CreateTargetRequest, DeliveryOutcome, DeliveryRecord, Endpoint,
-    EventRecord, Target, TestSendRequest, UpdateEndpointRequest, UemEvent,
+    BasicAuth, CreateEndpointRequest, CreateMappingRequest, CreatePersonRequest, CreateTargetRequest, DeliveryFilter,
+    DeliveryOutcome, DeliveryRecord, Endpoint, EventFilter, EventRecord, ImportItem, ImportResult, Mapping, Mention,
+    Person, Readiness, ScriptTestRequest, ScriptTestResult, SystemStatus, Target, TestSendRequest,
+    UpdateEndpointRequest, UpdateMappingRequest, UpdatePersonRequest, UemEvent,
 };
+use crate::utils::body::{self, IngressBody};
 use crate::utils::format::format_markdown;
+use crate::utils::mention::collect_mentions;
+use crate::utils::split::split_markdown;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: Db,
     pub auth: BasicAuth,
+    /// Read-only console credentials; secrets can't be revealed with them.
+    pub viewer: Option<BasicAuth>,
     pub http: reqwest::Client,
+    pub media: Option<MediaRelay>,
     pub console_html: std::sync::Arc<str>,
+    pub started_at: std::time::Instant,
+    pub script_limits: ScriptLimits,
 }
 
 #[utoipa::path(
@@ -37,30 +62,193 @@ pub struct AppState {
         ("basic_auth" = [])
     )
 )]
-pub async fn check_auth(State(state): State<AppState>) -> Json<Value> {
+pub async fn check_auth(State(state): State<AppState>, Extension(role): Extension<Role>) -> Json<Value> {
+    let username = match (role, &state.viewer) {
+        (Role::Viewer, Some(viewer)) => &viewer.username,
+        _ => &state.auth.username,
+    };
     Json(json!({
         "valid": true,
-        "username": state.auth.username
+        "username": username,
+        "role": role,
+    }))
+}
+
+#[derive(serde::Deserialize)]
+struct RevealQuery {
+    #[serde(default)]
+    reveal: bool,
+}
+
+/// Target secrets are masked unless an admin passes `reveal=true`.
+fn reveal_secrets(role: Role, query: &RevealQuery) -> Result<bool, AppError> {
+    if query.reveal && role != Role::Admin {
+        return Err(AppError::forbidden("revealing secrets requires the admin role"));
+    }
+    Ok(query.reveal)
+}
+
+/// Runs one readiness check, returning `ok` or the failure message.
+async fn readiness_check(check: impl std::future::Future<Output = Result<(), String>>) -> String {
+    match tokio::time::timeout(READINESS_CHECK_TIMEOUT, check).await {
+        Ok(Ok(())) => "ok".to_string(),
+        Ok(Err(err)) => err,
+        Err(_) => format!("timed out after {}s", READINESS_CHECK_TIMEOUT.as_secs()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/system",
+    responses(
+        (status = 200, description = "System status", body = SystemStatus),
+        (status = 403, description = "Forbidden")
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+async fn system_status(State(state): State<AppState>) -> Result<Json<SystemStatus>, AppError> {
+    let features = BTreeMap::from([("console".to_string(), cfg!(feature = "console"))]);
+    Ok(Json(SystemStatus {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_seconds: state.started_at.elapsed().as_secs(),
+        db_size_bytes: state.db.size_bytes().await.map_err(AppError::from)?,
+        tables: state.db.table_counts().await.map_err(AppError::from)?,
+        features,
+        dispatch_in_flight: metrics().dispatch_in_flight.get(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/export",
+    params(
+        ("reveal" = Option<bool>, Query, description = "Return target secrets unmasked (admin only)")
+    ),
+    responses(
+        (status = 200, description = "All endpoints with their targets", body = RouterConfig),
+        (status = 403, description = "Revealing secrets requires the admin role", body = AppErrorResponse)
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+async fn export_config(
+    State(state): State<AppState>,
+    Extension(role): Extension<Role>,
+    Query(query): Query<RevealQuery>,
+) -> Result<Json<RouterConfig>, AppError> {
+    let document = config::export(&state.db).await.map_err(AppError::from)?;
+    if reveal_secrets(role, &query)? {
+        return Ok(Json(document));
+    }
+    Ok(Json(document.masked()))
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ImportMode {
+    /// Create and update only.
+    #[default]
+    Merge,
+    /// Also delete endpoints and targets missing from the document.
+    Replace,
+}
+
+#[derive(serde::Deserialize)]
+struct ImportQuery {
+    #[serde(default)]
+    mode: ImportMode,
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    keep_ids: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/import",
+    params(
+        ("mode" = Option<String>, Query, description = "`merge` (default) or `replace`, which also deletes what the document doesn't contain"),
+        ("dry_run" = Option<bool>, Query, description = "Report the changes without writing them"),
+        ("keep_ids" = Option<bool>, Query, description = "Keep endpoint ids from the document so ingress URLs stay valid; otherwise endpoints are matched by name")
+    ),
+    request_body = RouterConfig,
+    responses(
+        (status = 200, description = "Per-item import results", body = ImportResult),
+        (status = 400, description = "Invalid document", body = AppErrorResponse)
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+async fn import_config(
+    State(state): State<AppState>,
+    Query(query): Query<ImportQuery>,
+    Json(mut document): Json<RouterConfig>,
+) -> Result<Json<ImportResult>, AppError> {
+    config::validate(&document).map_err(|err| AppError::bad_request(err.to_string()))?;
+    if document.contains_masked_secrets() {
+        return Err(AppError::bad_request(
+            "document contains masked secrets; export it with reveal=true",
+        ));
+    }
+    if !query.keep_ids {
+        config::assign_ids(&state.db, &mut document)
+            .await
+            .map_err(AppError::from)?;
+    }
+    let mode = match query.mode {
+        ImportMode::Merge => ConfigMode::Merge,
+        ImportMode::Replace => ConfigMode::Strict,
+    };
+    let changes = config::plan(&state.db, &document, mode)
+        .await
+        .map_err(AppError::from)?;
+    if !query.dry_run {
+        config::apply(&state.db, &changes).await.map_err(AppError::from)?;
+    }
+    Ok(Json(ImportResult {
+        dry_run: query.dry_run,
+        items: config::report(&document, &changes),
     }))
 }
 
 pub fn api_router() -> Router<AppState> {
     Router::<AppState>::new()
         .route("/auth/check", get(check_auth))
+        .route("/system", get(system_status))
+        .route("/export", get(export_config))
+        .route("/import", post(import_config))
         .route("/endpoints/:id/targets", post(create_target).get(list_targets))
         .route("/endpoints/:id/targets/:target_id", delete(delete_target))
         .route("/endpoints", post(create_endpoint).get(list_endpoints))
         .route("/endpoints/:id", put(update_endpoint).get(get_endpoint).delete(delete_endpoint))
         .route("/endpoints/:id/test", post(test_send))
         .route("/events", get(list_events))
+        .route("/scripts/test", post(test_script))
+        .route("/people", post(create_person).get(list_people))
+        .route("/people/:id", put(update_person).get(get_person).delete(delete_person))
+        .route("/mappings", post(create_mapping).get(list_mappings))
+        .route("/mappings/:id", put(update_mapping).get(get_mapping).delete(delete_mapping))
 }
 
 #[derive(OpenApi)]
 #[openapi(
     paths(
         healthz,
+        readyz,
+        metrics_handler,
         ingress,
+        ingress_detect,
+        verify_ingress,
+        verify_ingress_detect,
+        media,
         check_auth,
+        system_status,
+        export_config,
+        import_config,
         create_target,
         list_targets,
         delete_target,
@@ -70,7 +258,18 @@ pub fn api_router() -> Router<AppState> {
         update_endpoint,
         delete_endpoint,
         test_send,
+        test_script,
         list_events,
+        create_person,
+        list_people,
+        get_person,
+        update_person,
+        delete_person,
+        create_mapping,
+        list_mappings,
+        get_mapping,
+        update_mapping,
+        delete_mapping,
     ),
     components(
         schemas(
@@ -81,9 +280,26 @@ pub fn api_router() -> Router<AppState> {
             UpdateEndpointRequest,
             Endpoint,
             EventRecord,
+            DeliveryFilter,
             DeliveryRecord,
             DeliveryOutcome,
             TestSendRequest,
+            ScriptTestRequest,
+            ScriptTestResult,
+            Mention,
+            Person,
+            CreatePersonRequest,
+            UpdatePersonRequest,
+            Mapping,
+            CreateMappingRequest,
+            UpdateMappingRequest,
+            Readiness,
+            SystemStatus,
+            RouterConfig,
+            EndpointConfig,
+            TargetConfig,
+            ImportItem,
+            ImportResult,
             AppErrorResponse,
         )
     ),
@@ -110,6 +326,96 @@ pub async fn healthz() -> &'static str {
     "ok"
 }
 
+/// Upper bound for each readiness check, so a locked database fails the
+/// probe quickly instead of waiting out SQLite's busy timeout.
+const READINESS_CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, description = "Ready to accept ingress", body = Readiness),
+        (status = 503, description = "A readiness check failed", body = Readiness)
+    )
+)]
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let db = &state.db;
+    let (database, writable, migrations) = tokio::join!(
+        readiness_check(async { db.ping().await.map_err(|err| err.to_string()) }),
+        readiness_check(async { db.check_writable().await.map_err(|err| err.to_string()) }),
+        readiness_check(async {
+            match db.pending_migrations().await {
+                Ok(pending) if pending.is_empty() => Ok(()),
+                Ok(pending) => Err(format!("pending migrations: {:?}", pending)),
+                Err(err) => Err(err.to_string()),
+            }
+        }),
+    );
+    let checks = BTreeMap::from([
+        ("database".to_string(), database),
+        ("writable".to_string(), writable),
+        ("migrations".to_string(), migrations),
+    ]);
+
+    let ready = checks.values().all(|check| check == "ok");
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    let readiness = Readiness {
+        status: if ready { "ready" } else { "not_ready" }.to_string(),
+        checks,
+    };
+    (status, Json(readiness))
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Prometheus metrics", content_type = "text/plain", body = String),
+        (status = 403, description = "Forbidden when metrics auth is enabled")
+    )
+)]
+pub async fn metrics_handler() -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics().render(),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/media/{id}",
+    params(
+        ("id" = String, Path, description = "Media ID")
+    ),
+    responses(
+        (status = 200, description = "Relayed media bytes", content_type = "application/octet-stream"),
+        (status = 404, description = "Media not found", body = AppErrorResponse)
+    )
+)]
+pub async fn media(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Response, AppError> {
+    let (content_type, data) = state
+        .db
+        .get_media(&id)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(|| AppError::not_found("media not found"))?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CACHE_CONTROL, "public, max-age=31536000, immutable".to_string()),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+            (header::CONTENT_SECURITY_POLICY, "default-src 'none'".to_string()),
+            (header::CONTENT_DISPOSITION, "inline".to_string()),
+        ],
+        data,
+    )
+        .into_response())
+}
+
 #[utoipa::path(
     post,
     path = "/ingress/{endpoint_id}/{platform}",
@@ -117,84 +423,224 @@ pub async fn healthz() -> &'static str {
         ("endpoint_id" = String, Path, description = "Endpoint ID"),
         ("platform" = String, Path, description = "Platform name")
     ),
-    request_body = Value,
+    request_body(
+        description = "JSON, form-encoded (`payload=` JSON is unwrapped), `text/*` or XML; gzip/deflate `Content-Encoding` is accepted",
+        content(
+            (Value = "application/json"),
+            (Value = "application/x-www-form-urlencoded"),
+            (String = "text/plain")
+        )
+    ),
     responses(
-        (status = 200, description = "Event processed successfully", body = Value),
+        (status = 200, description = "Event processed successfully; `{}` unless the platform expects a particular reply", body = Value),
         (status = 400, description = "Bad request", body = AppErrorResponse),
+        (status = 401, description = "Callback signature or decryption failed", body = AppErrorResponse),
+        (status = 403, description = "Platform not allowed for the endpoint", body = AppErrorResponse),
+        (status = 413, description = "Decoded body too large", body = AppErrorResponse),
+        (status = 415, description = "Unsupported content encoding", body = AppErrorResponse),
         (status = 404, description = "Endpoint not found", body = AppErrorResponse)
     )
 )]
 pub async fn ingress(
     Path((endpoint_id, platform)): Path<(String, String)>,
     State(state): State<AppState>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
     body: axum::body::Bytes,
-) -> Result<Json<Value>, AppError> {
-    let payload: Value = serde_json::from_slice(&body).map_err(|err| {
-        AppError::bad_request(format!("invalid json payload: {err}"))
-    })?;
+) -> Result<Response, AppError> {
+    handle_ingress(&state, &endpoint_id, Some(&platform), &headers, &query, &body).await
+}
 
-    if let Some(challenge) = payload.get("challenge").and_then(|value| value.as_str()) {
-        if payload.get("type").and_then(|value| value.as_str()) == Some("url_verification") {
-            return Ok(Json(json!({ "challenge": challenge })));
-        }
-    }
+#[utoipa::path(
+    post,
+    path = "/ingress/{endpoint_id}",
+    params(
+        ("endpoint_id" = String, Path, description = "Endpoint ID")
+    ),
+    request_body(
+        description = "Any supported platform's payload; the platform is detected from headers, query and body",
+        content(
+            (Value = "application/json"),
+            (Value = "application/x-www-form-urlencoded"),
+            (String = "text/plain")
+        )
+    ),
+    responses(
+        (status = 200, description = "Event processed successfully; `{}` unless the platform expects a particular reply", body = Value),
+        (status = 400, description = "Bad request", body = AppErrorResponse),
+        (status = 401, description = "Callback signature or decryption failed", body = AppErrorResponse),
+        (status = 403, description = "Detected platform not allowed for the endpoint", body = AppErrorResponse),
+        (status = 413, description = "Decoded body too large", body = AppErrorResponse),
+        (status = 415, description = "Unsupported content encoding", body = AppErrorResponse),
+        (status = 404, description = "Endpoint not found", body = AppErrorResponse)
+    )
+)]
+pub async fn ingress_detect(
+    Path(endpoint_id): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<Response, AppError> {
+    handle_ingress(&state, &endpoint_id, None, &headers, &query, &body).await
+}
+
+/// What an ingress request resolved to, for its metrics labels.
+#[derive(Default)]
+struct IngressLabels {
+    known_endpoint: bool,
+    platform: Option<&'static str>,
+}
+
+async fn handle_ingress(
+    state: &AppState,
+    endpoint_id: &str,
+    platform: Option<&str>,
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+    body: &[u8],
+) -> Result<Response, AppError> {
+    let span = tracing::info_span!(
+        "ingress",
+        endpoint_id = %endpoint_id,
+        platform = platform.unwrap_or("auto"),
+        event_id = tracing::field::Empty,
+    );
+    let mut labels = IngressLabels::default();
+    let result = process_ingress(state, endpoint_id, platform, headers, query, body, &mut labels)
+        .instrument(span)
+        .await;
+    let status = match &result {
+        Ok(_) => StatusCode::OK,
+        Err(err) => err.status,
+    };
+    // Unknown endpoints and platforms share one series to bound label cardinality.
+    let endpoint_label = if labels.known_endpoint { endpoint_id } else { "unknown" };
+    let platform_label = labels.platform.unwrap_or("unknown");
+    metrics()
+        .ingress_requests
+        .with_label_values(&[endpoint_label, platform_label, status.as_str()])
+        .inc();
+    result
+}
+
+async fn process_ingress(
+    state: &AppState,
+    endpoint_id: &str,
+    platform: Option<&str>,
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+    body: &[u8],
+    labels: &mut IngressLabels,
+) -> Result<Response, AppError> {
+    let request_id = request_id(headers);
+    let mut body = body::decode(headers, body).map_err(|err| AppError {
+        status: err.status,
+        message: err.message,
+    })?;
 
     let endpoint = state
         .db
-        .get_endpoint(&endpoint_id)
+        .get_endpoint(endpoint_id)
         .await
         .map_err(AppError::from)?;
 
     let endpoint = endpoint.ok_or_else(|| AppError::not_found("endpoint not found"))?;
+    labels.known_endpoint = true;
 
-    let adapter = ingress_adapter(&platform)
-        .ok_or_else(|| AppError::bad_request("unsupported platform"))?;
+    // A platform segment that names no platform may name a mapping.
+    let mapping = match platform {
+        Some(name) if ingress_platform(name).is_none() => {
+            state.db.get_mapping_by_name(name).await.map_err(AppError::from)?
+        }
+        _ => None,
+    };
+    let (platform, event_platform, adapter): (_, _, Box<dyn WebhookAdapter>) = match mapping {
+        Some(mapping) => ("mapped", mapping.name.clone(), Box::new(MappedAdapter::new(mapping))),
+        None => {
+            let platform = resolve_platform(&endpoint, platform, headers, query, &body)?;
+            let adapter = ingress_adapter(platform).expect("ingress platforms have adapters");
+            (platform, platform.to_string(), adapter)
+        }
+    };
+    labels.platform = Some(platform);
+    tracing::Span::current().record("platform", platform);
+    ensure_platform_allowed(&endpoint, platform)?;
 
-    let mut event = adapter
-        .ingress_to_uem(&payload)
-        .map_err(|err| AppError::bad_request(err.message))?;
+    // Encrypted callbacks are opened first: a URL verification challenge
+    // may itself be encrypted.
+    let callback = Callback {
+        headers,
+        query,
+        settings: endpoint.ingress_settings(platform),
+    };
+    let answer = adapter
+        .open_callback(&callback, &mut body)
+        .map_err(|err| AppError {
+            status: StatusCode::UNAUTHORIZED,
+            message: err.message,
+        })?;
+    let reply = match answer {
+        CallbackAnswer::Handshake(reply) => return Ok(reply.into_response()),
+        CallbackAnswer::EventReply(reply) => Some(reply),
+        CallbackAnswer::Event => None,
+    };
+    let payload = &body.value;
+
+    if let Some(challenge) = payload.get("challenge").and_then(|value| value.as_str()) {
+        if payload.get("type").and_then(|value| value.as_str()) == Some("url_verification") {
+            return Ok(Json(json!({ "challenge": challenge })).into_response());
+        }
+    }
+
+    let converted = ingress_body_to_uem(adapter.as_ref(), platform, &body)
+        .instrument(tracing::info_span!("ingress_to_uem", platform = %platform))
+        .await;
+    let mut event = converted.map_err(|err| {
+        metrics()
+            .parse_failures
+            .with_label_values(&[platform])
+            .inc();
+        AppError::bad_request(err.message)
+    })?;
 
     if event.id.is_empty() {
         event.id = Uuid::new_v4().to_string();
     }
+    tracing::Span::current().record("event_id", event.id.as_str());
 
-    // Concatenate banner + markdown + footer with proper formatting
-    let mut final_markdown = String::new();
-    
-    if let Some(banner) = &endpoint.banner {
-        if !banner.is_empty() {
-            final_markdown.push_str(banner.trim());
-            final_markdown.push_str("\n\n");
-        }
+    if run_endpoint_script(state, &endpoint, &mut event).await == ScriptOutcome::Drop {
+        info!(endpoint_id = %endpoint.id, event_id = %event.id, "event dropped by endpoint script");
+        return Ok(match reply {
+            Some(reply) => reply.into_response(),
+            None => Json(json!({ "dropped": true })).into_response(),
+        });
     }
-    
-    final_markdown.push_str(event.markdown.trim());
-    
-    if let Some(footer) = &endpoint.footer {
-        if !footer.is_empty() {
-            final_markdown.push_str("\n\n");
-            final_markdown.push_str(footer.trim());
-        }
-    }
-    
-    // Format the concatenated markdown for proper spacing
-    event.markdown = format_markdown(&final_markdown)
-        .unwrap_or_else(|_| final_markdown);
+
+    let inline_media = adapter.inline_media(payload);
+    finalize_event(state, &endpoint, &mut event, inline_media).await?;
 
     state
         .db
-        .insert_event(&endpoint.id, &platform, &event)
+        .insert_event(&endpoint.id, &event_platform, &event)
         .await
         .map_err(AppError::from)?;
 
     // Fetch targets for this endpoint
     let targets = state.db.list_targets(&endpoint.id).await.map_err(AppError::from)?;
     
+    let document = Arc::new(Document::parse(&event.markdown));
     let mut tasks = JoinSet::new();
     for target in targets {
         let state = state.clone();
         let event = event.clone();
-        tasks.spawn(async move { dispatch_to_target(&state, &event, &target).await });
+        let document = document.clone();
+        let request_id = request_id.clone();
+        let span = dispatch_span(&endpoint.id, &event.id, &target, request_id.as_deref());
+        tasks.spawn(
+            async move { dispatch_to_target(&state, &event, &document, &target, request_id.as_deref()).await }
+                .instrument(span),
+        );
     }
 
     // Spawn a background task to collect results so we don't block the response
@@ -206,45 +652,450 @@ pub async fn ingress(
         }
     });
 
-    Ok(Json(json!({})))
+    Ok(match reply {
+        Some(reply) => reply.into_response(),
+        None => Json(json!({})).into_response(),
+    })
+}
+
+impl IntoResponse for CallbackReply {
+    fn into_response(self) -> Response {
+        ([(header::CONTENT_TYPE, self.content_type)], self.body).into_response()
+    }
+}
+
+/// The canonical ingress platform for a request: the path segment when
+/// given, otherwise the detected candidate the endpoint allows, preferring
+/// ones it has callback settings for. Bodies matching no known format are
+/// `custom`. Whether the endpoint allows the result is checked separately.
+fn resolve_platform(
+    endpoint: &Endpoint,
+    platform: Option<&str>,
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+    body: &IngressBody,
+) -> Result<&'static str, AppError> {
+    let platform = match platform {
+        Some(platform) => ingress_platform(platform).ok_or_else(|| AppError::bad_request("unsupported platform"))?,
+        None => {
+            let mut candidates = detect_platforms(headers, query, body);
+            candidates.sort_by_key(|platform| endpoint.ingress_settings(platform).is_none());
+            candidates
+                .iter()
+                .copied()
+                .find(|platform| endpoint.allows_platform(platform))
+                .or(candidates.first().copied())
+                .unwrap_or("custom")
+        }
+    };
+    Ok(platform)
+}
+
+fn ensure_platform_allowed(endpoint: &Endpoint, platform: &str) -> Result<(), AppError> {
+    if !endpoint.allows_platform(platform) {
+        return Err(AppError::forbidden(format!(
+            "platform {} is not allowed for this endpoint",
+            platform
+        )));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/ingress/{endpoint_id}/{platform}",
+    params(
+        ("endpoint_id" = String, Path, description = "Endpoint ID"),
+        ("platform" = String, Path, description = "Platform name"),
+        ("msg_signature" = String, Query, description = "Signature over the token, timestamp, nonce and echostr"),
+        ("timestamp" = String, Query, description = "Request timestamp"),
+        ("nonce" = String, Query, description = "Request nonce"),
+        ("echostr" = String, Query, description = "Encrypted text to decrypt and echo back")
+    ),
+    responses(
+        (status = 200, description = "Decrypted echostr", body = String, content_type = "text/plain"),
+        (status = 400, description = "Platform does not verify URLs with GET", body = AppErrorResponse),
+        (status = 401, description = "Callback signature or decryption failed", body = AppErrorResponse),
+        (status = 403, description = "Platform not allowed for the endpoint", body = AppErrorResponse),
+        (status = 404, description = "Endpoint not found", body = AppErrorResponse)
+    )
+)]
+pub async fn verify_ingress(
+    Path((endpoint_id, platform)): Path<(String, String)>,
+    State(state): State<AppState>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    verify_callback_url(&state, &endpoint_id, Some(&platform), &headers, &query).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/ingress/{endpoint_id}",
+    params(
+        ("endpoint_id" = String, Path, description = "Endpoint ID"),
+        ("msg_signature" = String, Query, description = "Signature over the token, timestamp, nonce and echostr"),
+        ("timestamp" = String, Query, description = "Request timestamp"),
+        ("nonce" = String, Query, description = "Request nonce"),
+        ("echostr" = String, Query, description = "Encrypted text to decrypt and echo back")
+    ),
+    responses(
+        (status = 200, description = "Decrypted echostr", body = String, content_type = "text/plain"),
+        (status = 400, description = "Platform does not verify URLs with GET", body = AppErrorResponse),
+        (status = 401, description = "Callback signature or decryption failed", body = AppErrorResponse),
+        (status = 403, description = "Detected platform not allowed for the endpoint", body = AppErrorResponse),
+        (status = 404, description = "Endpoint not found", body = AppErrorResponse)
+    )
+)]
+pub async fn verify_ingress_detect(
+    Path(endpoint_id): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    verify_callback_url(&state, &endpoint_id, None, &headers, &query).await
+}
+
+async fn verify_callback_url(
+    state: &AppState,
+    endpoint_id: &str,
+    platform: Option<&str>,
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+) -> Result<Response, AppError> {
+    let endpoint = state
+        .db
+        .get_endpoint(endpoint_id)
+        .await
+        .map_err(AppError::from)?
+        .ok_or_else(|| AppError::not_found("endpoint not found"))?;
+    let empty = IngressBody {
+        value: Value::Null,
+        bytes: Vec::new(),
+        content_type: String::new(),
+    };
+    let platform = resolve_platform(&endpoint, platform, headers, query, &empty)?;
+    ensure_platform_allowed(&endpoint, platform)?;
+    let adapter = ingress_adapter(platform).expect("ingress platforms have adapters");
+
+    let callback = Callback {
+        headers,
+        query,
+        settings: endpoint.ingress_settings(platform),
+    };
+    let echo = adapter
+        .verify_callback_url(&callback)
+        .ok_or_else(|| AppError::bad_request("platform does not verify callback URLs with GET"))?
+        .map_err(|err| {
+            warn!(endpoint_id = %endpoint_id, platform = %platform, "callback URL verification failed: {}", err.message);
+            AppError {
+                status: StatusCode::UNAUTHORIZED,
+                message: err.message,
+            }
+        })?;
+    Ok(([(header::CONTENT_TYPE, "text/plain")], echo).into_response())
+}
+
+/// Converts an ingress body, on a blocking thread for plugin platforms since
+/// a plugin call may run until its fuel is used up.
+async fn ingress_body_to_uem(
+    adapter: &dyn WebhookAdapter,
+    platform: &str,
+    body: &IngressBody,
+) -> Result<UemEvent, AdapterError> {
+    match plugin::ingress_adapter(platform) {
+        Some(plugin) => plugin.ingress_to_uem_blocking(body.value.clone()).await,
+        None => adapter.ingress_body_to_uem(body),
+    }
+}
+
+/// Runs the endpoint's script, if any, on a blocking thread. A failing script
+/// leaves the event as it was (noting the error in `meta.script_error`) so a
+/// broken script can't lose events.
+async fn run_endpoint_script(state: &AppState, endpoint: &Endpoint, event: &mut UemEvent) -> ScriptOutcome {
+    let Some(source) = endpoint.script.clone().filter(|source| !source.trim().is_empty()) else {
+        return ScriptOutcome::Keep;
+    };
+    let limits = state.script_limits;
+    let mut scripted = event.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        script::run(&source, &mut scripted, &limits).map(|run| (run, scripted))
+    })
+    .await
+    .unwrap_or_else(|err| {
+        Err(script::ScriptError {
+            message: format!("script task failed: {}", err),
+        })
+    });
+
+    let (outcome, label) = match result {
+        Ok((run, scripted)) => {
+            for line in &run.output {
+                info!(endpoint_id = %endpoint.id, event_id = %event.id, "script: {}", line);
+            }
+            if run.outcome == ScriptOutcome::Keep {
+                *event = scripted;
+            }
+            let label = match run.outcome {
+                ScriptOutcome::Keep => "kept",
+                ScriptOutcome::Drop => "dropped",
+            };
+            (run.outcome, label)
+        }
+        Err(err) => {
+            warn!(endpoint_id = %endpoint.id, event_id = %event.id, "endpoint script failed: {}", err);
+            if let Some(meta) = event.meta.as_object_mut() {
+                meta.insert("script_error".to_string(), Value::String(err.message));
+            } else {
+                event.meta = json!({ "script_error": err.message });
+            }
+            (ScriptOutcome::Keep, "error")
+        }
+    };
+    metrics()
+        .script_runs
+        .with_label_values(&[&endpoint.id, label])
+        .inc();
+    outcome
+}
+
+/// Relays media, applies the endpoint banner/footer, formats the markdown and
+/// resolves mention tokens against the people directory.
+async fn finalize_event(
+    state: &AppState,
+    endpoint: &Endpoint,
+    event: &mut UemEvent,
+    inline_media: Vec<InlineMedia>,
+) -> Result<(), AppError> {
+    if let Some(relay) = &state.media {
+        relay_event_media(relay, &state.db, event, inline_media)
+            .await
+            .map_err(AppError::from)?;
+    }
+
+    // Concatenate banner + markdown + footer with proper formatting
+    let mut final_markdown = String::new();
+
+    if let Some(banner) = &endpoint.banner {
+        if !banner.is_empty() {
+            final_markdown.push_str(banner.trim());
+            final_markdown.push_str("\n\n");
+        }
+    }
+
+    final_markdown.push_str(event.markdown.trim());
+
+    if let Some(footer) = &endpoint.footer {
+        if !footer.is_empty() {
+            final_markdown.push_str("\n\n");
+            final_markdown.push_str(footer.trim());
+        }
+    }
+
+    // Format the concatenated markdown for proper spacing
+    event.markdown = format_markdown(&final_markdown).unwrap_or(final_markdown);
+
+    event.mentions = collect_mentions(&event.markdown, &event.mentions);
+    state
+        .db
+        .resolve_mentions(&mut event.mentions)
+        .await
+        .map_err(AppError::from)?;
+    Ok(())
 }
 
+/// The `X-Request-Id` set (or passed through) by the request id layer.
+fn request_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+}
+
+/// Span for one target's dispatch task, carrying the correlation ids so every
+/// log line on the dispatch path has them.
+fn dispatch_span(endpoint_id: &str, event_id: &str, target: &Target, request_id: Option<&str>) -> tracing::Span {
+    tracing::info_span!(
+        "dispatch",
+        request_id,
+        endpoint_id,
+        event_id,
+        target_id = %target.id,
+        kind = %target.kind,
+    )
+}
+
+/// Delivers `event` to `target`, split into numbered parts when it exceeds
+/// the destination's size limit. Parts go out in order and each is recorded
+/// as its own delivery; once a part fails the rest are skipped.
 async fn dispatch_to_target(
     state: &AppState,
     event: &UemEvent,
+    document: &Document,
     target: &Target,
-) -> DeliveryOutcome {
+    request_id: Option<&str>,
+) -> Vec<DeliveryOutcome> {
+    let _in_flight = InFlight::start();
     let adapter = match egress_adapter(&target.kind) {
         Some(adapter) => adapter,
         None => {
-            let _ = state
-                .db
-                .insert_delivery(&event.id, &target.id, "failed", None, Some("unsupported target".to_string()))
-                .await;
-            return DeliveryOutcome {
-                target_id: target.id.clone(),
-                status: "failed".to_string(),
-                response_code: None,
-                error: Some("unsupported target".to_string()),
-            };
+            let failure = ("failed", None, Some("unsupported target".to_string()));
+            let outcome = record_delivery(state, event, target, request_id, None, failure).await;
+            return vec![outcome];
         }
     };
 
-    let payload = match adapter.uem_to_egress(event) {
-        Ok(payload) => payload,
-        Err(err) => {
-            let _ = state
-                .db
-                .insert_delivery(&event.id, &target.id, "failed", None, Some(err.message.clone()))
-                .await;
-            return DeliveryOutcome {
-                target_id: target.id.clone(),
-                status: "failed".to_string(),
-                response_code: None,
-                error: Some(err.message),
-            };
+    let parts = split_event(event, adapter.as_ref());
+    let count = parts.len() as u32;
+    let mut outcomes = Vec::with_capacity(parts.len());
+    let mut failed = false;
+    for (index, part) in parts.iter().enumerate() {
+        let number = (count > 1).then_some((index as u32 + 1, count));
+        if failed {
+            let skipped = ("skipped", None, Some("skipped after an earlier part failed".to_string()));
+            outcomes.push(record_delivery(state, event, target, request_id, number, skipped).await);
+            continue;
         }
+        // The shared document is reused unless the event had to be split.
+        let part_document = (count > 1).then(|| Document::parse(&part.markdown));
+        let document = part_document.as_ref().unwrap_or(document);
+        let timer = metrics()
+            .delivery_duration
+            .with_label_values(&[&target.kind])
+            .start_timer();
+        let result = deliver_part(state, adapter.as_ref(), part, document, target, index > 0).await;
+        timer.observe_duration();
+        failed = result.0 != "sent";
+        outcomes.push(record_delivery(state, event, target, request_id, number, result).await);
+    }
+    outcomes
+}
+
+/// Splits the event so each rendered part fits the adapter's message limit,
+/// numbering the parts "(1/3)" in the title (or a leading line when there is
+/// none).
+///
+/// Parts are cut from the source markdown, but escaping and mention expansion
+/// can make the rendered payload larger, so the rendered parts are measured
+/// and the markdown is re-split with a smaller budget until they fit.
+fn split_event(event: &UemEvent, adapter: &dyn WebhookAdapter) -> Vec<UemEvent> {
+    let Some(limit) = adapter.message_limit() else {
+        return vec![event.clone()];
     };
+    if rendered_size(adapter, &limit, event) <= limit.max {
+        return vec![event.clone()];
+    }
+    // The title gets at most a quarter of the limit so the body keeps room;
+    // the reserve covers the part label.
+    let title = event.title.as_deref().map(|title| truncate_title(title, limit.max / 4, &limit));
+    let reserve = title.as_deref().map_or(0, |title| limit.measure(title)) + 32;
+    let mut budget = limit.max.saturating_sub(reserve);
+    loop {
+        let parts = split_parts(event, title.as_deref(), budget, &limit);
+        let largest = parts
+            .iter()
+            .map(|part| rendered_size(adapter, &limit, part))
+            .max()
+            .unwrap_or(0);
+        if largest <= limit.max || budget <= MIN_SPLIT_BUDGET {
+            return parts;
+        }
+        // Shrink by the overshoot, and by at least a tenth so this ends.
+        let scaled = budget * limit.max / largest;
+        budget = scaled.min(budget - budget / 10).max(MIN_SPLIT_BUDGET);
+    }
+}
+
+/// Smallest source budget [`split_event`] tries before giving up on fitting
+/// the rendered parts.
+const MIN_SPLIT_BUDGET: usize = 256;
+
+/// Splits the markdown into chunks of at most `budget` and labels them.
+fn split_parts(event: &UemEvent, title: Option<&str>, budget: usize, limit: &MessageLimit) -> Vec<UemEvent> {
+    let chunks = split_markdown(&event.markdown, budget, &|text| limit.measure(text));
+    if chunks.len() <= 1 {
+        return vec![event.clone()];
+    }
+
+    let count = chunks.len();
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, markdown)| {
+            let label = format!("({}/{})", index + 1, count);
+            let mut part = event.clone();
+            part.mentions = collect_mentions(&markdown, &event.mentions);
+            match title {
+                Some(title) => {
+                    part.title = Some(format!("{} {}", title, label));
+                    part.markdown = markdown;
+                }
+                None => part.markdown = format!("{}\n\n{}", label, markdown),
+            }
+            part
+        })
+        .collect()
+}
+
+/// Size of the payload `adapter` renders for `event`, escaping included.
+/// Events that fail to render measure 0 and fail again on delivery.
+fn rendered_size(adapter: &dyn WebhookAdapter, limit: &MessageLimit, event: &UemEvent) -> usize {
+    adapter
+        .uem_to_egress(event)
+        .map_or(0, |payload| limit.measure(&payload.body.to_string()))
+}
+
+/// Cuts `title` to measure at most `max`, marking the cut with an ellipsis.
+fn truncate_title(title: &str, max: usize, limit: &MessageLimit) -> String {
+    if limit.measure(title) <= max {
+        return title.to_string();
+    }
+    let mut size = limit.measure("…");
+    let mut end = 0;
+    for (index, ch) in title.char_indices() {
+        size += limit.measure(ch.encode_utf8(&mut [0; 4]));
+        if size > max {
+            break;
+        }
+        end = index + ch.len_utf8();
+    }
+    format!("{}…", &title[..end])
+}
+
+/// Converts and sends one event (or part) and returns the delivery status,
+/// response code and error. `follow_up` is set for every part after the first.
+async fn deliver_part(
+    state: &AppState,
+    adapter: &dyn WebhookAdapter,
+    event: &UemEvent,
+    document: &Document,
+    target: &Target,
+    follow_up: bool,
+) -> (&'static str, Option<u16>, Option<String>) {
+    let span = tracing::info_span!("render", kind = %target.kind);
+    // Plugin calls may run until their fuel is used up, so they get a blocking thread.
+    let payload = match plugin::egress_adapter(&target.kind) {
+        Some(plugin) => plugin.uem_to_egress_blocking(event.clone()).instrument(span).await,
+        None => span.in_scope(|| match adapter.renderer() {
+            Some(renderer) => render_event(renderer, event, document),
+            None => adapter.uem_to_egress(event),
+        }),
+    };
+    let payload = match payload {
+        Ok(payload) => payload,
+        Err(err) => return ("failed", None, Some(err.message)),
+    };
+
+    let span = tracing::info_span!("send", kind = %target.kind);
+    if target.kind == "slack_api" {
+        let sent = slack_api::send(&state.http, &state.db, target, event, payload.body, follow_up)
+            .instrument(span)
+            .await;
+        return match sent {
+            Ok(code) => ("sent", Some(code), None),
+            Err(err) => ("failed", err.response_code, Some(err.message)),
+        };
+    }
 
     let mut request = state
         .http
@@ -255,46 +1106,60 @@ async fn dispatch_to_target(
     if let Some(headers) = target.headers.as_ref().and_then(|value| value.as_object()) {
         for (key, value) in headers {
             if let Some(value) = value.as_str() {
-                request = request.header(key, value);
-            }
-        }
-    }
-
-    let response = request.send().await;
-    match response {
-        Ok(resp) => {
-            let status = if resp.status().is_success() { "sent" } else { "failed" };
-            let code = resp.status().as_u16();
-            let error = if resp.status().is_success() {
-                None
-            } else {
-                Some(format!("non-success status: {}", resp.status()))
-            };
-            let _ = state
-                .db
-                .insert_delivery(&event.id, &target.id, status, Some(code), error.clone())
-                .await;
-            DeliveryOutcome {
-                target_id: target.id.clone(),
-                status: status.to_string(),
-                response_code: Some(code),
-                error,
-            }
-        }
-        Err(err) => {
-            let message = err.to_string();
-            let _ = state
-                .db
-                .insert_delivery(&event.id, &target.id, "failed", None, Some(message.clone()))
-                .await;
-            DeliveryOutcome {
-                target_id: target.id.clone(),
-                status: "failed".to_string(),
-                response_code: None,
-                error: Some(message),
+                request = request.header(key, value);
             }
         }
     }
+
+    // Custom HTTP receivers can continue the trace; chat platforms ignore it.
+    if matches!(target.kind.as_str(), "http" | "custom") {
+        let mut headers = HeaderMap::new();
+        span.in_scope(|| inject_trace_context(&mut headers));
+        request = request.headers(headers);
+    }
+
+    match request.send().instrument(span).await {
+        Ok(resp) if resp.status().is_success() => ("sent", Some(resp.status().as_u16()), None),
+        Ok(resp) => (
+            "failed",
+            Some(resp.status().as_u16()),
+            Some(format!("non-success status: {}", resp.status())),
+        ),
+        // The URL holds the webhook token, so keep it out of delivery records.
+        Err(err) => ("failed", None, Some(err.without_url().to_string())),
+    }
+}
+
+/// Records one delivery attempt (or skipped part) and returns its outcome.
+async fn record_delivery(
+    state: &AppState,
+    event: &UemEvent,
+    target: &Target,
+    request_id: Option<&str>,
+    part: Option<(u32, u32)>,
+    (status, response_code, error): (&str, Option<u16>, Option<String>),
+) -> DeliveryOutcome {
+    metrics()
+        .deliveries
+        .with_label_values(&[&target.id, &target.kind, status])
+        .inc();
+    if status == "sent" {
+        info!(status, response_code, "delivered");
+    } else {
+        warn!(status, response_code, error = error.as_deref(), "delivery not sent");
+    }
+    let outcome = DeliveryOutcome {
+        target_id: target.id.clone(),
+        status: status.to_string(),
+        response_code,
+        error,
+        part: part.map(|(part, _)| part),
+        parts: part.map(|(_, parts)| parts),
+    };
+    if let Err(err) = state.db.insert_delivery(&event.id, request_id, &outcome).await {
+        error!("failed to record delivery: {}", err);
+    }
+    outcome
 }
 
 #[utoipa::path(
@@ -322,18 +1187,24 @@ async fn create_target(
         return Err(AppError::not_found("endpoint not found"));
     }
 
+    if req.kind == "slack_api" {
+        SlackApiConfig::from_value(req.config.as_ref()).map_err(AppError::bad_request)?;
+    }
+
     let target = state.db.create_target(&endpoint_id, req).await.map_err(AppError::from)?;
-    Ok(Json(target))
+    Ok(Json(secrets::mask_target(target)))
 }
 
 #[utoipa::path(
     get,
     path = "/api/endpoints/{id}/targets",
     params(
-        ("id" = String, Path, description = "Endpoint ID")
+        ("id" = String, Path, description = "Endpoint ID"),
+        ("reveal" = Option<bool>, Query, description = "Return secrets unmasked (admin only)")
     ),
     responses(
-        (status = 200, description = "List of targets", body = [Target])
+        (status = 200, description = "List of targets; `url`, `headers` and credential `config` values are masked", body = [Target]),
+        (status = 403, description = "Revealing secrets requires the admin role", body = AppErrorResponse)
     ),
     security(
         ("basic_auth" = [])
@@ -342,9 +1213,15 @@ async fn create_target(
 async fn list_targets(
     Path(endpoint_id): Path<String>,
     State(state): State<AppState>,
+    Extension(role): Extension<Role>,
+    Query(query): Query<RevealQuery>,
 ) -> Result<Json<Vec<Target>>, AppError> {
+    let reveal = reveal_secrets(role, &query)?;
     let targets = state.db.list_targets(&endpoint_id).await.map_err(AppError::from)?;
-    Ok(Json(targets))
+    if reveal {
+        return Ok(Json(targets));
+    }
+    Ok(Json(targets.into_iter().map(secrets::mask_target).collect()))
 }
 
 #[utoipa::path(
@@ -389,12 +1266,18 @@ async fn create_endpoint(
     State(state): State<AppState>,
     Json(req): Json<CreateEndpointRequest>,
 ) -> Result<Json<Endpoint>, AppError> {
+    if let Some(platforms) = &req.allowed_platforms {
+        validate_allowed_platforms(platforms).map_err(AppError::bad_request)?;
+    }
+    if let Some(source) = &req.script {
+        script::compile(source).map_err(|err| AppError::bad_request(err.message))?;
+    }
     let endpoint = state
         .db
         .create_endpoint(req)
         .await
         .map_err(AppError::from)?;
-    Ok(Json(endpoint))
+    Ok(Json(secrets::mask_endpoint(endpoint)))
 }
 
 #[utoipa::path(
@@ -409,7 +1292,7 @@ async fn create_endpoint(
 )]
 async fn list_endpoints(State(state): State<AppState>) -> Result<Json<Vec<Endpoint>>, AppError> {
     let endpoints = state.db.list_endpoints().await.map_err(AppError::from)?;
-    Ok(Json(endpoints))
+    Ok(Json(endpoints.into_iter().map(secrets::mask_endpoint).collect()))
 }
 
 #[utoipa::path(
@@ -433,16 +1316,31 @@ async fn update_endpoint(
     State(state): State<AppState>,
     Json(req): Json<UpdateEndpointRequest>,
 ) -> Result<Json<Endpoint>, AppError> {
-    if req.name.is_none() && req.banner.is_none() && req.footer.is_none() {
+    if req.name.is_none()
+        && req.banner.is_none()
+        && req.footer.is_none()
+        && req.ingress_config.is_none()
+        && req.allowed_platforms.is_none()
+        && req.script.is_none()
+    {
         return Err(AppError::bad_request("no fields to update"));
     }
+    if let Some(platforms) = &req.allowed_platforms {
+        validate_allowed_platforms(platforms).map_err(AppError::bad_request)?;
+    }
+    if let Some(source) = &req.script {
+        script::compile(source).map_err(|err| AppError::bad_request(err.message))?;
+    }
+    if req.ingress_config.as_ref().is_some_and(secrets::contains_mask) {
+        return Err(AppError::bad_request("ingress_config contains masked secrets"));
+    }
     let endpoint = state
         .db
         .update_endpoint(&id, req)
         .await
         .map_err(AppError::from)?;
     let endpoint = endpoint.ok_or_else(|| AppError::not_found("endpoint not found"))?;
-    Ok(Json(endpoint))
+    Ok(Json(secrets::mask_endpoint(endpoint)))
 }
 
 #[utoipa::path(
@@ -494,8 +1392,11 @@ async fn delete_endpoint(
 async fn test_send(
     Path(endpoint_id): Path<String>,
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(req): Json<TestSendRequest>,
 ) -> Result<Json<Value>, AppError> {
+    let request_id = request_id(&headers);
+    let request_id = request_id.as_deref();
     let endpoint = state
         .db
         .get_endpoint(&endpoint_id)
@@ -511,44 +1412,31 @@ async fn test_send(
         markdown: req.markdown,
         raw: json!({"test": true}),
         meta: json!({}),
+        mentions: Vec::new(),
     };
 
-    // Apply banner/footer concatenation with proper formatting
-    let mut final_markdown = String::new();
-    
-    if let Some(banner) = &endpoint.banner {
-        if !banner.is_empty() {
-            final_markdown.push_str(banner.trim());
-            final_markdown.push_str("\n\n");
-        }
-    }
-    
-    final_markdown.push_str(event.markdown.trim());
-    
-    if let Some(footer) = &endpoint.footer {
-        if !footer.is_empty() {
-            final_markdown.push_str("\n\n");
-            final_markdown.push_str(footer.trim());
-        }
-    }
-    
-    // Format the concatenated markdown for proper spacing
-    event.markdown = format_markdown(&final_markdown)
-        .unwrap_or_else(|_| final_markdown);
+    finalize_event(&state, &endpoint, &mut event, Vec::new()).await?;
 
     // Fetch targets and dispatch
     let targets = state.db.list_targets(&endpoint_id).await.map_err(AppError::from)?;
+    let document = Arc::new(Document::parse(&event.markdown));
     let mut tasks = JoinSet::new();
     for target in targets {
         let state = state.clone();
         let event = event.clone();
-        tasks.spawn(async move { dispatch_to_target(&state, &event, &target).await });
+        let document = document.clone();
+        let request_id = request_id.map(String::from);
+        let span = dispatch_span(&endpoint.id, &event.id, &target, request_id.as_deref());
+        tasks.spawn(
+            async move { dispatch_to_target(&state, &event, &document, &target, request_id.as_deref()).await }
+                .instrument(span),
+        );
     }
 
     let mut outcomes = Vec::new();
     while let Some(result) = tasks.join_next().await {
         match result {
-            Ok(outcome) => outcomes.push(outcome),
+            Ok(parts) => outcomes.extend(parts),
             Err(err) => error!("dispatch task failed: {}", err),
         }
     }
@@ -559,6 +1447,73 @@ async fn test_send(
     })))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/scripts/test",
+    request_body = ScriptTestRequest,
+    responses(
+        (status = 200, description = "Script ran; script errors are reported in `error`", body = ScriptTestResult),
+        (status = 400, description = "Unsupported platform or unparseable payload", body = AppErrorResponse)
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+async fn test_script(
+    State(state): State<AppState>,
+    Json(req): Json<ScriptTestRequest>,
+) -> Result<Json<ScriptTestResult>, AppError> {
+    let body = IngressBody {
+        bytes: req.payload.to_string().into_bytes(),
+        value: req.payload,
+        content_type: "application/json".to_string(),
+    };
+    let platform = match req.platform.as_deref() {
+        Some(platform) => ingress_platform(platform).ok_or_else(|| AppError::bad_request("unsupported platform"))?,
+        None => detect_platforms(&HeaderMap::new(), &HashMap::new(), &body)
+            .first()
+            .copied()
+            .unwrap_or("custom"),
+    };
+    let adapter = ingress_adapter(platform).expect("ingress platforms have adapters");
+    let mut event = ingress_body_to_uem(adapter.as_ref(), platform, &body)
+        .await
+        .map_err(|err| AppError::bad_request(err.message))?;
+    if event.id.is_empty() {
+        event.id = Uuid::new_v4().to_string();
+    }
+
+    let limits = state.script_limits;
+    let (result, event) = tokio::task::spawn_blocking(move || {
+        let result = script::run(&req.script, &mut event, &limits);
+        (result, event)
+    })
+    .await
+    .map_err(|err| AppError {
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+        message: format!("script task failed: {}", err),
+    })?;
+
+    Ok(Json(match result {
+        Ok(run) => ScriptTestResult {
+            platform: platform.to_string(),
+            dropped: run.outcome == ScriptOutcome::Drop,
+            event: (run.outcome == ScriptOutcome::Keep).then(|| script::event_value(&event)),
+            output: run.output,
+            error: None,
+            duration_ms: run.duration.as_millis() as u64,
+        },
+        Err(err) => ScriptTestResult {
+            platform: platform.to_string(),
+            dropped: false,
+            event: None,
+            output: Vec::new(),
+            error: Some(err.message),
+            duration_ms: 0,
+        },
+    }))
+}
+
 fn now_timestamp() -> i64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -590,12 +1545,18 @@ async fn get_endpoint(
         .await
         .map_err(AppError::from)?;
     let endpoint = endpoint.ok_or_else(|| AppError::not_found("endpoint not found"))?;
-    Ok(Json(endpoint))
+    Ok(Json(secrets::mask_endpoint(endpoint)))
 }
 
 #[derive(serde::Deserialize)]
 struct ListEventsQuery {
     endpoint_id: Option<String>,
+    q: Option<String>,
+    platform: Option<String>,
+    target_id: Option<String>,
+    delivery: Option<DeliveryFilter>,
+    since: Option<i64>,
+    until: Option<i64>,
     page: Option<i64>,
     page_size: Option<i64>,
 }
@@ -605,6 +1566,12 @@ struct ListEventsQuery {
     path = "/api/events",
     params(
         ("endpoint_id" = Option<String>, Query, description = "Filter by endpoint ID"),
+        ("q" = Option<String>, Query, description = "Words or \"quoted phrases\" that must all appear in the title or markdown"),
+        ("platform" = Option<String>, Query, description = "Filter by ingress platform (or mapping name)"),
+        ("target_id" = Option<String>, Query, description = "Only events delivered (or attempted) to this target"),
+        ("delivery" = Option<DeliveryFilter>, Query, description = "`failed`: any delivery failed; `sent`: every delivery was sent"),
+        ("since" = Option<i64>, Query, description = "Created at or after this Unix time"),
+        ("until" = Option<i64>, Query, description = "Created before this Unix time"),
         ("page" = Option<i64>, Query, description = "Page number (default: 1)"),
         ("page_size" = Option<i64>, Query, description = "Items per page (default: 50, max: 100)")
     ),
@@ -619,21 +1586,345 @@ async fn list_events(
     State(state): State<AppState>,
     Query(query): Query<ListEventsQuery>,
 ) -> Result<Json<Vec<EventRecord>>, AppError> {
+    let filter = EventFilter {
+        endpoint_id: query.endpoint_id,
+        q: query.q.filter(|q| !q.trim().is_empty()),
+        platform: query.platform,
+        target_id: query.target_id,
+        delivery: query.delivery,
+        since: query.since,
+        until: query.until,
+    };
     let events = state
         .db
-        .list_events(query.endpoint_id.as_deref(), query.page, query.page_size)
+        .list_events(&filter, query.page, query.page_size)
         .await
         .map_err(AppError::from)?;
     Ok(Json(events))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/people",
+    request_body = CreatePersonRequest,
+    responses(
+        (status = 200, description = "Person created successfully", body = Person),
+        (status = 400, description = "Bad request", body = AppErrorResponse),
+        (status = 409, description = "Handle already assigned", body = AppErrorResponse)
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+async fn create_person(
+    State(state): State<AppState>,
+    Json(mut req): Json<CreatePersonRequest>,
+) -> Result<Json<Person>, AppError> {
+    if let Some(handles) = req.handles.take() {
+        req.handles = Some(normalize_handles(handles)?);
+    }
+    let person = state.db.create_person(req).await.map_err(AppError::from)?;
+    Ok(Json(person))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/people",
+    responses(
+        (status = 200, description = "List of people", body = [Person])
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+async fn list_people(State(state): State<AppState>) -> Result<Json<Vec<Person>>, AppError> {
+    let people = state.db.list_people().await.map_err(AppError::from)?;
+    Ok(Json(people))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/people/{id}",
+    params(
+        ("id" = String, Path, description = "Person ID")
+    ),
+    responses(
+        (status = 200, description = "Person details", body = Person),
+        (status = 404, description = "Person not found", body = AppErrorResponse)
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+async fn get_person(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Person>, AppError> {
+    let person = state.db.get_person(&id).await.map_err(AppError::from)?;
+    let person = person.ok_or_else(|| AppError::not_found("person not found"))?;
+    Ok(Json(person))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/people/{id}",
+    params(
+        ("id" = String, Path, description = "Person ID")
+    ),
+    request_body = UpdatePersonRequest,
+    responses(
+        (status = 200, description = "Person updated successfully", body = Person),
+        (status = 400, description = "Bad request", body = AppErrorResponse),
+        (status = 404, description = "Person not found", body = AppErrorResponse),
+        (status = 409, description = "Handle already assigned", body = AppErrorResponse)
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+async fn update_person(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(mut req): Json<UpdatePersonRequest>,
+) -> Result<Json<Person>, AppError> {
+    if req.name.is_none() && req.handles.is_none() {
+        return Err(AppError::bad_request("no fields to update"));
+    }
+    if let Some(handles) = req.handles.take() {
+        req.handles = Some(normalize_handles(handles)?);
+    }
+    let person = state
+        .db
+        .update_person(&id, req)
+        .await
+        .map_err(AppError::from)?;
+    let person = person.ok_or_else(|| AppError::not_found("person not found"))?;
+    Ok(Json(person))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/people/{id}",
+    params(
+        ("id" = String, Path, description = "Person ID")
+    ),
+    responses(
+        (status = 204, description = "Person deleted successfully"),
+        (status = 404, description = "Person not found", body = AppErrorResponse)
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+async fn delete_person(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    let affected = state.db.delete_person(&id).await.map_err(AppError::from)?;
+    if affected == 0 {
+        return Err(AppError::not_found("person not found"));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/mappings",
+    request_body = CreateMappingRequest,
+    responses(
+        (status = 200, description = "Mapping created successfully", body = Mapping),
+        (status = 400, description = "Invalid name, JSONPath or template", body = AppErrorResponse),
+        (status = 409, description = "Mapping name already taken", body = AppErrorResponse)
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+async fn create_mapping(
+    State(state): State<AppState>,
+    Json(req): Json<CreateMappingRequest>,
+) -> Result<Json<Mapping>, AppError> {
+    let mapping = Mapping {
+        id: Uuid::new_v4().to_string(),
+        name: req.name,
+        id_path: req.id_path,
+        title_path: req.title_path,
+        timestamp_path: req.timestamp_path,
+        meta: req.meta.unwrap_or_default(),
+        template: req.template,
+        created_at: now_timestamp(),
+    };
+    validate_mapping(&mapping).map_err(AppError::bad_request)?;
+    state.db.create_mapping(&mapping).await.map_err(AppError::from)?;
+    Ok(Json(mapping))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/mappings",
+    responses(
+        (status = 200, description = "List of mappings", body = [Mapping])
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+async fn list_mappings(State(state): State<AppState>) -> Result<Json<Vec<Mapping>>, AppError> {
+    let mappings = state.db.list_mappings().await.map_err(AppError::from)?;
+    Ok(Json(mappings))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/mappings/{id}",
+    params(
+        ("id" = String, Path, description = "Mapping ID")
+    ),
+    responses(
+        (status = 200, description = "Mapping details", body = Mapping),
+        (status = 404, description = "Mapping not found", body = AppErrorResponse)
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+async fn get_mapping(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Mapping>, AppError> {
+    let mapping = state.db.get_mapping(&id).await.map_err(AppError::from)?;
+    let mapping = mapping.ok_or_else(|| AppError::not_found("mapping not found"))?;
+    Ok(Json(mapping))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/mappings/{id}",
+    params(
+        ("id" = String, Path, description = "Mapping ID")
+    ),
+    request_body = UpdateMappingRequest,
+    responses(
+        (status = 200, description = "Mapping updated successfully", body = Mapping),
+        (status = 400, description = "Invalid name, JSONPath or template", body = AppErrorResponse),
+        (status = 404, description = "Mapping not found", body = AppErrorResponse),
+        (status = 409, description = "Mapping name already taken", body = AppErrorResponse)
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+async fn update_mapping(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(req): Json<UpdateMappingRequest>,
+) -> Result<Json<Mapping>, AppError> {
+    let mapping = state.db.get_mapping(&id).await.map_err(AppError::from)?;
+    let mut mapping = mapping.ok_or_else(|| AppError::not_found("mapping not found"))?;
+    if let Some(name) = req.name {
+        mapping.name = name;
+    }
+    if req.id_path.is_some() {
+        mapping.id_path = req.id_path;
+    }
+    if req.title_path.is_some() {
+        mapping.title_path = req.title_path;
+    }
+    if req.timestamp_path.is_some() {
+        mapping.timestamp_path = req.timestamp_path;
+    }
+    if let Some(meta) = req.meta {
+        mapping.meta = meta;
+    }
+    if let Some(template) = req.template {
+        mapping.template = template;
+    }
+    validate_mapping(&mapping).map_err(AppError::bad_request)?;
+    let affected = state.db.update_mapping(&mapping).await.map_err(AppError::from)?;
+    if affected == 0 {
+        return Err(AppError::not_found("mapping not found"));
+    }
+    Ok(Json(mapping))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/mappings/{id}",
+    params(
+        ("id" = String, Path, description = "Mapping ID")
+    ),
+    responses(
+        (status = 204, description = "Mapping deleted successfully"),
+        (status = 404, description = "Mapping not found", body = AppErrorResponse)
+    ),
+    security(
+        ("basic_auth" = [])
+    )
+)]
+async fn delete_mapping(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    let affected = state.db.delete_mapping(&id).await.map_err(AppError::from)?;
+    if affected == 0 {
+        return Err(AppError::not_found("mapping not found"));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Rewrites platform aliases (e.g. `feishu`) to their canonical names and
+/// rejects platforms that have no mention syntax.
+fn normalize_handles(
+    handles: BTreeMap<String, String>,
+) -> Result<BTreeMap<String, String>, AppError> {
+    let mut normalized = BTreeMap::new();
+    for (platform, handle) in handles {
+        let platform = canonical_platform(&platform).ok_or_else(|| {
+            AppError::bad_request(format!("unsupported mention platform: {platform}"))
+        })?;
+        let handle = handle.trim();
+        if handle.is_empty() {
+            return Err(AppError::bad_request(format!("empty handle for {platform}")));
+        }
+        normalized.insert(platform.to_string(), handle.to_string());
+    }
+    Ok(normalized)
+}
+
+/// Console API role, set by [`basic_auth`] as a request extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Admin,
+    /// Read-only: `GET` requests only, secrets stay masked.
+    Viewer,
+}
+
 pub async fn basic_auth(
     State(state): State<AppState>,
-    request: axum::http::Request<axum::body::Body>,
+    mut request: axum::http::Request<axum::body::Body>,
     next: axum::middleware::Next,
 ) -> Result<Response, StatusCode> {
-    if is_authorized(&state.auth, request.headers()) {
-        return Ok(next.run(request).await);
+    let role = if is_authorized(&state.auth, request.headers()) {
+        Some(Role::Admin)
+    } else if state
+        .viewer
+        .as_ref()
+        .is_some_and(|viewer| is_authorized(viewer, request.headers()))
+    {
+        Some(Role::Viewer)
+    } else {
+        None
+    };
+    match role {
+        Some(Role::Viewer) if !matches!(*request.method(), Method::GET | Method::HEAD) => {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        Some(role) => {
+            request.extensions_mut().insert(role);
+            return Ok(next.run(request).await);
+        }
+        None => {}
     }
 
     // Return 403 Forbidden without WWW-Authenticate header to prevent browser's native auth dialog
@@ -682,6 +1973,13 @@ impl AppError {
         }
     }
 
+    fn forbidden(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            message: message.into(),
+        }
+    }
+
     fn not_found(message: impl Into<String>) -> Self {
         Self {
             status: StatusCode::NOT_FOUND,
@@ -692,8 +1990,12 @@ impl AppError {
 
 impl From<sqlx::Error> for AppError {
     fn from(err: sqlx::Error) -> Self {
+        let status = match err.as_database_error() {
+            Some(db_err) if db_err.is_unique_violation() => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
         Self {
-            status: StatusCode::INTERNAL_SERVER_ERROR,
+            status,
             message: err.to_string(),
         }
     }
@@ -705,3 +2007,185 @@ impl IntoResponse for AppError {
         (self.status, body).into_response()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::{SlackAdapter, WecomAdapter};
+
+    #[test]
+    fn split_event_numbers_parts_within_limit() {
+        let limit = WecomAdapter.message_limit().expect("wecom limit");
+        let markdown = (1..=200)
+            .map(|n| format!("- series {n}: value above threshold <@wecom:oncall>"))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let event = UemEvent {
+            id: "evt".to_string(),
+            source: "custom".to_string(),
+            timestamp: 0,
+            title: Some("Alert".to_string()),
+            markdown: markdown.clone(),
+            raw: json!({}),
+            meta: json!({}),
+            mentions: vec![Mention::new("wecom", "oncall", None)],
+        };
+
+        let parts = split_event(&event, &WecomAdapter);
+        assert!(parts.len() > 1);
+        for (index, part) in parts.iter().enumerate() {
+            assert_eq!(
+                part.title.as_deref(),
+                Some(format!("Alert ({}/{})", index + 1, parts.len()).as_str())
+            );
+            let payload = WecomAdapter.uem_to_egress(part).expect("egress");
+            let content = payload.body["markdown"]["content"].as_str().unwrap();
+            assert!(content.len() <= limit.max);
+            assert_eq!(part.mentions.len(), 1);
+        }
+        let rejoined = parts
+            .iter()
+            .map(|part| part.markdown.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        assert_eq!(rejoined, markdown);
+
+        let long_title = UemEvent {
+            title: Some("T".repeat(limit.max)),
+            ..event.clone()
+        };
+        let parts = split_event(&long_title, &WecomAdapter);
+        assert!(parts.len() <= 5, "{} parts", parts.len());
+        for part in &parts {
+            let payload = WecomAdapter.uem_to_egress(part).expect("egress");
+            assert!(payload.body["markdown"]["content"].as_str().unwrap().len() <= limit.max);
+        }
+
+        let short = UemEvent { markdown: "short".to_string(), ..event };
+        assert_eq!(split_event(&short, &WecomAdapter).len(), 1);
+    }
+
+    #[test]
+    fn split_event_fits_rendered_parts_after_escaping() {
+        let limit = SlackAdapter.message_limit().expect("slack limit");
+        // Every `&` renders as `&amp;`, five times its source size.
+        let markdown = (1..=400)
+            .map(|n| format!("{n} {}", "&".repeat(95)))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let event = UemEvent {
+            id: "evt".to_string(),
+            source: "custom".to_string(),
+            timestamp: 0,
+            title: None,
+            markdown: markdown.clone(),
+            raw: json!({}),
+            meta: json!({}),
+            mentions: Vec::new(),
+        };
+
+        let parts = split_event(&event, &SlackAdapter);
+        assert!(parts.len() > 1);
+        for part in &parts {
+            let payload = SlackAdapter.uem_to_egress(part).expect("egress");
+            assert!(limit.measure(payload.body["text"].as_str().unwrap()) <= limit.max);
+        }
+        let rejoined = parts
+            .iter()
+            .map(|part| part.markdown.split_once("\n\n").unwrap().1)
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        assert_eq!(rejoined, markdown);
+    }
+
+    #[tokio::test]
+    async fn split_parts_reply_in_thread_on_update_mode_targets() {
+        use crate::slack_api::tests::{slack_target, start_mock};
+
+        let (url, calls) = start_mock().await;
+        let db = Db::connect(":memory:").await.unwrap();
+        let target = slack_target(&db, &url, "update").await;
+        let state = AppState {
+            db,
+            auth: BasicAuth {
+                username: "admin".to_string(),
+                password: "admin".to_string(),
+            },
+            viewer: None,
+            http: reqwest::Client::new(),
+            media: None,
+            console_html: Arc::from(""),
+            started_at: std::time::Instant::now(),
+            script_limits: ScriptLimits::default(),
+        };
+
+        let event = UemEvent {
+            id: "evt".to_string(),
+            source: "custom".to_string(),
+            timestamp: 0,
+            title: Some("Alert".to_string()),
+            markdown: vec!["x".repeat(1_000); 60].join("\n\n"),
+            raw: json!({ "fingerprint": "fp-1" }),
+            meta: json!({}),
+            mentions: Vec::new(),
+        };
+        let outcomes = dispatch_to_target(&state, &event, &Document::parse(&event.markdown), &target, None).await;
+        assert!(outcomes.len() > 1);
+        assert!(outcomes.iter().all(|outcome| outcome.status == "sent"));
+
+        let resolved = UemEvent {
+            markdown: "resolved".to_string(),
+            ..event
+        };
+        dispatch_to_target(&state, &resolved, &Document::parse(&resolved.markdown), &target, None).await;
+
+        let calls = calls.lock().unwrap();
+        let (first, rest) = calls.split_first().unwrap();
+        let (last, parts) = rest.split_last().unwrap();
+        assert_eq!(first.0, "chat.postMessage");
+        assert_eq!(first.2.get("thread_ts"), None);
+        assert_eq!(parts.len(), outcomes.len() - 1);
+        for (method, _, body) in parts {
+            assert_eq!(method, "chat.postMessage");
+            assert_eq!(body["thread_ts"], "1700000000.000001");
+        }
+        assert_eq!(last.0, "chat.update");
+        assert_eq!(last.2["ts"], "1700000000.000001");
+    }
+
+    #[test]
+    fn resolves_platforms_allowed_by_the_endpoint() {
+        let endpoint = |allowed: Option<&[&str]>, ingress_config: Option<Value>| Endpoint {
+            id: "ep".to_string(),
+            name: "ep".to_string(),
+            banner: None,
+            footer: None,
+            ingress_config,
+            allowed_platforms: allowed.map(|platforms| platforms.iter().map(|name| name.to_string()).collect()),
+            script: None,
+            created_at: 0,
+        };
+        let value = json!({"msgtype": "text", "text": {"content": "disk full"}});
+        let body = IngressBody {
+            bytes: value.to_string().into_bytes(),
+            value,
+            content_type: "application/json".to_string(),
+        };
+        let resolve = |endpoint: &Endpoint, platform: Option<&str>| {
+            resolve_platform(endpoint, platform, &HeaderMap::new(), &HashMap::new(), &body)
+        };
+
+        assert_eq!(resolve(&endpoint(None, None), None).unwrap(), "dingtalk");
+        assert_eq!(resolve(&endpoint(Some(&["wecom"]), None), None).unwrap(), "wecom");
+        let configured = endpoint(None, Some(json!({"wecom": {"token": "t"}})));
+        assert_eq!(resolve(&configured, None).unwrap(), "wecom");
+
+        let slack_only = endpoint(Some(&["slack"]), None);
+        let platform = resolve(&slack_only, None).unwrap();
+        let err = ensure_platform_allowed(&slack_only, platform).unwrap_err();
+        assert_eq!(err.status, StatusCode::FORBIDDEN);
+        assert_eq!(err.message, "platform dingtalk is not allowed for this endpoint");
+        assert_eq!(resolve(&endpoint(Some(&["lark"]), None), Some("feishu")).unwrap(), "lark");
+        assert_eq!(resolve(&slack_only, Some("nope")).unwrap_err().status, StatusCode::BAD_REQUEST);
+    }
+}
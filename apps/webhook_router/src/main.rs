@@ -2,18 +2,29 @@ use axum::middleware;
 use axum::routing::{get, post};
 use axum::Router;
 use clap::Parser;
-use tracing_subscriber::EnvFilter;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
 mod adapters;
+mod config;
 mod console_handlers;
 mod db;
+mod document;
 mod handlers;
+mod media;
+mod metrics;
 mod models;
+mod script;
+mod secrets;
+mod slack_api;
+mod telemetry;
 mod utils;
 
-use handlers::{api_router, basic_auth, healthz, ingress, AppState, ApiDoc};
+use handlers::{
+    api_router, basic_auth, healthz, ingress, ingress_detect, media, metrics_handler, readyz, verify_ingress,
+    verify_ingress_detect, AppState, ApiDoc,
+};
 use models::BasicAuth;
 
 #[derive(Debug, Parser)]
@@ -27,24 +38,71 @@ struct Args {
     username: String,
     #[arg(long, env = "WEBHOOK_ROUTER_PASSWORD")]
     password: String,
+    /// Read-only console credentials; target secrets stay masked.
+    #[arg(long, env = "WEBHOOK_ROUTER_VIEWER_USERNAME", requires = "viewer_password")]
+    viewer_username: Option<String>,
+    #[arg(long, env = "WEBHOOK_ROUTER_VIEWER_PASSWORD", requires = "viewer_username")]
+    viewer_password: Option<String>,
     #[arg(long, env = "WEBHOOK_ROUTER_GENERATE_OPENAPI")]
     generate_openapi: bool,
     #[arg(long, env = "WEBHOOK_ROUTER_SWAGGER_UI")]
     swagger_ui: bool,
     #[arg(long, env = "WEBHOOK_ROUTER_PUBLIC_INGRESS_BASE_URL")]
     public_ingress_base_url: Option<String>,
+    /// Copy ingress images into the DB and serve them from `/media/:id`.
+    #[arg(long, env = "WEBHOOK_ROUTER_MEDIA_RELAY")]
+    media_relay: bool,
+    /// Bot token used by the media relay to download private Slack files.
+    #[arg(long, env = "WEBHOOK_ROUTER_SLACK_BOT_TOKEN")]
+    slack_bot_token: Option<String>,
+    /// Days relayed media is kept before it is deleted.
+    #[arg(long, env = "WEBHOOK_ROUTER_MEDIA_RETENTION_DAYS", default_value_t = 30)]
+    media_retention_days: u64,
+    /// Log line format.
+    #[arg(long, env = "WEBHOOK_ROUTER_LOG_FORMAT", value_enum, default_value = "text")]
+    log_format: telemetry::LogFormat,
+    /// OTLP/HTTP collector base URL (e.g. `http://localhost:4318`); spans are
+    /// exported to `<url>/v1/traces`.
+    #[arg(long, env = "WEBHOOK_ROUTER_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+    /// Require the API basic auth credentials for `/metrics`.
+    #[arg(long, env = "WEBHOOK_ROUTER_METRICS_AUTH")]
+    metrics_auth: bool,
+    /// Base64-encoded 32-byte master key that encrypts target secrets at rest.
+    #[arg(long, env = "WEBHOOK_ROUTER_SECRET_KEY", conflicts_with = "secret_key_file")]
+    secret_key: Option<String>,
+    /// File containing the master key.
+    #[arg(long, env = "WEBHOOK_ROUTER_SECRET_KEY_FILE")]
+    secret_key_file: Option<std::path::PathBuf>,
+    /// Comma-separated previous master keys; secrets under them are
+    /// re-encrypted with the current key at startup.
+    #[arg(long, env = "WEBHOOK_ROUTER_PREVIOUS_SECRET_KEYS", value_delimiter = ',')]
+    previous_secret_keys: Vec<String>,
+    /// YAML or TOML file declaring endpoints and targets; the DB is
+    /// reconciled to match it at startup.
+    #[arg(long, env = "WEBHOOK_ROUTER_CONFIG")]
+    config: Option<std::path::PathBuf>,
+    /// `strict` also deletes endpoints and targets the config doesn't declare.
+    #[arg(long, env = "WEBHOOK_ROUTER_CONFIG_MODE", value_enum, default_value = "merge")]
+    config_mode: config::ConfigMode,
+    /// Print the changes the config would make and exit.
+    #[arg(long, requires = "config")]
+    config_dry_run: bool,
+    /// Directory of WebAssembly adapter plugins (`*.wasm`) to load at startup.
+    #[arg(long, env = "WEBHOOK_ROUTER_PLUGINS_DIR")]
+    plugins_dir: Option<std::path::PathBuf>,
+    /// Wall-clock limit for an endpoint script run, in milliseconds.
+    #[arg(long, env = "WEBHOOK_ROUTER_SCRIPT_TIMEOUT_MS", default_value_t = 100)]
+    script_timeout_ms: u64,
+    /// Operation limit for an endpoint script run.
+    #[arg(long, env = "WEBHOOK_ROUTER_SCRIPT_MAX_OPERATIONS", default_value_t = 1_000_000)]
+    script_max_operations: u64,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| EnvFilter::new("info,tower_http=debug")),
-        )
-        .init();
-
     let args = Args::parse();
+    let tracer_provider = telemetry::init(args.log_format, args.otlp_endpoint.as_deref())?;
 
     if args.generate_openapi {
         let doc = ApiDoc::openapi();
@@ -52,7 +110,60 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
-    let db = db::Db::connect(&args.db_path).await?;
+    // Plugins register platforms and kinds, so they load before the config is validated.
+    if let Some(dir) = &args.plugins_dir {
+        for name in adapters::plugin::load_dir(dir)? {
+            tracing::info!("loaded plugin {}", name);
+        }
+    }
+
+    let router_config = args.config.as_deref().map(config::load).transpose()?;
+
+    let secret_key = match &args.secret_key_file {
+        Some(path) => Some(std::fs::read_to_string(path)?),
+        None => args.secret_key.clone(),
+    };
+    let mut db = db::Db::connect(&args.db_path).await?;
+    if let Some(secret_key) = secret_key {
+        db = db.with_secrets(secrets::SecretKeys::new(&secret_key, &args.previous_secret_keys)?);
+    }
+    adapters::check_mappings_reachable(&db.list_mappings().await?)?;
+    let sealed = db.seal_secrets().await?;
+    if sealed > 0 {
+        tracing::info!("encrypted secrets of {} targets and endpoints with the current key", sealed);
+    }
+
+    if let Some(router_config) = &router_config {
+        let changes = config::plan(&db, router_config, args.config_mode).await?;
+        if args.config_dry_run {
+            for change in &changes {
+                println!("{}", change);
+            }
+            return Ok(());
+        }
+        for change in &changes {
+            tracing::info!("config: {}", change);
+        }
+        config::apply(&db, &changes).await?;
+    }
+
+    let media_relay = if args.media_relay {
+        let relay = args
+            .public_ingress_base_url
+            .as_deref()
+            .and_then(|base| media::MediaRelay::new(base, args.slack_bot_token.clone()));
+        if relay.is_none() {
+            tracing::warn!("--media-relay requires --public-ingress-base-url; media relay disabled");
+        }
+        relay
+    } else {
+        None
+    };
+    // Media already stored still expires after the relay is switched off.
+    media::spawn_cleanup(
+        db.clone(),
+        std::time::Duration::from_secs(args.media_retention_days * 24 * 60 * 60),
+    );
 
     let state = AppState {
         db,
@@ -60,10 +171,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             username: args.username,
             password: args.password,
         },
+        viewer: args
+            .viewer_username
+            .zip(args.viewer_password)
+            .map(|(username, password)| BasicAuth { username, password }),
         http: reqwest::Client::new(),
+        media: media_relay,
         console_html: std::sync::Arc::from(console_handlers::build_console_html(
             args.public_ingress_base_url.as_deref(),
         )),
+        started_at: std::time::Instant::now(),
+        script_limits: script::ScriptLimits {
+            timeout: std::time::Duration::from_millis(args.script_timeout_ms),
+            max_operations: args.script_max_operations,
+        },
     };
 
     // Protected API routes with authentication
@@ -75,10 +196,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/", get(console_handlers::serve_console))
         .nest("/api", protected_api);
 
+    let mut metrics = Router::<AppState>::new().route("/metrics", get(metrics_handler));
+    if args.metrics_auth {
+        metrics = metrics.layer(middleware::from_fn_with_state(state.clone(), basic_auth));
+    }
+
     let mut app = Router::<AppState>::new()
+        .merge(metrics)
         // Public routes (no authentication)
         .route("/healthz", get(healthz))
-        .route("/ingress/:endpoint_id/:platform", post(ingress));
+        .route("/readyz", get(readyz))
+        .route("/ingress/:endpoint_id", post(ingress_detect).get(verify_ingress_detect))
+        .route("/ingress/:endpoint_id/:platform", post(ingress).get(verify_ingress))
+        .route("/media/:id", get(media));
 
     if args.swagger_ui {
         app = app.merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()));
@@ -88,7 +218,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         // Console routes (HTML public, API protected)
         .nest("/console", console)
         .with_state(state)
-        .layer(tower_http::trace::TraceLayer::new_for_http());
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(
+            tower_http::trace::TraceLayer::new_for_http()
+                .make_span_with(|request: &axum::http::Request<axum::body::Body>| {
+                    telemetry::make_request_span(request)
+                }),
+        )
+        // Outermost so the trace span and handlers see the id; a client-sent
+        // X-Request-Id is kept.
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
 
     let listener = tokio::net::TcpListener::bind(&args.bind).await?;
     tracing::info!("listening on {}", args.bind);
@@ -100,6 +239,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     axum::serve(listener, app)
         .with_graceful_shutdown(shutdown_signal())
         .await?;
+
+    // Flushing blocks on the exporter thread.
+    if let Err(err) = tokio::task::spawn_blocking(move || tracer_provider.shutdown()).await? {
+        eprintln!("failed to flush traces: {err}");
+    }
     Ok(())
 }
 
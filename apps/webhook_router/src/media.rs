@@ -0,0 +1,486 @@
+//! Media relay: copies images referenced by ingress events into the DB and
+//! rewrites the event markdown to point at `/media/:id`.
+//!
+//! Ingress images are often private (Slack files) or have no URL at all
+//! (WeCom base64 images), so destinations can't load them directly.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect;
+use serde_json::{json, Value};
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use crate::adapters::InlineMedia;
+use crate::db::Db;
+use crate::models::UemEvent;
+
+/// Largest image the relay will copy.
+pub const MAX_MEDIA_BYTES: usize = 10 * 1024 * 1024;
+/// Most images relayed per event; the rest keep their original reference.
+pub const MAX_MEDIA_PER_EVENT: usize = 8;
+/// Most bytes relayed per event, across all of its images.
+pub const MAX_EVENT_MEDIA_BYTES: usize = 20 * 1024 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// Fetches run concurrently on the ingress path and all of them must finish
+/// within this deadline; images still loading keep their original reference.
+const RELAY_DEADLINE: Duration = Duration::from_secs(10);
+const MAX_REDIRECTS: usize = 5;
+/// How often [`spawn_cleanup`] deletes expired media.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone)]
+pub struct MediaRelay {
+    /// Public URL prefix that relayed ids are appended to.
+    pub base_url: String,
+    /// Bot token used to download private Slack files.
+    pub slack_token: Option<String>,
+    /// Client for remote images; see [`relay_client`].
+    http: reqwest::Client,
+    /// Lets tests fetch from loopback servers.
+    allow_private: bool,
+}
+
+impl MediaRelay {
+    /// Builds the relay from `public_ingress_base_url`; relayed URLs are
+    /// `<base>/media/:id`.
+    pub fn new(public_ingress_base_url: &str, slack_token: Option<String>) -> Option<Self> {
+        let base = public_ingress_base_url.trim().trim_end_matches('/');
+        if base.is_empty() {
+            return None;
+        }
+        Some(Self {
+            base_url: format!("{}/media", base),
+            slack_token,
+            http: relay_client(false),
+            allow_private: false,
+        })
+    }
+
+    pub fn url_for(&self, id: &str) -> String {
+        format!("{}/{}", self.base_url, id)
+    }
+}
+
+/// Relays `inline` media and every remote image in the event markdown,
+/// replacing references with relayed URLs and listing them in `meta.media`.
+/// Images that can't be fetched, or that exceed the per-event limits, keep
+/// their original reference.
+pub async fn relay_event_media(
+    relay: &MediaRelay,
+    db: &Db,
+    event: &mut UemEvent,
+    inline: Vec<InlineMedia>,
+) -> Result<(), sqlx::Error> {
+    let mut relayed = Vec::new();
+    let budget = Arc::new(AtomicUsize::new(MAX_EVENT_MEDIA_BYTES));
+
+    for media in inline {
+        if !event.markdown.contains(&media.placeholder) {
+            continue;
+        }
+        let Some(content_type) = sniff_image_type(&media.data) else {
+            warn!("media relay skipped inline media: not a png, jpeg, gif or webp image");
+            continue;
+        };
+        if relayed.len() >= MAX_MEDIA_PER_EVENT || !take_budget(&budget, media.data.len()) {
+            warn!("media relay skipped inline media: event media limit reached");
+            continue;
+        }
+        let id = db.insert_media(content_type, &media.data, None).await?;
+        let url = relay.url_for(&id);
+        event.markdown = event
+            .markdown
+            .replace(&media.placeholder, &format!("![Image]({})", url));
+        relayed.push(json!({
+            "id": id,
+            "url": url,
+            "content_type": content_type,
+            "size": media.data.len(),
+        }));
+    }
+
+    let mut sources = remote_image_urls(&event.markdown);
+    let room = MAX_MEDIA_PER_EVENT - relayed.len();
+    if sources.len() > room {
+        warn!("media relay skipped {} images: event media limit reached", sources.len() - room);
+        sources.truncate(room);
+    }
+    for (source, (content_type, data)) in fetch_images(relay, sources, budget).await {
+        let id = db.insert_media(content_type, &data, Some(&source)).await?;
+        let url = relay.url_for(&id);
+        event.markdown = event
+            .markdown
+            .replace(&format!("]({})", source), &format!("]({})", url))
+            .replace(&format!("]({} ", source), &format!("]({} ", url));
+        relayed.push(json!({
+            "id": id,
+            "url": url,
+            "source": source,
+            "content_type": content_type,
+            "size": data.len(),
+        }));
+    }
+
+    if !relayed.is_empty() {
+        if !event.meta.is_object() {
+            event.meta = json!({});
+        }
+        event.meta["media"] = Value::Array(relayed);
+    }
+    Ok(())
+}
+
+/// Fetches `sources` concurrently and returns the images that loaded within
+/// [`RELAY_DEADLINE`], in source order.
+async fn fetch_images(
+    relay: &MediaRelay,
+    sources: Vec<String>,
+    budget: Arc<AtomicUsize>,
+) -> Vec<(String, (&'static str, Vec<u8>))> {
+    let mut fetches = JoinSet::new();
+    for (index, source) in sources.into_iter().enumerate() {
+        let relay = relay.clone();
+        let budget = budget.clone();
+        fetches.spawn(async move {
+            let result = fetch_image(&relay, &source, &budget).await;
+            (index, source, result)
+        });
+    }
+
+    let mut fetched = Vec::new();
+    let collect = async {
+        while let Some(joined) = fetches.join_next().await {
+            match joined {
+                Ok((index, source, Ok(image))) => fetched.push((index, source, image)),
+                Ok((_, source, Err(err))) => warn!("media relay skipped {}: {}", source, err),
+                Err(err) => warn!("media relay fetch failed: {}", err),
+            }
+        }
+    };
+    if tokio::time::timeout(RELAY_DEADLINE, collect).await.is_err() {
+        // Dropping the set aborts the fetches still running.
+        warn!("media relay skipped {} images: deadline exceeded", fetches.len());
+    }
+    fetched.sort_by_key(|(index, ..)| *index);
+    fetched
+        .into_iter()
+        .map(|(_, source, image)| (source, image))
+        .collect()
+}
+
+/// Reserves `size` bytes of the event's media budget.
+fn take_budget(budget: &AtomicUsize, size: usize) -> bool {
+    budget
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(size))
+        .is_ok()
+}
+
+/// Deletes media older than `retention` every hour.
+pub fn spawn_cleanup(db: Db, retention: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match db.delete_media_older_than(retention).await {
+                Ok(0) => {}
+                Ok(deleted) => info!("deleted {} expired media items", deleted),
+                Err(err) => warn!("media cleanup failed: {}", err),
+            }
+        }
+    });
+}
+
+fn remote_image_urls(markdown: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for event in Parser::new_ext(markdown, Options::all()) {
+        if let Event::Start(Tag::Image { dest_url, .. }) = event {
+            let url = dest_url.to_string();
+            let remote = url.starts_with("https://") || url.starts_with("http://");
+            if remote && !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+    }
+    urls
+}
+
+/// Downloads an image and returns its sniffed content type. Only png, jpeg,
+/// gif and webp are accepted; anything else (notably SVG, which can carry
+/// scripts) would be served from the router's own origin. Downloaded bytes
+/// are taken from the event's `budget`.
+async fn fetch_image(
+    relay: &MediaRelay,
+    url: &str,
+    budget: &AtomicUsize,
+) -> Result<(&'static str, Vec<u8>), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|err| err.to_string())?;
+    check_url(&parsed, relay.allow_private)?;
+    let mut request = relay.http.get(parsed.clone());
+    if let Some(token) = &relay.slack_token {
+        if parsed.host_str() == Some("files.slack.com") {
+            request = request.bearer_auth(token);
+        }
+    }
+
+    let mut response = request.send().await.map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("non-success status: {}", response.status()));
+    }
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    if !content_type.starts_with("image/") {
+        return Err(format!("not an image: {:?}", content_type));
+    }
+    if response
+        .content_length()
+        .is_some_and(|length| length as usize > MAX_MEDIA_BYTES)
+    {
+        return Err("image too large".to_string());
+    }
+
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|err| err.to_string())? {
+        if data.len() + chunk.len() > MAX_MEDIA_BYTES {
+            return Err("image too large".to_string());
+        }
+        if !take_budget(budget, chunk.len()) {
+            return Err("event media limit reached".to_string());
+        }
+        data.extend_from_slice(&chunk);
+    }
+    let content_type = sniff_image_type(&data)
+        .ok_or_else(|| format!("{:?} is not a png, jpeg, gif or webp image", content_type))?;
+    Ok((content_type, data))
+}
+
+/// The relay's HTTP client. Ingress markdown is untrusted, so unless
+/// `allow_private` is set it only connects to public addresses, checked after
+/// DNS resolution and on every redirect, and it never uses a proxy.
+fn relay_client(allow_private: bool) -> reqwest::Client {
+    let redirects = redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+        match check_url(attempt.url(), allow_private) {
+            Ok(()) => attempt.follow(),
+            Err(err) => attempt.error(err),
+        }
+    });
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(RELAY_DEADLINE)
+        .redirect(redirects)
+        .no_proxy();
+    if !allow_private {
+        builder = builder.dns_resolver(Arc::new(PublicResolver));
+    }
+    builder.build().expect("media relay client config is valid")
+}
+
+/// Rejects non-HTTP URLs and literal non-public IP hosts; host names are
+/// checked by [`PublicResolver`].
+fn check_url(url: &reqwest::Url, allow_private: bool) -> Result<(), String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("unsupported scheme {}", url.scheme()));
+    }
+    let host = url.host_str().unwrap_or("");
+    let literal = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>();
+    match literal {
+        Ok(ip) if !allow_private && !is_public(ip) => Err(format!("{} is not a public address", ip)),
+        _ => Ok(()),
+    }
+}
+
+/// Resolves host names and drops private, loopback and link-local addresses.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // Carrier-grade NAT, 100.64.0.0/10.
+                || (a == 100 && (64..128).contains(&b))
+                // Reserved, 240.0.0.0/4.
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local, fc00::/7.
+                || (first & 0xfe00) == 0xfc00
+                // Link-local, fe80::/10.
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+fn sniff_image_type(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::header;
+    use axum::routing::get;
+    use axum::Router;
+    use base64::Engine;
+
+    use super::*;
+    use crate::adapters::{WebhookAdapter, WecomAdapter};
+
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+    async fn start_image_server() -> String {
+        let app = Router::new()
+            .route("/chart.png", get(|| async { ([(header::CONTENT_TYPE, "image/png")], PNG) }))
+            .route("/page", get(|| async { ([(header::CONTENT_TYPE, "text/html")], "<html>") }))
+            .route("/logo.svg", get(|| async { ([(header::CONTENT_TYPE, "image/svg+xml")], "<svg onload=\"alert(1)\"/>") }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn relays_remote_and_inline_images() {
+        let origin = start_image_server().await;
+        let db = Db::connect(":memory:").await.unwrap();
+        let relay = MediaRelay {
+            http: relay_client(true),
+            allow_private: true,
+            ..MediaRelay::new("https://hooks.example.com/webhooks/", None).unwrap()
+        };
+
+        let payload = json!({
+            "msgtype": "image",
+            "image": { "base64": base64::engine::general_purpose::STANDARD.encode(PNG), "md5": "abc" }
+        });
+        let mut event = WecomAdapter.ingress_to_uem(&payload).unwrap();
+        event.markdown = format!(
+            "{}\n\n![chart]({origin}/chart.png) ![page]({origin}/page) ![logo]({origin}/logo.svg)",
+            event.markdown
+        );
+        let inline = WecomAdapter.inline_media(&payload);
+
+        relay_event_media(&relay, &db, &mut event, inline)
+            .await
+            .unwrap();
+
+        let media = event.meta["media"].as_array().unwrap();
+        assert_eq!(media.len(), 2);
+        let inline_url = media[0]["url"].as_str().unwrap();
+        let chart_url = media[1]["url"].as_str().unwrap();
+        assert!(inline_url.starts_with("https://hooks.example.com/webhooks/media/"));
+        assert_eq!(
+            event.markdown,
+            format!("![Image]({inline_url})\n\n![chart]({chart_url}) ![page]({origin}/page) ![logo]({origin}/logo.svg)")
+        );
+
+        let id = media[1]["id"].as_str().unwrap();
+        let (content_type, data) = db.get_media(id).await.unwrap().unwrap();
+        assert_eq!(content_type, "image/png");
+        assert_eq!(data, PNG);
+    }
+
+    #[tokio::test]
+    async fn caps_images_per_event_and_expires_them() {
+        let origin = start_image_server().await;
+        let db = Db::connect(":memory:").await.unwrap();
+        let relay = MediaRelay {
+            http: relay_client(true),
+            allow_private: true,
+            ..MediaRelay::new("https://hooks.example.com", None).unwrap()
+        };
+        let mut event = WecomAdapter
+            .ingress_to_uem(&json!({ "msgtype": "text", "text": { "content": "charts" } }))
+            .unwrap();
+        event.markdown = (0..MAX_MEDIA_PER_EVENT + 2)
+            .map(|index| format!("![{index}]({origin}/chart.png?{index})"))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        relay_event_media(&relay, &db, &mut event, Vec::new()).await.unwrap();
+
+        let media = event.meta["media"].as_array().unwrap();
+        assert_eq!(media.len(), MAX_MEDIA_PER_EVENT);
+        assert_eq!(media[0]["source"], format!("{origin}/chart.png?0"));
+        assert!(event.markdown.ends_with(&format!("![9]({origin}/chart.png?9)")));
+
+        let budget = AtomicUsize::new(PNG.len() - 1);
+        let err = fetch_image(&relay, &format!("{origin}/chart.png"), &budget).await.unwrap_err();
+        assert_eq!(err, "event media limit reached");
+
+        let id = media[0]["id"].as_str().unwrap();
+        assert_eq!(db.delete_media_older_than(Duration::from_secs(60)).await.unwrap(), 0);
+        assert!(db.get_media(id).await.unwrap().is_some());
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(
+            db.delete_media_older_than(Duration::ZERO).await.unwrap(),
+            MAX_MEDIA_PER_EVENT as u64
+        );
+        assert!(db.get_media(id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn refuses_private_addresses() {
+        let origin = start_image_server().await;
+        let relay = MediaRelay::new("https://hooks.example.com", None).unwrap();
+        let budget = AtomicUsize::new(MAX_EVENT_MEDIA_BYTES);
+        let err = fetch_image(&relay, &format!("{origin}/chart.png"), &budget).await.unwrap_err();
+        assert_eq!(err, "127.0.0.1 is not a public address");
+        let localhost = origin.replace("127.0.0.1", "localhost");
+        assert!(fetch_image(&relay, &format!("{localhost}/chart.png"), &budget).await.is_err());
+
+        for ip in ["10.0.0.1", "169.254.169.254", "100.64.0.1", "::1", "fe80::1", "fd00::1", "::ffff:192.168.0.1"] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["93.184.216.34", "2606:2800:220:1::1"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+}
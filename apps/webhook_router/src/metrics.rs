@@ -0,0 +1,147 @@
+//! Prometheus metrics served from `/metrics`.
+
+use std::sync::LazyLock;
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramTimer, HistogramVec, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+
+pub struct Metrics {
+    registry: Registry,
+    /// Ingress requests by endpoint, platform and response status.
+    pub ingress_requests: IntCounterVec,
+    /// Payloads the ingress adapter couldn't turn into a UEM event, by platform.
+    pub parse_failures: IntCounterVec,
+    /// Endpoint script runs by endpoint and outcome (`kept`, `dropped`, `error`).
+    pub script_runs: IntCounterVec,
+    /// Delivery records by target, target kind and status (one per part).
+    pub deliveries: IntCounterVec,
+    /// Time spent sending one delivery, by target kind.
+    pub delivery_duration: HistogramVec,
+    /// Dispatch tasks currently delivering an event to a target.
+    pub dispatch_in_flight: IntGauge,
+    /// DB query latency by query name.
+    pub db_query_duration: HistogramVec,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("webhook_router".to_string()), None)
+            .expect("valid metrics prefix");
+        let ingress_requests = IntCounterVec::new(
+            Opts::new("ingress_requests_total", "Ingress requests by endpoint, platform and status."),
+            &["endpoint", "platform", "status"],
+        )
+        .expect("valid metric");
+        let parse_failures = IntCounterVec::new(
+            Opts::new("adapter_parse_failures_total", "Ingress payloads the adapter failed to parse."),
+            &["platform"],
+        )
+        .expect("valid metric");
+        let script_runs = IntCounterVec::new(
+            Opts::new("script_runs_total", "Endpoint script runs by endpoint and outcome."),
+            &["endpoint", "outcome"],
+        )
+        .expect("valid metric");
+        let deliveries = IntCounterVec::new(
+            Opts::new("deliveries_total", "Deliveries by target, kind and status."),
+            &["target", "kind", "status"],
+        )
+        .expect("valid metric");
+        let delivery_duration = HistogramVec::new(
+            HistogramOpts::new("delivery_duration_seconds", "Time spent sending a delivery."),
+            &["kind"],
+        )
+        .expect("valid metric");
+        let dispatch_in_flight = IntGauge::new("dispatch_in_flight", "Dispatch tasks currently running.")
+            .expect("valid metric");
+        let db_query_duration = HistogramVec::new(
+            HistogramOpts::new("db_query_duration_seconds", "DB query latency.").buckets(vec![
+                0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+            ]),
+            &["query"],
+        )
+        .expect("valid metric");
+
+        for collector in [
+            Box::new(ingress_requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(parse_failures.clone()),
+            Box::new(script_runs.clone()),
+            Box::new(deliveries.clone()),
+            Box::new(delivery_duration.clone()),
+            Box::new(dispatch_in_flight.clone()),
+            Box::new(db_query_duration.clone()),
+        ] {
+            registry.register(collector).expect("metric registered once");
+        }
+
+        Self {
+            registry,
+            ingress_requests,
+            parse_failures,
+            script_runs,
+            deliveries,
+            delivery_duration,
+            dispatch_in_flight,
+            db_query_duration,
+        }
+    }
+
+    /// Encodes every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(err) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("failed to encode metrics: {}", err);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// Starts timing a DB query; the latency is recorded when the timer drops.
+pub fn db_timer(query: &str) -> HistogramTimer {
+    metrics().db_query_duration.with_label_values(&[query]).start_timer()
+}
+
+/// Counts a running dispatch task for as long as the guard lives.
+pub struct InFlight;
+
+impl InFlight {
+    pub fn start() -> Self {
+        metrics().dispatch_in_flight.inc();
+        Self
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        metrics().dispatch_in_flight.dec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_recorded_series() {
+        metrics()
+            .deliveries
+            .with_label_values(&["t-1", "slack", "sent"])
+            .inc();
+        {
+            let _in_flight = InFlight::start();
+            let _timer = db_timer("metrics_test");
+        }
+        let text = metrics().render();
+        assert!(text.contains(r#"webhook_router_deliveries_total{kind="slack",status="sent",target="t-1"} 1"#));
+        assert!(text.contains(r#"webhook_router_db_query_duration_seconds_count{query="metrics_test"} 1"#));
+        assert!(text.contains("# TYPE webhook_router_dispatch_in_flight gauge"));
+    }
+}
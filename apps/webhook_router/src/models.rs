@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use utoipa::ToSchema;
@@ -17,6 +19,39 @@ pub struct UemEvent {
     pub markdown: String,
     pub raw: Value,
     pub meta: Value,
+    pub mentions: Vec<Mention>,
+}
+
+/// A person referenced by a `<@platform:handle>` token in UEM markdown.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Mention {
+    pub platform: String,
+    pub handle: String,
+    pub name: Option<String>,
+    /// Per-platform handles resolved from the people directory.
+    pub handles: BTreeMap<String, String>,
+}
+
+impl Mention {
+    pub fn new(platform: &str, handle: &str, name: Option<String>) -> Self {
+        Self {
+            platform: platform.to_string(),
+            handle: handle.to_string(),
+            name,
+            handles: BTreeMap::new(),
+        }
+    }
+
+    pub fn handle_for(&self, platform: &str) -> Option<&str> {
+        self.handles
+            .get(platform)
+            .map(String::as_str)
+            .or_else(|| (self.platform == platform).then_some(self.handle.as_str()))
+    }
+
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.handle)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -31,6 +66,8 @@ pub struct CreateTargetRequest {
     pub kind: String,
     pub url: String,
     pub headers: Option<Value>,
+    /// Kind-specific settings, e.g. `token` and `channel` for `slack_api` targets.
+    pub config: Option<Value>,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -41,6 +78,7 @@ pub struct Target {
     pub kind: String,
     pub url: String,
     pub headers: Option<Value>,
+    pub config: Option<Value>,
     pub created_at: i64,
 }
 
@@ -49,6 +87,12 @@ pub struct CreateEndpointRequest {
     pub name: String,
     pub banner: Option<String>,
     pub footer: Option<String>,
+    /// Per-platform callback settings, e.g. `{"lark": {"encrypt_key": "..."}}`.
+    pub ingress_config: Option<Value>,
+    /// Ingress platforms accepted by the endpoint; any when unset.
+    pub allowed_platforms: Option<Vec<String>>,
+    /// Rhai script run on each ingress event; it may change or drop the event.
+    pub script: Option<String>,
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
@@ -56,6 +100,9 @@ pub struct UpdateEndpointRequest {
     pub name: Option<String>,
     pub banner: Option<String>,
     pub footer: Option<String>,
+    pub ingress_config: Option<Value>,
+    pub allowed_platforms: Option<Vec<String>>,
+    pub script: Option<String>,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -64,9 +111,27 @@ pub struct Endpoint {
     pub name: String,
     pub banner: Option<String>,
     pub footer: Option<String>,
+    pub ingress_config: Option<Value>,
+    pub allowed_platforms: Option<Vec<String>>,
+    pub script: Option<String>,
     pub created_at: i64,
 }
 
+impl Endpoint {
+    /// Callback settings for one platform (canonical name) from `ingress_config`.
+    pub fn ingress_settings(&self, platform: &str) -> Option<&Value> {
+        self.ingress_config.as_ref()?.get(platform)
+    }
+
+    /// Whether the endpoint accepts requests from a platform (canonical
+    /// ingress name).
+    pub fn allows_platform(&self, platform: &str) -> bool {
+        self.allowed_platforms
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|name| name == platform))
+    }
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct DeliveryRecord {
     pub target_id: String,
@@ -75,9 +140,39 @@ pub struct DeliveryRecord {
     pub status: String,
     pub response_code: Option<u16>,
     pub error: Option<String>,
+    /// 1-based part number when the event was split to fit the target.
+    pub part: Option<u32>,
+    pub parts: Option<u32>,
+    /// `X-Request-Id` of the ingress (or test send) request that caused it.
+    pub request_id: Option<String>,
     pub created_at: i64,
 }
 
+/// Which events `GET /api/events` returns; unset fields don't filter.
+#[derive(Debug, Default)]
+pub struct EventFilter {
+    pub endpoint_id: Option<String>,
+    /// Words or `"quoted phrases"` that must all appear in the title or markdown.
+    pub q: Option<String>,
+    pub platform: Option<String>,
+    /// Events with a delivery to this target.
+    pub target_id: Option<String>,
+    pub delivery: Option<DeliveryFilter>,
+    /// Unix seconds, inclusive.
+    pub since: Option<i64>,
+    /// Unix seconds, exclusive.
+    pub until: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryFilter {
+    /// At least one delivery failed.
+    Failed,
+    /// Every delivery was sent.
+    Sent,
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct EventRecord {
     pub id: String,
@@ -96,9 +191,149 @@ pub struct DeliveryOutcome {
     pub status: String,
     pub response_code: Option<u16>,
     pub error: Option<String>,
+    pub part: Option<u32>,
+    pub parts: Option<u32>,
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct TestSendRequest {
     pub markdown: String,
 }
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ScriptTestRequest {
+    /// Rhai source to try.
+    pub script: String,
+    /// Ingress platform that would receive `payload`; detected when unset.
+    pub platform: Option<String>,
+    /// Sample ingress payload.
+    pub payload: Value,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ScriptTestResult {
+    pub platform: String,
+    /// Whether the script dropped the event.
+    pub dropped: bool,
+    /// The event after the script ran (`id`, `source`, `timestamp`, `title`,
+    /// `markdown`, `raw`, `meta`); unset when dropped or failed.
+    pub event: Option<Value>,
+    /// Lines the script printed.
+    pub output: Vec<String>,
+    /// Compile or runtime error, including exceeded limits.
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// A declarative ingress adapter: JSONPath expressions pick fields out of a
+/// vendor's JSON and a template renders the markdown.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Mapping {
+    pub id: String,
+    /// Platform segment of the ingress URL: `/ingress/:endpoint_id/:name`.
+    pub name: String,
+    pub id_path: Option<String>,
+    pub title_path: Option<String>,
+    pub timestamp_path: Option<String>,
+    /// Meta field name → JSONPath.
+    pub meta: BTreeMap<String, String>,
+    /// Jinja-style markdown template with `payload`, `id`, `title` and `meta` in scope.
+    pub template: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateMappingRequest {
+    pub name: String,
+    /// JSONPath to the event id, e.g. `$.incident.id`; a UUID when unset or unmatched.
+    pub id_path: Option<String>,
+    pub title_path: Option<String>,
+    /// JSONPath to a Unix timestamp in seconds or milliseconds; now when unset.
+    pub timestamp_path: Option<String>,
+    pub meta: Option<BTreeMap<String, String>>,
+    pub template: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateMappingRequest {
+    pub name: Option<String>,
+    pub id_path: Option<String>,
+    pub title_path: Option<String>,
+    pub timestamp_path: Option<String>,
+    pub meta: Option<BTreeMap<String, String>>,
+    pub template: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreatePersonRequest {
+    pub name: String,
+    pub handles: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdatePersonRequest {
+    pub name: Option<String>,
+    pub handles: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Person {
+    pub id: String,
+    pub name: String,
+    pub handles: BTreeMap<String, String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportItem {
+    /// `endpoint` or `target`.
+    pub kind: String,
+    pub endpoint_id: String,
+    pub name: String,
+    /// `created`, `updated`, `skipped` (unchanged) or `deleted`.
+    pub action: String,
+    /// Fields that differ, for `updated` items.
+    pub fields: Vec<String>,
+}
+
+impl ImportItem {
+    pub fn new(kind: &str, endpoint_id: &str, name: &str, action: &str, fields: Vec<&str>) -> Self {
+        Self {
+            kind: kind.to_string(),
+            endpoint_id: endpoint_id.to_string(),
+            name: name.to_string(),
+            action: action.to_string(),
+            fields: fields.into_iter().map(String::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportResult {
+    /// True when nothing was written.
+    pub dry_run: bool,
+    pub items: Vec<ImportItem>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Readiness {
+    /// `ready` when every check passed, otherwise `not_ready`.
+    pub status: String,
+    /// Check name (`database`, `writable`, `migrations`) to
+    /// `ok` or the failure message.
+    pub checks: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SystemStatus {
+    pub version: String,
+    pub uptime_seconds: u64,
+    /// Size of the SQLite database in bytes (page count × page size).
+    pub db_size_bytes: i64,
+    /// Row count per table.
+    pub tables: BTreeMap<String, i64>,
+    /// Compile-time features, e.g. whether the `console` UI is embedded.
+    pub features: BTreeMap<String, bool>,
+    /// Dispatch tasks currently delivering to targets.
+    pub dispatch_in_flight: i64,
+}
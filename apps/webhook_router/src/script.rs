@@ -0,0 +1,268 @@
+//! Per-endpoint Rhai scripts that adjust or drop events after `ingress_to_uem`.
+//!
+//! A script sees `event` (`id`, `source`, `timestamp`, `title`, `markdown`,
+//! `raw`, `meta`), changes it in place and evaluates to `false` to drop it.
+//! Scripts are sandboxed: no `eval`, no module imports and no I/O besides
+//! `print`/`debug`, which are captured. Operations and wall time are limited,
+//! as are the sizes of strings, arrays and maps to bound memory.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, EvalAltResult, Scope};
+use serde_json::{json, Value};
+
+use crate::models::UemEvent;
+
+const MAX_STRING_SIZE: usize = 1024 * 1024;
+const MAX_COLLECTION_SIZE: usize = 10_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_OUTPUT_LINES: usize = 100;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptLimits {
+    /// Wall-clock time a script may run.
+    pub timeout: Duration,
+    /// Rhai operations (roughly, evaluated expressions) a script may run.
+    pub max_operations: u64,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_millis(100),
+            max_operations: 1_000_000,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ScriptError {
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptOutcome {
+    Keep,
+    Drop,
+}
+
+/// The result of a script run, with what it printed.
+#[derive(Debug)]
+pub struct ScriptRun {
+    pub outcome: ScriptOutcome,
+    pub output: Vec<String>,
+    pub duration: Duration,
+}
+
+fn engine(limits: &ScriptLimits, output: Arc<Mutex<Vec<String>>>) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_module_resolver(DummyModuleResolver::new())
+        .set_max_operations(limits.max_operations)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_COLLECTION_SIZE)
+        .set_max_map_size(MAX_COLLECTION_SIZE)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_expr_depths(64, 32);
+    engine.disable_symbol("eval");
+
+    let started = Instant::now();
+    let timeout = limits.timeout;
+    engine.on_progress(move |_| (started.elapsed() > timeout).then(|| Dynamic::from("timeout")));
+
+    let print_output = output.clone();
+    engine.on_print(move |text| capture(&print_output, text.to_string()));
+    engine.on_debug(move |text, _, position| capture(&output, format!("[debug {}] {}", position, text)));
+    engine
+}
+
+fn capture(output: &Mutex<Vec<String>>, line: String) {
+    let mut output = output.lock().expect("script output lock");
+    if output.len() < MAX_OUTPUT_LINES {
+        output.push(line);
+    }
+}
+
+/// Checks that a script parses, e.g. before it is saved.
+pub fn compile(source: &str) -> Result<(), ScriptError> {
+    engine(&ScriptLimits::default(), Arc::default())
+        .compile(source)
+        .map(|_| ())
+        .map_err(|err| ScriptError {
+            message: format!("invalid script: {}", err),
+        })
+}
+
+/// Runs `source` against `event`, which is updated unless the script drops it.
+pub fn run(source: &str, event: &mut UemEvent, limits: &ScriptLimits) -> Result<ScriptRun, ScriptError> {
+    let started = Instant::now();
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let engine = engine(limits, output.clone());
+    let ast = engine.compile(source).map_err(|err| ScriptError {
+        message: format!("invalid script: {}", err),
+    })?;
+
+    let mut scope = Scope::new();
+    let value = rhai::serde::to_dynamic(event_value(event)).map_err(|err| runtime_error(*err, limits))?;
+    scope.push_dynamic("event", value);
+    let result = engine
+        .eval_ast_with_scope::<Dynamic>(&mut scope, &ast)
+        .map_err(|err| runtime_error(*err, limits))?;
+
+    let outcome = if result.as_bool() == Ok(false) {
+        ScriptOutcome::Drop
+    } else {
+        let value = scope.get_value::<Dynamic>("event").unwrap_or_default();
+        let value: Value = rhai::serde::from_dynamic(&value).map_err(|err| runtime_error(*err, limits))?;
+        apply_event(event, value)?;
+        ScriptOutcome::Keep
+    };
+
+    let output = std::mem::take(&mut *output.lock().expect("script output lock"));
+    Ok(ScriptRun {
+        outcome,
+        output,
+        duration: started.elapsed(),
+    })
+}
+
+/// The fields of `event` a script can see and change.
+pub fn event_value(event: &UemEvent) -> Value {
+    json!({
+        "id": event.id,
+        "source": event.source,
+        "timestamp": event.timestamp,
+        "title": event.title,
+        "markdown": event.markdown,
+        "raw": event.raw,
+        "meta": event.meta,
+    })
+}
+
+fn apply_event(event: &mut UemEvent, value: Value) -> Result<(), ScriptError> {
+    let invalid = |field: &str, expected: &str| ScriptError {
+        message: format!("script set event.{} to a non-{} value", field, expected),
+    };
+    let Value::Object(mut fields) = value else {
+        return Err(ScriptError {
+            message: "script replaced event with a non-map value".to_string(),
+        });
+    };
+
+    if let Some(id) = fields.remove("id") {
+        event.id = id.as_str().ok_or_else(|| invalid("id", "string"))?.to_string();
+    }
+    if let Some(source) = fields.remove("source") {
+        event.source = source.as_str().ok_or_else(|| invalid("source", "string"))?.to_string();
+    }
+    if let Some(timestamp) = fields.remove("timestamp") {
+        event.timestamp = timestamp.as_i64().ok_or_else(|| invalid("timestamp", "integer"))?;
+    }
+    match fields.remove("title") {
+        None | Some(Value::Null) => event.title = None,
+        Some(Value::String(title)) => event.title = Some(title),
+        Some(_) => return Err(invalid("title", "string")),
+    }
+    if let Some(markdown) = fields.remove("markdown") {
+        event.markdown = markdown.as_str().ok_or_else(|| invalid("markdown", "string"))?.to_string();
+    }
+    if let Some(raw) = fields.remove("raw") {
+        event.raw = raw;
+    }
+    if let Some(meta) = fields.remove("meta") {
+        event.meta = meta;
+    }
+    Ok(())
+}
+
+fn runtime_error(err: EvalAltResult, limits: &ScriptLimits) -> ScriptError {
+    let message = match err {
+        EvalAltResult::ErrorTerminated(..) => {
+            format!("script exceeded the time limit of {} ms", limits.timeout.as_millis())
+        }
+        EvalAltResult::ErrorTooManyOperations(..) => {
+            format!("script exceeded the limit of {} operations", limits.max_operations)
+        }
+        other => format!("script failed: {}", other),
+    };
+    ScriptError { message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event() -> UemEvent {
+        UemEvent {
+            id: "evt-1".to_string(),
+            source: "custom".to_string(),
+            timestamp: 1_700_000_000,
+            title: None,
+            markdown: "disk full on db-1".to_string(),
+            raw: json!({"severity": "critical", "host": "db-1", "tags": ["disk"]}),
+            meta: json!({}),
+            mentions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn scripts_modify_and_drop_events() {
+        let script = r#"
+            if event.raw.severity == "info" { return false; }
+            event.title = `[${event.raw.severity.to_upper()}] ${event.raw.host}`;
+            event.markdown += "\n\nTags: " + event.raw.tags[0];
+            event.meta.routed = true;
+            print("titled " + event.title);
+        "#;
+        let mut kept = event();
+        let run = run(script, &mut kept, &ScriptLimits::default()).expect("run");
+        assert_eq!(run.outcome, ScriptOutcome::Keep);
+        assert_eq!(run.output, ["titled [CRITICAL] db-1"]);
+        assert_eq!(kept.title.as_deref(), Some("[CRITICAL] db-1"));
+        assert_eq!(kept.markdown, "disk full on db-1\n\nTags: disk");
+        assert_eq!(kept.meta, json!({"routed": true}));
+
+        let mut noise = event();
+        noise.raw["severity"] = json!("info");
+        let run = super::run(script, &mut noise, &ScriptLimits::default()).expect("run");
+        assert_eq!(run.outcome, ScriptOutcome::Drop);
+        assert_eq!(noise.title, None);
+    }
+
+    #[test]
+    fn scripts_are_limited_and_sandboxed() {
+        let limits = ScriptLimits {
+            timeout: Duration::from_millis(20),
+            max_operations: u64::MAX,
+        };
+        let err = run("loop {}", &mut event(), &limits).unwrap_err();
+        assert_eq!(err.message, "script exceeded the time limit of 20 ms");
+
+        let limits = ScriptLimits {
+            max_operations: 1_000,
+            ..ScriptLimits::default()
+        };
+        let err = run("let x = 0; loop { x += 1; }", &mut event(), &limits).unwrap_err();
+        assert_eq!(err.message, "script exceeded the limit of 1000 operations");
+
+        let err = run(r#"let s = "x"; loop { s += s; }"#, &mut event(), &ScriptLimits::default()).unwrap_err();
+        assert!(err.message.starts_with("script failed:"), "{}", err.message);
+        assert!(run(r#"import "secrets" as s;"#, &mut event(), &ScriptLimits::default()).is_err());
+        assert!(compile(r#"eval("1")"#).is_err());
+
+        let err = run("event.markdown = 1;", &mut event(), &ScriptLimits::default()).unwrap_err();
+        assert_eq!(err.message, "script set event.markdown to a non-string value");
+        assert!(compile("event.title = ").unwrap_err().message.starts_with("invalid script:"));
+    }
+}
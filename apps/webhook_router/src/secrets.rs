@@ -0,0 +1,278 @@
+//! Envelope encryption for stored secrets and masking for API responses.
+//!
+//! Each value gets its own AES-256-GCM data key, which is stored wrapped by
+//! the master key next to the ciphertext:
+//! `enc:v1:<master key id>:<wrapped data key>:<ciphertext>`. Rotating the
+//! master key only re-wraps data keys.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::models::{Endpoint, Target};
+
+const PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+/// Replaces secret values in API responses.
+pub const MASK: &str = "****";
+
+struct MasterKey {
+    id: String,
+    cipher: Aes256Gcm,
+}
+
+impl MasterKey {
+    fn parse(encoded: &str) -> Result<Self, String> {
+        let bytes = STANDARD
+            .decode(encoded.trim())
+            .map_err(|err| format!("secret key is not valid base64: {}", err))?;
+        if bytes.len() != 32 {
+            return Err(format!("secret key must be 32 bytes, got {}", bytes.len()));
+        }
+        let digest = Sha256::digest(&bytes);
+        Ok(Self {
+            id: digest[..4].iter().map(|byte| format!("{:02x}", byte)).collect(),
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&bytes)),
+        })
+    }
+}
+
+/// The current master key plus previous ones still accepted for decryption.
+pub struct SecretKeys {
+    current: MasterKey,
+    previous: Vec<MasterKey>,
+}
+
+impl SecretKeys {
+    /// Parses base64-encoded 32-byte keys.
+    pub fn new(current: &str, previous: &[String]) -> Result<Self, String> {
+        Ok(Self {
+            current: MasterKey::parse(current)?,
+            previous: previous
+                .iter()
+                .map(|key| MasterKey::parse(key))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn key(&self, id: &str) -> Option<&MasterKey> {
+        std::iter::once(&self.current)
+            .chain(&self.previous)
+            .find(|key| key.id == id)
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let data_key = Aes256Gcm::generate_key(OsRng);
+        let data = seal(&Aes256Gcm::new(&data_key), plaintext.as_bytes());
+        format!(
+            "{}{}:{}:{}",
+            PREFIX,
+            self.current.id,
+            seal(&self.current.cipher, &data_key),
+            data
+        )
+    }
+
+    /// Decrypts an `enc:v1:` value; anything else is returned as is, so
+    /// values written before encryption was enabled keep working.
+    pub fn decrypt(&self, value: &str) -> Result<String, String> {
+        let Some((key_id, wrapped, data)) = split(value) else {
+            return Ok(value.to_string());
+        };
+        let master = self
+            .key(key_id)
+            .ok_or_else(|| format!("no secret key with id {}", key_id))?;
+        let data_key = open(&master.cipher, wrapped)?;
+        let data_key = Aes256Gcm::new_from_slice(&data_key).map_err(|_| "invalid data key".to_string())?;
+        String::from_utf8(open(&data_key, data)?).map_err(|_| "secret is not UTF-8".to_string())
+    }
+
+    /// Returns the value re-encrypted for the current key: plaintext is
+    /// encrypted and data keys wrapped by a previous key are re-wrapped.
+    /// `None` when it is already current.
+    pub fn reseal(&self, value: &str) -> Result<Option<String>, String> {
+        let Some((key_id, wrapped, data)) = split(value) else {
+            return Ok(Some(self.encrypt(value)));
+        };
+        if key_id == self.current.id {
+            return Ok(None);
+        }
+        let master = self
+            .key(key_id)
+            .ok_or_else(|| format!("no secret key with id {}", key_id))?;
+        let data_key = open(&master.cipher, wrapped)?;
+        Ok(Some(format!(
+            "{}{}:{}:{}",
+            PREFIX,
+            self.current.id,
+            seal(&self.current.cipher, &data_key),
+            data
+        )))
+    }
+}
+
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+fn split(value: &str) -> Option<(&str, &str, &str)> {
+    let mut parts = value.strip_prefix(PREFIX)?.splitn(3, ':');
+    Some((parts.next()?, parts.next()?, parts.next()?))
+}
+
+fn seal(cipher: &Aes256Gcm, plaintext: &[u8]) -> String {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .expect("AES-GCM encryption of an in-memory buffer");
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    STANDARD.encode(sealed)
+}
+
+fn open(cipher: &Aes256Gcm, encoded: &str) -> Result<Vec<u8>, String> {
+    let sealed = STANDARD
+        .decode(encoded)
+        .map_err(|_| "malformed encrypted secret".to_string())?;
+    if sealed.len() < NONCE_LEN {
+        return Err("malformed encrypted secret".to_string());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "failed to decrypt secret (wrong key?)".to_string())
+}
+
+/// Hides everything after the host of a target URL, where webhook tokens
+/// live: `https://hooks.slack.com/****`.
+pub fn mask_url(url: &str) -> String {
+    let (scheme, rest) = match url.split_once("://") {
+        Some((scheme, rest)) => (Some(scheme), rest),
+        None => (None, url),
+    };
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    if host.len() == rest.len() {
+        return url.to_string();
+    }
+    match scheme {
+        Some(scheme) => format!("{}://{}/{}", scheme, host, MASK),
+        None => format!("{}/{}", host, MASK),
+    }
+}
+
+/// Masks every header value.
+pub fn mask_headers(headers: &Value) -> Value {
+    match headers {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(name, _)| (name.clone(), Value::String(MASK.to_string())))
+                .collect(),
+        ),
+        _ => Value::String(MASK.to_string()),
+    }
+}
+
+/// Masks config values whose key looks like a credential (`token`,
+/// `secret`, `password`, `key`); settings like `channel` stay visible.
+pub fn mask_config(config: &Value) -> Value {
+    match config {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(name, value)| {
+                    let lower = name.to_ascii_lowercase();
+                    let secret = ["token", "secret", "password", "key"]
+                        .iter()
+                        .any(|word| lower.contains(word));
+                    let value = if secret { Value::String(MASK.to_string()) } else { mask_config(value) };
+                    (name.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(mask_config).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Whether `value` holds a masked string anywhere, i.e. came from an API
+/// response rather than the real secret.
+pub fn contains_mask(value: &Value) -> bool {
+    match value {
+        Value::String(text) => text == MASK,
+        Value::Array(items) => items.iter().any(contains_mask),
+        Value::Object(map) => map.values().any(contains_mask),
+        _ => false,
+    }
+}
+
+pub fn mask_target(target: Target) -> Target {
+    Target {
+        url: mask_url(&target.url),
+        headers: target.headers.as_ref().map(mask_headers),
+        config: target.config.as_ref().map(mask_config),
+        ..target
+    }
+}
+
+pub fn mask_endpoint(endpoint: Endpoint) -> Endpoint {
+    Endpoint {
+        ingress_config: endpoint.ingress_config.as_ref().map(mask_config),
+        ..endpoint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn key(byte: u8) -> String {
+        STANDARD.encode([byte; 32])
+    }
+
+    #[test]
+    fn encrypts_and_rotates_keys() {
+        let old = SecretKeys::new(&key(1), &[]).unwrap();
+        let url = "https://hooks.slack.com/services/T0/B0/secret";
+        let sealed = old.encrypt(url);
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.contains("secret"));
+        assert_eq!(old.decrypt(&sealed).unwrap(), url);
+        assert_eq!(old.decrypt("plain").unwrap(), "plain");
+        assert_eq!(old.reseal(&sealed).unwrap(), None);
+
+        let rotated = SecretKeys::new(&key(2), &[key(1)]).unwrap();
+        let resealed = rotated.reseal(&sealed).unwrap().expect("re-wrapped");
+        // Only the data key is re-wrapped; the ciphertext is unchanged.
+        assert_eq!(resealed.rsplit(':').next(), sealed.rsplit(':').next());
+        assert_eq!(rotated.decrypt(&resealed).unwrap(), url);
+
+        let new_only = SecretKeys::new(&key(2), &[]).unwrap();
+        assert_eq!(new_only.decrypt(&resealed).unwrap(), url);
+        assert!(new_only.decrypt(&sealed).unwrap_err().starts_with("no secret key"));
+        assert!(SecretKeys::new("c2hvcnQ=", &[]).is_err());
+    }
+
+    #[test]
+    fn masks_secrets() {
+        assert_eq!(
+            mask_url("https://hooks.slack.com/services/T0/B0/secret"),
+            "https://hooks.slack.com/****"
+        );
+        assert_eq!(mask_url("https://user:pw@example.com?token=x"), "https://example.com/****");
+        assert_eq!(mask_url("https://slack.com"), "https://slack.com");
+        assert_eq!(
+            mask_headers(&json!({"Authorization": "Bearer x"})),
+            json!({"Authorization": MASK})
+        );
+        assert_eq!(
+            mask_config(&json!({"token": "xoxb-1", "channel": "C1", "signing_key": "k"})),
+            json!({"token": MASK, "channel": "C1", "signing_key": MASK})
+        );
+    }
+}
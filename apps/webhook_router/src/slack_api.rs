@@ -0,0 +1,423 @@
+//! Delivery through the Slack Web API (`chat.postMessage` / `chat.update`).
+//!
+//! Unlike incoming webhooks, the Web API returns the `ts` of the posted
+//! message. It is remembered per correlation key so follow-ups on the same
+//! incident reply in the original thread or update the original message.
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::db::Db;
+use crate::models::{Target, UemEvent};
+
+pub const DEFAULT_API_BASE: &str = "https://slack.com/api";
+
+type ThreadLock = Arc<tokio::sync::Mutex<()>>;
+
+/// One lock per (target id, correlation key). Looking up the thread, posting
+/// and saving the new `ts` happen under it, so concurrent events for the same
+/// incident can't each start a thread.
+static THREAD_LOCKS: LazyLock<Mutex<HashMap<(String, String), ThreadLock>>> = LazyLock::new(Default::default);
+
+/// What to do with an event whose correlation key already has a message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThreadMode {
+    /// Reply in the thread of the original message.
+    Reply,
+    /// Replace the original message with the new content.
+    Update,
+}
+
+#[derive(Debug)]
+pub struct SlackApiConfig {
+    pub token: String,
+    pub channel: String,
+    pub thread_mode: ThreadMode,
+}
+
+impl SlackApiConfig {
+    /// Reads the `slack_api` settings from a target's `config` object.
+    pub fn from_value(config: Option<&Value>) -> Result<Self, String> {
+        let field = |name: &str| {
+            config
+                .and_then(|config| config.get(name))
+                .and_then(|value| value.as_str())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        let token = field("token").ok_or("slack_api target requires config.token")?;
+        let channel = field("channel").ok_or("slack_api target requires config.channel")?;
+        let thread_mode = match field("thread_mode") {
+            None | Some("reply") => ThreadMode::Reply,
+            Some("update") => ThreadMode::Update,
+            Some(other) => {
+                return Err(format!(
+                    "unsupported thread_mode '{}', expected 'reply' or 'update'",
+                    other
+                ))
+            }
+        };
+        Ok(Self {
+            token: token.to_string(),
+            channel: channel.to_string(),
+            thread_mode,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct SlackApiError {
+    pub response_code: Option<u16>,
+    pub message: String,
+}
+
+impl SlackApiError {
+    fn new(response_code: Option<u16>, message: impl Into<String>) -> Self {
+        Self {
+            response_code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Picks the key that ties follow-up events to an earlier message: a Slack
+/// `meta.thread_ts`, or an alert fingerprint / Alertmanager group key.
+pub fn correlation_key(event: &UemEvent) -> Option<String> {
+    [
+        event.meta.get("thread_ts"),
+        event.meta.get("fingerprint"),
+        event.raw.get("fingerprint"),
+        event.raw.get("groupKey"),
+        event.raw.pointer("/alerts/0/fingerprint"),
+    ]
+    .into_iter()
+    .flatten()
+    .find_map(|value| match value {
+        Value::String(key) if !key.is_empty() => Some(key.clone()),
+        Value::Number(key) => Some(key.to_string()),
+        _ => None,
+    })
+}
+
+/// Sends `body` (the Slack egress payload) for `event` and returns the HTTP
+/// status of the Slack API response. `follow_up` marks the later parts of a
+/// split event: they reply in the thread instead of updating the message the
+/// first part just wrote.
+pub async fn send(
+    http: &reqwest::Client,
+    db: &Db,
+    target: &Target,
+    event: &UemEvent,
+    body: Value,
+    follow_up: bool,
+) -> Result<u16, SlackApiError> {
+    let config = SlackApiConfig::from_value(target.config.as_ref())
+        .map_err(|message| SlackApiError::new(None, message))?;
+    let Some(key) = correlation_key(event) else {
+        return post(http, db, target, &config, None, body, follow_up).await;
+    };
+
+    let lock_key = (target.id.clone(), key);
+    let lock = THREAD_LOCKS
+        .lock()
+        .unwrap()
+        .entry(lock_key.clone())
+        .or_default()
+        .clone();
+    let result = {
+        let _guard = lock.lock().await;
+        post(http, db, target, &config, Some(&lock_key.1), body, follow_up).await
+    };
+    drop(lock);
+    // Forget the lock once no other send is waiting on it.
+    let mut locks = THREAD_LOCKS.lock().unwrap();
+    if locks.get(&lock_key).is_some_and(|lock| Arc::strong_count(lock) == 1) {
+        locks.remove(&lock_key);
+    }
+    result
+}
+
+/// Posts or updates the message for correlation `key`, remembering the `ts`
+/// of a newly started thread.
+async fn post(
+    http: &reqwest::Client,
+    db: &Db,
+    target: &Target,
+    config: &SlackApiConfig,
+    key: Option<&str>,
+    body: Value,
+    follow_up: bool,
+) -> Result<u16, SlackApiError> {
+    let existing = match key {
+        Some(key) => db
+            .get_thread(&target.id, key)
+            .await
+            .map_err(|err| SlackApiError::new(None, err.to_string()))?,
+        None => None,
+    };
+
+    let mut message = match body {
+        Value::Object(message) => message,
+        _ => return Err(SlackApiError::new(None, "slack payload must be an object")),
+    };
+
+    let (method, channel) = match (&existing, config.thread_mode) {
+        (Some((channel, ts)), ThreadMode::Update) if !follow_up => {
+            message.insert("ts".to_string(), json!(ts));
+            ("chat.update", channel.as_str())
+        }
+        (Some((channel, ts)), _) => {
+            message.insert("thread_ts".to_string(), json!(ts));
+            ("chat.postMessage", channel.as_str())
+        }
+        (None, _) => ("chat.postMessage", config.channel.as_str()),
+    };
+    message.insert("channel".to_string(), json!(channel));
+
+    let (code, response) = call(http, target, &config.token, method, &Value::Object(message)).await?;
+
+    if let (Some(key), None) = (key, &existing) {
+        let channel = response
+            .get("channel")
+            .and_then(|value| value.as_str())
+            .unwrap_or(&config.channel);
+        if let Some(ts) = response.get("ts").and_then(|value| value.as_str()) {
+            // Slack already has the message, so the delivery still counts as
+            // sent; only threading of later follow-ups is lost.
+            if let Err(err) = db.save_thread(&target.id, key, channel, ts).await {
+                warn!("failed to remember slack thread {}: {}", key, err);
+            }
+        }
+    }
+
+    Ok(code)
+}
+
+async fn call(
+    http: &reqwest::Client,
+    target: &Target,
+    token: &str,
+    method: &str,
+    body: &Value,
+) -> Result<(u16, Value), SlackApiError> {
+    let base = target.url.trim().trim_end_matches('/');
+    let base = if base.is_empty() { DEFAULT_API_BASE } else { base };
+
+    let mut request = http.post(format!("{}/{}", base, method));
+    if let Some(headers) = target.headers.as_ref().and_then(|value| value.as_object()) {
+        for (key, value) in headers {
+            if let Some(value) = value.as_str() {
+                request = request.header(key, value);
+            }
+        }
+    }
+
+    let response = request
+        .bearer_auth(token)
+        .json(body)
+        .send()
+        .await
+        .map_err(|err| SlackApiError::new(None, err.to_string()))?;
+
+    let status = response.status();
+    let code = status.as_u16();
+    if !status.is_success() {
+        return Err(SlackApiError::new(
+            Some(code),
+            format!("non-success status: {}", status),
+        ));
+    }
+
+    let response: Value = response
+        .json()
+        .await
+        .map_err(|err| SlackApiError::new(Some(code), format!("invalid slack response: {err}")))?;
+    if response.get("ok").and_then(|value| value.as_bool()) != Some(true) {
+        let error = response
+            .get("error")
+            .and_then(|value| value.as_str())
+            .unwrap_or("unknown_error");
+        return Err(SlackApiError::new(
+            Some(code),
+            format!("slack {} failed: {}", method, error),
+        ));
+    }
+    Ok((code, response))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::extract::{Path, State};
+    use axum::http::HeaderMap;
+    use axum::routing::post;
+    use axum::{Json, Router};
+
+    use super::*;
+    use crate::models::{CreateEndpointRequest, CreateTargetRequest};
+
+    /// Method, `Authorization` header and body of each call to the mock.
+    pub(crate) type Calls = Arc<Mutex<Vec<(String, Option<String>, Value)>>>;
+
+    async fn mock_slack(
+        Path(method): Path<String>,
+        State(calls): State<Calls>,
+        headers: HeaderMap,
+        Json(body): Json<Value>,
+    ) -> Json<Value> {
+        let auth = headers
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        // Widens the window in which concurrent sends could race.
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        let mut calls = calls.lock().unwrap();
+        calls.push((method, auth, body.clone()));
+        Json(json!({
+            "ok": true,
+            "channel": "C123",
+            "ts": format!("1700000000.{:06}", calls.len()),
+        }))
+    }
+
+    /// Starts a mock Slack Web API and returns its base URL.
+    pub(crate) async fn start_mock() -> (String, Calls) {
+        let calls: Calls = Arc::default();
+        let app = Router::new()
+            .route("/api/:method", post(mock_slack))
+            .with_state(calls.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}/api", addr), calls)
+    }
+
+    fn alert(fingerprint: &str, text: &str) -> UemEvent {
+        UemEvent {
+            id: text.to_string(),
+            source: "custom".to_string(),
+            timestamp: 0,
+            title: None,
+            markdown: text.to_string(),
+            raw: json!({ "alerts": [{ "fingerprint": fingerprint }] }),
+            meta: json!({}),
+            mentions: Vec::new(),
+        }
+    }
+
+    pub(crate) async fn slack_target(db: &Db, url: &str, thread_mode: &str) -> Target {
+        let endpoint = db
+            .create_endpoint(CreateEndpointRequest {
+                name: "alerts".to_string(),
+                banner: None,
+                footer: None,
+                ingress_config: None,
+                allowed_platforms: None,
+                script: None,
+            })
+            .await
+            .unwrap();
+        db.create_target(&endpoint.id, CreateTargetRequest {
+            name: "Slack".to_string(),
+            kind: "slack_api".to_string(),
+            url: url.to_string(),
+            headers: None,
+            config: Some(json!({
+                "token": "xoxb-test",
+                "channel": "#alerts",
+                "thread_mode": thread_mode,
+            })),
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn follow_ups_reply_in_thread() {
+        let (url, calls) = start_mock().await;
+        let db = Db::connect(":memory:").await.unwrap();
+        let target = slack_target(&db, &url, "reply").await;
+        let http = reqwest::Client::new();
+
+        for event in [alert("fp-1", "firing"), alert("fp-1", "resolved"), alert("fp-2", "other")] {
+            let body = json!({ "text": event.markdown });
+            send(&http, &db, &target, &event, body, false).await.unwrap();
+        }
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 3);
+        assert!(calls.iter().all(|(method, auth, _)| {
+            method == "chat.postMessage" && auth.as_deref() == Some("Bearer xoxb-test")
+        }));
+        assert_eq!(calls[0].2, json!({ "text": "firing", "channel": "#alerts" }));
+        assert_eq!(
+            calls[1].2,
+            json!({ "text": "resolved", "channel": "C123", "thread_ts": "1700000000.000001" })
+        );
+        assert_eq!(calls[2].2, json!({ "text": "other", "channel": "#alerts" }));
+    }
+
+    #[tokio::test]
+    async fn follow_ups_update_original_message() {
+        let (url, calls) = start_mock().await;
+        let db = Db::connect(":memory:").await.unwrap();
+        let target = slack_target(&db, &url, "update").await;
+        let http = reqwest::Client::new();
+
+        for event in [alert("fp-1", "firing"), alert("fp-1", "resolved")] {
+            let body = json!({ "text": event.markdown });
+            send(&http, &db, &target, &event, body, false).await.unwrap();
+        }
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls[1].0, "chat.update");
+        assert_eq!(
+            calls[1].2,
+            json!({ "text": "resolved", "channel": "C123", "ts": "1700000000.000001" })
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_events_share_one_thread() {
+        let (url, calls) = start_mock().await;
+        let db = Db::connect(":memory:").await.unwrap();
+        let target = slack_target(&db, &url, "reply").await;
+        let http = reqwest::Client::new();
+
+        let (first, second) = (alert("fp-1", "firing"), alert("fp-1", "still firing"));
+        let (a, b) = tokio::join!(
+            send(&http, &db, &target, &first, json!({ "text": "firing" }), false),
+            send(&http, &db, &target, &second, json!({ "text": "still firing" }), false),
+        );
+        a.unwrap();
+        b.unwrap();
+
+        {
+            let calls = calls.lock().unwrap();
+            assert_eq!(calls[0].2.get("thread_ts"), None);
+            assert_eq!(calls[1].2["thread_ts"], "1700000000.000001");
+        }
+        assert_eq!(
+            db.get_thread(&target.id, "fp-1").await.unwrap(),
+            Some(("C123".to_string(), "1700000000.000001".to_string()))
+        );
+        let lock_key = (target.id.clone(), "fp-1".to_string());
+        assert!(!THREAD_LOCKS.lock().unwrap().contains_key(&lock_key));
+    }
+
+    #[test]
+    fn config_requires_token_and_channel() {
+        assert!(SlackApiConfig::from_value(Some(&json!({ "token": "xoxb" }))).is_err());
+        assert!(SlackApiConfig::from_value(Some(
+            &json!({ "token": "xoxb", "channel": "C1", "thread_mode": "fork" })
+        ))
+        .is_err());
+        let config =
+            SlackApiConfig::from_value(Some(&json!({ "token": "xoxb", "channel": "C1" }))).unwrap();
+        assert_eq!(config.thread_mode, ThreadMode::Reply);
+    }
+}
@@ -0,0 +1,175 @@
+//! Tracing setup and W3C trace context propagation.
+//!
+//! Spans always go to the log output; with an OTLP endpoint they are also
+//! exported over OTLP/HTTP so one ingress can be followed to its deliveries.
+
+use axum::http::{HeaderMap, Request};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+const SERVICE_NAME: &str = "webhook-router";
+
+/// Log line format selected with `--log-format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, including the fields of the enclosing spans
+    /// (`request_id`, `endpoint_id`, `event_id`, `target_id`).
+    Json,
+}
+
+/// Installs the global subscriber and returns the tracer provider so it can
+/// be flushed on shutdown.
+pub fn init(
+    log_format: LogFormat,
+    otlp_endpoint: Option<&str>,
+) -> Result<SdkTracerProvider, Box<dyn std::error::Error>> {
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new("info,tower_http=debug"));
+    let provider = tracer_provider(otlp_endpoint)?;
+    let otel = tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME));
+
+    let text = (log_format == LogFormat::Text).then(tracing_subscriber::fmt::layer);
+    let json = (log_format == LogFormat::Json).then(|| {
+        tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(text)
+        .with(json)
+        .with(otel)
+        .init();
+    Ok(provider)
+}
+
+/// Builds a provider exporting batches to `<endpoint>/v1/traces`. Without an
+/// endpoint spans are only kept for context propagation, so an incoming
+/// `traceparent` still reaches `http` targets.
+pub fn tracer_provider(endpoint: Option<&str>) -> Result<SdkTracerProvider, Box<dyn std::error::Error>> {
+    let mut builder = SdkTracerProvider::builder()
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build());
+    if let Some(endpoint) = endpoint {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+            .build()?;
+        builder = builder.with_batch_exporter(exporter);
+    }
+    Ok(builder.build())
+}
+
+/// Span for an incoming HTTP request, tagged with its `X-Request-Id` and
+/// continuing the caller's trace when the request carries a `traceparent`
+/// header.
+pub fn make_request_span<B>(request: &Request<B>) -> Span {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok());
+    let span = tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id,
+    );
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    let _ = span.set_parent(parent);
+    span
+}
+
+/// Adds `traceparent` for the current span to outgoing request headers.
+pub fn inject_trace_context(headers: &mut HeaderMap) {
+    let context = Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::body::Bytes;
+    use axum::extract::State;
+    use axum::routing::post;
+    use axum::Router;
+    use opentelemetry::trace::Tracer as _;
+
+    use super::*;
+
+    type Exports = Arc<Mutex<Vec<(Option<String>, usize)>>>;
+
+    async fn mock_collector(State(exports): State<Exports>, headers: HeaderMap, body: Bytes) {
+        let content_type = headers
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        exports.lock().unwrap().push((content_type, body.len()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn exports_spans_to_collector() {
+        let exports: Exports = Arc::default();
+        let app = Router::new()
+            .route("/v1/traces", post(mock_collector))
+            .with_state(exports.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let endpoint = format!("http://{}/", addr);
+        tokio::task::spawn_blocking(move || {
+            let provider = tracer_provider(Some(&endpoint)).expect("provider");
+            provider.tracer("test").in_span("probe", |_| {});
+            provider.shutdown().expect("flush");
+        })
+        .await
+        .unwrap();
+
+        let exports = exports.lock().unwrap();
+        assert_eq!(exports.len(), 1);
+        assert_eq!(exports[0].0.as_deref(), Some("application/x-protobuf"));
+        assert!(exports[0].1 > 0);
+    }
+
+    #[test]
+    fn continues_incoming_trace() {
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = SdkTracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let request = Request::builder()
+            .uri("/ingress/e/slack")
+            .header("traceparent", format!("00-{}-00f067aa0ba902b7-01", trace_id))
+            .body(())
+            .unwrap();
+        let mut headers = HeaderMap::new();
+        tracing::subscriber::with_default(subscriber, || {
+            let span = make_request_span(&request);
+            span.in_scope(|| inject_trace_context(&mut headers));
+        });
+
+        let traceparent = headers["traceparent"].to_str().unwrap();
+        assert!(traceparent.starts_with(&format!("00-{}-", trace_id)));
+        assert!(!traceparent.contains("00f067aa0ba902b7"));
+    }
+}
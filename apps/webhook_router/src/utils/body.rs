@@ -0,0 +1,209 @@
+//! Decodes ingress request bodies by `Content-Encoding` and `Content-Type`.
+
+use std::io::Read;
+
+use axum::http::{header, HeaderMap, StatusCode};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use serde_json::{Map, Value};
+
+/// Decompressed bodies larger than this are rejected.
+pub const MAX_DECODED_BODY: usize = 10 * 1024 * 1024;
+
+/// An ingress body as decoded for the adapter, with the bytes it came from.
+#[derive(Debug, Clone)]
+pub struct IngressBody {
+    pub value: Value,
+    /// The body exactly as received, before any `Content-Encoding` is undone.
+    pub bytes: Vec<u8>,
+    /// Media type without parameters, e.g. `application/json`.
+    pub content_type: String,
+}
+
+#[derive(Debug)]
+pub struct BodyError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl BodyError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// Decodes `bytes` into a JSON value:
+///
+/// - `application/x-www-form-urlencoded` becomes an object of fields; a
+///   `payload` field holding JSON (Slack interactivity) is used as the value
+/// - `text/*` and `application/xml` become `{"text": ...}`
+/// - anything else, including no content type, is parsed as JSON
+pub fn decode(headers: &HeaderMap, bytes: &[u8]) -> Result<IngressBody, BodyError> {
+    let encoding = header_value(headers, header::CONTENT_ENCODING);
+    let decoded = decompress(&encoding, bytes)?;
+    let content_type = header_value(headers, header::CONTENT_TYPE)
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+
+    let value = match content_type.as_str() {
+        "application/x-www-form-urlencoded" => form_value(&decoded),
+        text if text.starts_with("text/") || text == "application/xml" => {
+            let text = String::from_utf8(decoded.into_owned())
+                .map_err(|_| BodyError::new(StatusCode::BAD_REQUEST, "text payload is not valid UTF-8"))?;
+            serde_json::json!({ "text": text })
+        }
+        _ => serde_json::from_slice(&decoded).map_err(|err| {
+            BodyError::new(StatusCode::BAD_REQUEST, format!("invalid json payload: {err}"))
+        })?,
+    };
+
+    Ok(IngressBody {
+        value,
+        bytes: bytes.to_vec(),
+        content_type,
+    })
+}
+
+fn header_value(headers: &HeaderMap, name: header::HeaderName) -> String {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+fn decompress<'a>(encoding: &str, bytes: &'a [u8]) -> Result<std::borrow::Cow<'a, [u8]>, BodyError> {
+    let reader: Box<dyn Read + 'a> = match encoding.trim() {
+        "" | "identity" => return Ok(bytes.into()),
+        "gzip" | "x-gzip" => Box::new(GzDecoder::new(bytes)),
+        // `deflate` is zlib-wrapped per RFC 9110, but some senders use raw deflate.
+        "deflate" if is_zlib(bytes) => Box::new(ZlibDecoder::new(bytes)),
+        "deflate" => Box::new(DeflateDecoder::new(bytes)),
+        other => {
+            return Err(BodyError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("unsupported content encoding: {}", other),
+            ))
+        }
+    };
+
+    let mut decoded = Vec::new();
+    reader
+        .take(MAX_DECODED_BODY as u64 + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|err| BodyError::new(StatusCode::BAD_REQUEST, format!("invalid {} body: {}", encoding, err)))?;
+    if decoded.len() > MAX_DECODED_BODY {
+        return Err(BodyError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("decoded body exceeds {} bytes", MAX_DECODED_BODY),
+        ));
+    }
+    Ok(decoded.into())
+}
+
+/// Whether `bytes` start with a zlib header (deflate method, valid checksum).
+fn is_zlib(bytes: &[u8]) -> bool {
+    match bytes {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+/// Form fields as a JSON object; repeated fields become arrays.
+fn form_value(bytes: &[u8]) -> Value {
+    let mut fields = Map::new();
+    for (name, value) in form_urlencoded::parse(bytes) {
+        let value = Value::String(value.into_owned());
+        match fields.get_mut(name.as_ref()) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+            None => {
+                fields.insert(name.into_owned(), value);
+            }
+        }
+    }
+
+    let payload = fields
+        .get("payload")
+        .and_then(Value::as_str)
+        .and_then(|payload| serde_json::from_str::<Value>(payload).ok())
+        .filter(Value::is_object);
+    payload.unwrap_or(Value::Object(fields))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use axum::http::HeaderValue;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use serde_json::json;
+
+    use super::*;
+
+    fn headers(content_type: &str, encoding: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(content_type).unwrap());
+        if let Some(encoding) = encoding {
+            headers.insert(header::CONTENT_ENCODING, HeaderValue::from_str(encoding).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn decodes_by_content_type() {
+        let form = b"command=%2Fdeploy&text=api+v2&channel_id=C1";
+        let body = decode(&headers("application/x-www-form-urlencoded", None), form).unwrap();
+        assert_eq!(body.value, json!({"command": "/deploy", "text": "api v2", "channel_id": "C1"}));
+        assert_eq!(body.bytes, form);
+
+        let interactive = b"payload=%7B%22type%22%3A%22block_actions%22%7D";
+        let body = decode(&headers("application/x-www-form-urlencoded", None), interactive).unwrap();
+        assert_eq!(body.value, json!({"type": "block_actions"}));
+
+        let body = decode(&headers("text/plain; charset=utf-8", None), b"disk full").unwrap();
+        assert_eq!(body.value, json!({"text": "disk full"}));
+        assert_eq!(body.content_type, "text/plain");
+        let body = decode(&headers("application/xml", None), b"<xml/>").unwrap();
+        assert_eq!(body.value, json!({"text": "<xml/>"}));
+
+        let body = decode(&HeaderMap::new(), br#"{"text":"hi"}"#).unwrap();
+        assert_eq!(body.value, json!({"text": "hi"}));
+        let err = decode(&headers("application/json", None), b"nope").unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn undoes_content_encoding() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(br#"{"status":"firing"}"#).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        let body = decode(&headers("application/json", Some("gzip")), &gzipped).unwrap();
+        assert_eq!(body.value, json!({"status": "firing"}));
+        assert_eq!(body.bytes, gzipped);
+
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"zlib").unwrap();
+        let body = decode(&headers("text/plain", Some("deflate")), &encoder.finish().unwrap()).unwrap();
+        assert_eq!(body.value, json!({"text": "zlib"}));
+
+        let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"raw").unwrap();
+        let body = decode(&headers("text/plain", Some("deflate")), &encoder.finish().unwrap()).unwrap();
+        assert_eq!(body.value, json!({"text": "raw"}));
+
+        let err = decode(&headers("application/json", Some("br")), b"").unwrap_err();
+        assert_eq!(err.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![b' '; MAX_DECODED_BODY + 1]).unwrap();
+        let err = decode(&headers("text/plain", Some("gzip")), &encoder.finish().unwrap()).unwrap_err();
+        assert_eq!(err.status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
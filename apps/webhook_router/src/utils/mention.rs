@@ -0,0 +1,216 @@
+use crate::models::Mention;
+
+/// Builds the platform-neutral mention token stored in UEM markdown.
+///
+/// Tokens look like `<@slack:U123>` and survive markdown formatting untouched,
+/// so egress adapters can swap them for their own mention syntax.
+pub fn mention_token(platform: &str, handle: &str) -> String {
+    format!("<@{}:{}>", platform, handle)
+}
+
+/// A piece of markdown split around mention tokens.
+#[derive(Debug, PartialEq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    Mention { platform: &'a str, handle: &'a str },
+}
+
+/// Splits `markdown` into plain text and mention token segments.
+pub fn split_mention_tokens(markdown: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut text_start = 0;
+    let mut cursor = 0;
+    while let Some(offset) = markdown[cursor..].find("<@") {
+        let start = cursor + offset;
+        match parse_token(&markdown[start..]) {
+            Some((platform, handle, len)) => {
+                if text_start < start {
+                    segments.push(Segment::Text(&markdown[text_start..start]));
+                }
+                segments.push(Segment::Mention { platform, handle });
+                cursor = start + len;
+                text_start = cursor;
+            }
+            None => cursor = start + 2,
+        }
+    }
+    if text_start < markdown.len() {
+        segments.push(Segment::Text(&markdown[text_start..]));
+    }
+    segments
+}
+
+/// Replaces every mention token in `markdown` with the result of `render`.
+///
+/// `render` receives the platform and handle encoded in the token.
+pub fn replace_mention_tokens(
+    markdown: &str,
+    mut render: impl FnMut(&str, &str) -> String,
+) -> String {
+    let mut output = String::with_capacity(markdown.len());
+    for segment in split_mention_tokens(markdown) {
+        match segment {
+            Segment::Text(text) => output.push_str(text),
+            Segment::Mention { platform, handle } => output.push_str(&render(platform, handle)),
+        }
+    }
+    output
+}
+
+/// Collects the mentions referenced by tokens in `markdown`, keeping any
+/// details (such as display names) already known from `known`.
+pub fn collect_mentions(markdown: &str, known: &[Mention]) -> Vec<Mention> {
+    let mut mentions: Vec<Mention> = Vec::new();
+    replace_mention_tokens(markdown, |platform, handle| {
+        if !mentions
+            .iter()
+            .any(|mention| mention.platform == platform && mention.handle == handle)
+        {
+            let mention = known
+                .iter()
+                .find(|mention| mention.platform == platform && mention.handle == handle)
+                .cloned()
+                .unwrap_or_else(|| Mention::new(platform, handle, None));
+            mentions.push(mention);
+        }
+        String::new()
+    });
+    mentions
+}
+
+/// Looks up how the token `<@source:handle>` should appear on `platform`.
+///
+/// Returns the person's handle on `platform` when the directory knows one,
+/// and a display fallback (name or original handle).
+pub fn lookup_mention<'a>(
+    mentions: &'a [Mention],
+    source: &'a str,
+    handle: &'a str,
+    platform: &str,
+) -> (Option<&'a str>, &'a str) {
+    match mentions
+        .iter()
+        .find(|mention| mention.platform == source && mention.handle == handle)
+    {
+        Some(mention) => (mention.handle_for(platform), mention.display_name()),
+        None if source == platform => (Some(handle), handle),
+        None => (None, handle),
+    }
+}
+
+/// Renders mention tokens for the `platform` egress using `render`, which
+/// receives the result of [`lookup_mention`].
+pub fn render_mentions(
+    markdown: &str,
+    mentions: &[Mention],
+    platform: &str,
+    mut render: impl FnMut(Option<&str>, &str) -> String,
+) -> String {
+    replace_mention_tokens(markdown, |source, handle| {
+        let (target_handle, fallback) = lookup_mention(mentions, source, handle, platform);
+        render(target_handle, fallback)
+    })
+}
+
+/// Replaces plain `@handle` references in `text` with `token`.
+///
+/// A reference only counts when the handle ends there, so `@138` does not
+/// match the start of `@13800000000`. Returns `None` when nothing matched.
+pub fn replace_handle_references(text: &str, handle: &str, token: &str) -> Option<String> {
+    let reference = format!("@{}", handle);
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut replaced = false;
+    while let Some(start) = rest.find(&reference) {
+        let end = start + reference.len();
+        output.push_str(&rest[..start]);
+        if rest[end..].chars().next().is_some_and(is_handle_char) {
+            output.push_str(&reference);
+        } else {
+            output.push_str(token);
+            replaced = true;
+        }
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+    replaced.then_some(output)
+}
+
+fn is_handle_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_' || ch == '-'
+}
+
+fn parse_token(input: &str) -> Option<(&str, &str, usize)> {
+    let end = input.find('>')?;
+    let body = &input[2..end];
+    let (platform, handle) = body.split_once(':')?;
+    let valid_platform = !platform.is_empty()
+        && platform
+            .chars()
+            .all(|ch| ch.is_ascii_lowercase() || ch == '_');
+    let valid_handle = !handle.is_empty() && !handle.chars().any(|ch| ch.is_whitespace() || ch == '<');
+    if valid_platform && valid_handle {
+        Some((platform, handle, end + 1))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_tokens_and_keeps_other_text() {
+        let markdown = "hi <@slack:U1> and <@lark:ou_2>, not <@bad token> or a <b>";
+        let rendered = replace_mention_tokens(markdown, |platform, handle| {
+            format!("[{}/{}]", platform, handle)
+        });
+        assert_eq!(
+            rendered,
+            "hi [slack/U1] and [lark/ou_2], not <@bad token> or a <b>"
+        );
+    }
+
+    #[test]
+    fn splits_text_and_tokens() {
+        assert_eq!(
+            split_mention_tokens("<@lark:ou_1> hi"),
+            vec![
+                Segment::Mention { platform: "lark", handle: "ou_1" },
+                Segment::Text(" hi"),
+            ]
+        );
+    }
+
+    #[test]
+    fn replaces_whole_handle_references_only() {
+        assert_eq!(
+            replace_handle_references("@138 and @13800000000, @138.", "138", "<@dingtalk:138>").as_deref(),
+            Some("<@dingtalk:138> and @13800000000, <@dingtalk:138>.")
+        );
+        assert_eq!(replace_handle_references("@13800000000", "138", "<@dingtalk:138>"), None);
+    }
+
+    #[test]
+    fn renders_with_directory_handles() {
+        let mut mention = Mention::new("slack", "U1", Some("Alice".to_string()));
+        mention.handles.insert("slack".to_string(), "U1".to_string());
+        mention
+            .handles
+            .insert("dingtalk".to_string(), "13800000000".to_string());
+        let mentions = collect_mentions("ping <@slack:U1> <@slack:U2>", &[mention]);
+        assert_eq!(mentions.len(), 2);
+
+        let rendered = render_mentions(
+            "ping <@slack:U1> <@slack:U2>",
+            &mentions,
+            "dingtalk",
+            |handle, fallback| match handle {
+                Some(handle) => format!("@{}", handle),
+                None => format!("@{}", fallback),
+            },
+        );
+        assert_eq!(rendered, "ping @13800000000 @U2");
+    }
+}
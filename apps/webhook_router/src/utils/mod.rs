@@ -1,2 +1,5 @@
+pub mod body;
 pub mod markdown;
 pub mod format;
+pub mod mention;
+pub mod split;
@@ -0,0 +1,234 @@
+/// Splits `markdown` into parts whose `measure` stays within `budget`.
+///
+/// Parts are cut on block boundaries (blank lines outside code fences).
+/// Oversized blocks fall back to line boundaries; an oversized fenced block
+/// is closed at the end of each part and reopened at the start of the next,
+/// so every part stays valid markdown. Lines longer than the budget are cut
+/// at character boundaries as a last resort.
+///
+/// `measure` must add up over concatenation, as character and byte counts
+/// do, so sizes are tracked as running totals rather than re-measured.
+pub fn split_markdown(markdown: &str, budget: usize, measure: &dyn Fn(&str) -> usize) -> Vec<String> {
+    let budget = budget.max(1);
+    if measure(markdown) <= budget {
+        return vec![markdown.to_string()];
+    }
+
+    let separator = measure("\n\n");
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut size = 0;
+    for block in markdown_blocks(markdown) {
+        let block_size = measure(&block);
+        if !current.is_empty() && size + separator + block_size <= budget {
+            current.push_str("\n\n");
+            current.push_str(&block);
+            size += separator + block_size;
+            continue;
+        }
+        if !current.is_empty() {
+            parts.push(std::mem::take(&mut current));
+        }
+        if block_size <= budget {
+            current = block;
+            size = block_size;
+        } else {
+            let mut pieces = split_block(&block, budget, measure);
+            current = pieces.pop().unwrap_or_default();
+            size = measure(&current);
+            parts.extend(pieces);
+        }
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+/// Groups lines into blocks separated by blank lines, keeping fenced code
+/// blocks whole.
+fn markdown_blocks(markdown: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut lines: Vec<&str> = Vec::new();
+    let mut fence: Option<String> = None;
+    for line in markdown.lines() {
+        match &fence {
+            Some(marker) => {
+                if is_closing_fence(line, marker) {
+                    fence = None;
+                }
+            }
+            None => {
+                if line.trim().is_empty() {
+                    if !lines.is_empty() {
+                        blocks.push(lines.join("\n"));
+                        lines.clear();
+                    }
+                    continue;
+                }
+                fence = opening_fence(line);
+            }
+        }
+        lines.push(line);
+    }
+    if !lines.is_empty() {
+        blocks.push(lines.join("\n"));
+    }
+    blocks
+}
+
+fn split_block(block: &str, budget: usize, measure: &dyn Fn(&str) -> usize) -> Vec<String> {
+    let lines: Vec<&str> = block.lines().collect();
+    if let Some(marker) = lines.first().and_then(|line| opening_fence(line)) {
+        let open = lines[0];
+        let closed = lines.len() > 1 && is_closing_fence(lines[lines.len() - 1], &marker);
+        let body = &lines[1..if closed { lines.len() - 1 } else { lines.len() }];
+        let close = if closed { lines[lines.len() - 1] } else { marker.as_str() };
+        let overhead = measure(open) + measure(close) + 2;
+        if overhead < budget {
+            return pack_lines(body, budget - overhead, measure)
+                .into_iter()
+                .map(|chunk| format!("{}\n{}\n{}", open, chunk, close))
+                .collect();
+        }
+    }
+    pack_lines(&lines, budget, measure)
+}
+
+fn pack_lines(lines: &[&str], budget: usize, measure: &dyn Fn(&str) -> usize) -> Vec<String> {
+    let separator = measure("\n");
+    let mut chunks = Vec::new();
+    // `None` until a line starts the chunk, so a leading blank line counts.
+    let mut current: Option<String> = None;
+    let mut size = 0;
+    for line in lines {
+        let line_size = measure(line);
+        if let Some(chunk) = current.as_mut().filter(|_| size + separator + line_size <= budget) {
+            chunk.push('\n');
+            chunk.push_str(line);
+            size += separator + line_size;
+            continue;
+        }
+        chunks.extend(current.take());
+        if line_size <= budget {
+            current = Some(line.to_string());
+            size = line_size;
+        } else {
+            let mut pieces = split_line(line, budget, measure);
+            current = pieces.pop();
+            size = current.as_deref().map_or(0, measure);
+            chunks.extend(pieces);
+        }
+    }
+    chunks.extend(current);
+    chunks
+}
+
+/// Cuts `line` at character boundaries, measuring each character once.
+fn split_line(line: &str, budget: usize, measure: &dyn Fn(&str) -> usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut size = 0;
+    let mut buffer = [0; 4];
+    for (index, ch) in line.char_indices() {
+        let ch_size = measure(ch.encode_utf8(&mut buffer));
+        if size + ch_size > budget && index > start {
+            pieces.push(line[start..index].to_string());
+            start = index;
+            size = 0;
+        }
+        size += ch_size;
+    }
+    if start < line.len() {
+        pieces.push(line[start..].to_string());
+    }
+    pieces
+}
+
+/// Returns the fence marker (e.g. "```" or "~~~~") when `line` opens a code block.
+fn opening_fence(line: &str) -> Option<String> {
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let ch = trimmed.chars().next().filter(|ch| *ch == '`' || *ch == '~')?;
+    let count = trimmed.chars().take_while(|c| *c == ch).count();
+    (count >= 3).then(|| ch.to_string().repeat(count))
+}
+
+fn is_closing_fence(line: &str, marker: &str) -> bool {
+    let trimmed = line.trim();
+    let ch = marker.chars().next().unwrap_or('`');
+    trimmed.len() >= marker.len() && trimmed.chars().all(|c| c == ch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> usize {
+        text.chars().count()
+    }
+
+    #[test]
+    fn keeps_short_markdown_whole() {
+        assert_eq!(split_markdown("hello\n\nworld", 100, &chars), vec!["hello\n\nworld"]);
+    }
+
+    #[test]
+    fn splits_on_block_boundaries() {
+        let markdown = "# Title\n\nfirst paragraph\n\nsecond paragraph\n\nthird";
+        assert_eq!(
+            split_markdown(markdown, 30, &chars),
+            vec!["# Title\n\nfirst paragraph", "second paragraph\n\nthird"]
+        );
+    }
+
+    #[test]
+    fn never_cuts_inside_a_code_fence() {
+        let markdown = "intro\n\n```log\nline one\n\nline two\nline three\n```\n\noutro";
+        let parts = split_markdown(markdown, 30, &chars);
+        assert_eq!(
+            parts,
+            vec![
+                "intro",
+                "```log\nline one\n\nline two\n```",
+                "```log\nline three\n```\n\noutro",
+            ]
+        );
+        assert!(parts.iter().all(|part| chars(part) <= 30));
+    }
+
+    #[test]
+    fn keeps_blank_lines_that_start_a_reopened_fence() {
+        let markdown = "```\naaaa\nbbbb\n\ncc\n```";
+        assert_eq!(
+            split_markdown(markdown, 17, &chars),
+            vec!["```\naaaa\nbbbb\n```", "```\n\ncc\n```"]
+        );
+    }
+
+    #[test]
+    fn measures_bytes() {
+        let markdown = "告警".repeat(10);
+        let parts = split_markdown(&markdown, 16, &str::len);
+        assert_eq!(parts.len(), 4);
+        assert!(parts.iter().all(|part| part.len() <= 16));
+        assert_eq!(parts.concat(), markdown);
+    }
+
+    #[test]
+    fn splits_long_lines_in_linear_time() {
+        let markdown = "x".repeat(1_000_000);
+        let measured = std::cell::Cell::new(0);
+        let counting = |text: &str| {
+            measured.set(measured.get() + text.len());
+            chars(text)
+        };
+        let parts = split_markdown(&markdown, 40_000, &counting);
+        // Each character is measured a bounded number of times.
+        assert!(measured.get() <= 5 * markdown.len(), "measured {} bytes", measured.get());
+        assert_eq!(parts.len(), 25);
+        assert_eq!(parts.concat(), markdown);
+    }
+}